@@ -105,6 +105,155 @@ fn bench_leapfrog_leapmap(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_dashmap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dashmap");
+    group.throughput(Throughput::Elements(
+        NUM_OPS * 6 * 2_u64 * THREAD_COUNT as u64,
+    ));
+    group.sample_size(10);
+    group.bench_function("parallel_insert_update", |b| {
+        let map = Arc::new(dashmap::DashMap::with_capacity_and_hasher(
+            CAPACITY,
+            BuildHasherDefault::<HashFn>::default(),
+        ));
+
+        b.iter_custom(|iters| {
+            let mut handles = vec![];
+            for _ in 0..THREAD_COUNT {
+                let map = map.clone();
+                handles.push(thread::spawn(move || {
+                    for _ in 0..iters {
+                        let mut rng = thread_rng();
+                        let mut bits: u64 = rng.gen();
+                        let mut mask = 0u64;
+
+                        for _ in 0..6 {
+                            mask <<= 4;
+                            mask |= bits & 0b00001111;
+                            bits >>= 4;
+
+                            for i in 0..NUM_OPS {
+                                let key = rng.gen::<u64>() & mask;
+                                map.insert(key, i as u16);
+                                let key = rng.gen::<u64>() & mask;
+                                if let Some(mut value) = map.get_mut(&key) {
+                                    *value = i as u16;
+                                }
+                            }
+                        }
+                    }
+                }));
+            }
+            let start = Instant::now();
+            for h in handles {
+                h.join().unwrap();
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
+fn bench_flurry_hashmap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flurry_hashmap");
+    group.throughput(Throughput::Elements(
+        NUM_OPS * 6 * 2_u64 * THREAD_COUNT as u64,
+    ));
+    group.sample_size(10);
+    group.bench_function("parallel_insert_update", |b| {
+        let map = Arc::new(flurry::HashMap::with_capacity_and_hasher(
+            CAPACITY,
+            BuildHasherDefault::<HashFn>::default(),
+        ));
+
+        b.iter_custom(|iters| {
+            let mut handles = vec![];
+            for _ in 0..THREAD_COUNT {
+                let map = map.clone();
+                handles.push(thread::spawn(move || {
+                    for _ in 0..iters {
+                        let mut rng = thread_rng();
+                        let mut bits: u64 = rng.gen();
+                        let mut mask = 0u64;
+
+                        for _ in 0..6 {
+                            mask <<= 4;
+                            mask |= bits & 0b00001111;
+                            bits >>= 4;
+
+                            let map = map.pin();
+                            for i in 0..NUM_OPS {
+                                let key = rng.gen::<u64>() & mask;
+                                map.insert(key, i as u16);
+                                let key = rng.gen::<u64>() & mask;
+                                map.compute_if_present(&key, |_, _| Some(i as u16));
+                            }
+                        }
+                    }
+                }));
+            }
+            let start = Instant::now();
+            for h in handles {
+                h.join().unwrap();
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
+/// evmap only allows one writer at a time, so the write handle is shared behind a `Mutex`, and
+/// writes are published to readers with a refresh at the end of each round.
+fn bench_evmap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evmap_mutex");
+    group.throughput(Throughput::Elements(
+        NUM_OPS * 6 * 2_u64 * THREAD_COUNT as u64,
+    ));
+    group.sample_size(10);
+    group.bench_function("parallel_insert_update", |b| {
+        let (_read, write) = evmap::Options::default()
+            .with_capacity(CAPACITY)
+            .with_hasher(BuildHasherDefault::<HashFn>::default())
+            .construct::<u64, u16>();
+        let write = Arc::new(std::sync::Mutex::new(write));
+
+        b.iter_custom(|iters| {
+            let mut handles = vec![];
+            for _ in 0..THREAD_COUNT {
+                let write = write.clone();
+                handles.push(thread::spawn(move || {
+                    for _ in 0..iters {
+                        let mut rng = thread_rng();
+                        let mut bits: u64 = rng.gen();
+                        let mut mask = 0u64;
+
+                        for _ in 0..6 {
+                            mask <<= 4;
+                            mask |= bits & 0b00001111;
+                            bits >>= 4;
+
+                            let mut write = write.lock().unwrap();
+                            for i in 0..NUM_OPS {
+                                let key = rng.gen::<u64>() & mask;
+                                write.update(key, i as u16);
+                                let key = rng.gen::<u64>() & mask;
+                                write.update(key, i as u16);
+                            }
+                            write.refresh();
+                        }
+                    }
+                }));
+            }
+            let start = Instant::now();
+            for h in handles {
+                h.join().unwrap();
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
 fn bench_std_hashmap(c: &mut Criterion) {
     let mut group = c.benchmark_group("std_hashmap_rw_lock");
     group.throughput(Throughput::Elements(
@@ -159,6 +308,9 @@ criterion_group!(
     benches,
     bench_folklore_hashmap,
     bench_leapfrog_leapmap,
+    bench_dashmap,
+    bench_flurry_hashmap,
+    bench_evmap,
     bench_std_hashmap,
 );
 criterion_main!(benches);
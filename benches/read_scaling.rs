@@ -0,0 +1,89 @@
+#![allow(clippy::pedantic)]
+
+//! Get-only throughput on a pre-populated map, swept from 1 to 32 threads.
+//!
+//! Throughput is counted per thread, so under perfect scaling every thread count reports the
+//! same rate, and the group's summary plot is flat. The rate at `n` threads divided by the rate at
+//! one thread is the scaling efficiency at `n`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hash::BuildHasherDefault;
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CAPACITY: usize = i16::MAX as usize;
+/// The number of keys inserted before the reads run.
+const PREPOPULATED: u64 = CAPACITY as u64 / 2;
+/// The number of gets each thread makes per iteration.
+const NUM_OPS: usize = i16::MAX as usize;
+const THREAD_COUNTS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+type HashFn = std::collections::hash_map::DefaultHasher;
+
+/// Keys for each thread, drawn from the prepopulated keys with a different seed per thread.
+fn thread_keys(thread: usize) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(thread as u64);
+    (0..NUM_OPS)
+        .map(|_| rng.gen_range(0..PREPOPULATED))
+        .collect()
+}
+
+fn bench_folklore_read_scaling(c: &mut Criterion) {
+    let map = folklore::HashMap::<u64, u16, _>::with_capacity_and_hasher(
+        CAPACITY,
+        BuildHasherDefault::<HashFn>::default(),
+    );
+    for key in 0..PREPOPULATED {
+        assert!(map.insert(key, key as u16));
+    }
+    let keys: Vec<Vec<u64>> = (0..*THREAD_COUNTS.last().unwrap())
+        .map(thread_keys)
+        .collect();
+
+    let mut group = c.benchmark_group("folklore_read_scaling");
+    group.throughput(Throughput::Elements(NUM_OPS as u64));
+    group.sample_size(10);
+    for threads in THREAD_COUNTS {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    // Every thread starts reading at once, and the slowest one sets the time.
+                    let barrier = Barrier::new(threads);
+                    thread::scope(|s| {
+                        let handles: Vec<_> = keys[..threads]
+                            .iter()
+                            .map(|keys| {
+                                let map = &map;
+                                let barrier = &barrier;
+                                s.spawn(move || {
+                                    barrier.wait();
+                                    let start = Instant::now();
+                                    for _ in 0..iters {
+                                        for key in keys {
+                                            black_box(map.get(key));
+                                        }
+                                    }
+                                    start.elapsed()
+                                })
+                            })
+                            .collect();
+                        handles
+                            .into_iter()
+                            .map(|h| h.join().unwrap())
+                            .max()
+                            .unwrap_or(Duration::ZERO)
+                    })
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_folklore_read_scaling);
+criterion_main!(benches);
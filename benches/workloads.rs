@@ -0,0 +1,168 @@
+#![allow(clippy::pedantic)]
+
+//! Single-threaded workloads which are closer to real access patterns than the insert/update
+//! benchmarks: mostly reads, on a table which is already populated, with uniform or Zipfian keys.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hash::BuildHasherDefault;
+
+const CAPACITY: usize = i16::MAX as usize;
+/// The number of keys inserted before each workload runs.
+const PREPOPULATED: u64 = CAPACITY as u64 / 2;
+/// The keys are drawn from twice as many keys as are prepopulated, so some reads miss and some
+/// writes insert.
+const KEY_SPACE: usize = PREPOPULATED as usize * 2;
+const NUM_OPS: usize = i16::MAX as usize;
+/// The fraction of operations which are writes, in a read-heavy workload.
+const WRITE_RATIO: f64 = 0.05;
+/// The exponent of the Zipfian distribution. At 1.0, the most common key is drawn about 9% of
+/// the time.
+const ZIPF_EXPONENT: f64 = 1.0;
+
+type HashFn = std::collections::hash_map::DefaultHasher;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Get(u64),
+    Write(u64, u16),
+}
+
+/// Samples ranks in `0..n` from a Zipfian distribution, by inverting its CDF.
+struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, exponent: f64) -> Self {
+        let weights: Vec<f64> = (1..=n)
+            .map(|rank| 1.0 / (rank as f64).powf(exponent))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let mut cumulative = 0.0;
+        let cdf = weights
+            .iter()
+            .map(|weight| {
+                cumulative += weight / total;
+                cumulative
+            })
+            .collect();
+        Self { cdf }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        let p: f64 = rng.gen();
+        self.cdf.partition_point(|&c| c < p).min(self.cdf.len() - 1)
+    }
+}
+
+/// Generate the operations for a workload up front, so the benchmark only times the map.
+/// Zipfian ranks are scattered over the key space, so the hottest keys aren't all prepopulated.
+fn operations(write_ratio: f64, zipf: Option<&Zipf>) -> Vec<Op> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let scatter: Vec<u64> = {
+        let mut keys: Vec<u64> = (0..KEY_SPACE as u64).collect();
+        for i in (1..keys.len()).rev() {
+            keys.swap(i, rng.gen_range(0..=i));
+        }
+        keys
+    };
+    (0..NUM_OPS)
+        .map(|i| {
+            let key = match zipf {
+                Some(zipf) => scatter[zipf.sample(&mut rng)],
+                None => rng.gen_range(0..KEY_SPACE as u64),
+            };
+            if rng.gen_bool(write_ratio) {
+                Op::Write(key, i as u16)
+            } else {
+                Op::Get(key)
+            }
+        })
+        .collect()
+}
+
+fn bench_workloads(c: &mut Criterion) {
+    let zipf = Zipf::new(KEY_SPACE, ZIPF_EXPONENT);
+    let workloads = [
+        ("get_prepopulated", operations(0.0, None)),
+        ("read_heavy_uniform", operations(WRITE_RATIO, None)),
+        ("read_heavy_zipf", operations(WRITE_RATIO, Some(&zipf))),
+    ];
+    for (name, ops) in &workloads {
+        let mut group = c.benchmark_group(*name);
+        group.throughput(Throughput::Elements(ops.len() as u64));
+        group.sample_size(10);
+
+        group.bench_function("folklore_hashmap", |b| {
+            let map = folklore::HashMap::with_capacity(CAPACITY);
+            for key in 0..PREPOPULATED {
+                map.insert(key, 0);
+            }
+            b.iter(|| {
+                for &op in ops {
+                    match op {
+                        Op::Get(key) => {
+                            black_box(map.get(&key));
+                        }
+                        Op::Write(key, value) => {
+                            if !map.insert(key, value) {
+                                map.update(&key, value);
+                            }
+                        }
+                    }
+                }
+            })
+        });
+
+        group.bench_function("leapfrog_leapmap", |b| {
+            let map = leapfrog::LeapMap::with_capacity_and_hasher(
+                CAPACITY,
+                BuildHasherDefault::<HashFn>::default(),
+            );
+            for key in 0..PREPOPULATED {
+                map.insert(key, 0_u16);
+            }
+            b.iter(|| {
+                for &op in ops {
+                    match op {
+                        Op::Get(key) => {
+                            black_box(map.get(&key).and_then(|mut entry| entry.value()));
+                        }
+                        Op::Write(key, value) => {
+                            map.insert(key, value);
+                        }
+                    }
+                }
+            })
+        });
+
+        group.bench_function("std_hashmap", |b| {
+            let mut map = std::collections::HashMap::with_capacity_and_hasher(
+                CAPACITY,
+                BuildHasherDefault::<HashFn>::default(),
+            );
+            for key in 0..PREPOPULATED {
+                map.insert(key, 0_u16);
+            }
+            b.iter(|| {
+                for &op in ops {
+                    match op {
+                        Op::Get(key) => {
+                            black_box(map.get(&key));
+                        }
+                        Op::Write(key, value) => {
+                            map.insert(key, value);
+                        }
+                    }
+                }
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_workloads);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+#![no_main]
+
+//! Runs fuzzer-chosen operation sequences on real threads against one `folklore::HashMap`, then
+//! checks every observation against a `Mutex<std::HashMap>` oracle built from the operation log.
+//! Each key behaves as a register: it is inserted at most once, every successful update replaces
+//! exactly one earlier write, and the final value is the last write in that chain.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+const MAX_THREADS: usize = 4;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(u8),
+    Get(u8),
+    Update(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capacity: u8,
+    threads: Vec<Vec<Op>>,
+}
+
+#[derive(Debug)]
+enum Observed {
+    Insert(u8, u16, bool),
+    Get(u8, Option<u16>),
+    Update(u8, u16, Option<u16>),
+}
+
+fuzz_target!(|input: Input| {
+    let map = folklore::HashMap::<u8, u16>::with_capacity(usize::from(input.capacity));
+    let log = Mutex::new(Vec::new());
+
+    std::thread::scope(|s| {
+        for (thread, ops) in input.threads.iter().take(MAX_THREADS).enumerate() {
+            let (map, log) = (&map, &log);
+            s.spawn(move || {
+                let mut observed = Vec::with_capacity(ops.len());
+                for (seq, op) in ops.iter().enumerate() {
+                    // Every written value is unique, so it identifies the write that produced it.
+                    let value = ((thread as u16) << 12) | (seq as u16 & 0x0FFF);
+                    observed.push(match *op {
+                        Op::Insert(key) => Observed::Insert(key, value, map.insert(key, value)),
+                        Op::Get(key) => Observed::Get(key, map.get(&key)),
+                        Op::Update(key) => Observed::Update(key, value, map.update(&key, value)),
+                    });
+                }
+                log.lock().unwrap().extend(observed);
+            });
+        }
+    });
+
+    let log = log.into_inner().unwrap();
+    let mut oracle = HashMap::new();
+    let mut replaced_by = HashMap::new();
+    let mut written = HashSet::new();
+    for observed in &log {
+        match *observed {
+            Observed::Insert(key, value, true) => {
+                assert!(oracle.insert(key, value).is_none(), "{observed:?}");
+                written.insert((key, value));
+            }
+            Observed::Update(key, value, Some(previous)) => {
+                assert!(
+                    replaced_by.insert((key, previous), value).is_none(),
+                    "{observed:?}"
+                );
+                written.insert((key, value));
+            }
+            _ => {}
+        }
+    }
+    for observed in &log {
+        if let Observed::Get(key, Some(value)) = *observed {
+            assert!(written.contains(&(key, value)), "{observed:?}");
+        }
+    }
+
+    assert_eq!(map.len(), oracle.len());
+    for (&key, &first) in &oracle {
+        let mut last = first;
+        while let Some(&next) = replaced_by.get(&(key, last)) {
+            last = next;
+        }
+        assert_eq!(map.get(&key), Some(last));
+    }
+});
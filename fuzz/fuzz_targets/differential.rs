@@ -0,0 +1,96 @@
+#![no_main]
+
+//! Replays operation sequences from several simulated threads, interleaved according to a
+//! fuzzer-chosen schedule, against both `folklore::HashMap` and a `Mutex<std::HashMap>` oracle.
+//! Every operation must observe the same result from both maps.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(u8, u16),
+    Get(u8),
+    Update(u8, u16),
+    ContainsKey(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capacity: u8,
+    threads: Vec<Vec<Op>>,
+    schedule: Vec<u8>,
+}
+
+struct Oracle {
+    map: Mutex<HashMap<u8, u16>>,
+    capacity: usize,
+}
+
+impl Oracle {
+    fn insert(&self, key: u8, value: u16) -> bool {
+        let mut map = self.map.lock().unwrap();
+        if map.len() >= self.capacity || map.contains_key(&key) {
+            return false;
+        }
+        map.insert(key, value);
+        true
+    }
+
+    fn get(&self, key: u8) -> Option<u16> {
+        self.map.lock().unwrap().get(&key).copied()
+    }
+
+    fn update(&self, key: u8, value: u16) -> Option<u16> {
+        self.map
+            .lock()
+            .unwrap()
+            .get_mut(&key)
+            .map(|current| core::mem::replace(current, value))
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let capacity = usize::from(input.capacity);
+    let map = folklore::HashMap::<u8, u16>::with_capacity(capacity);
+    let oracle = Oracle {
+        map: Mutex::new(HashMap::new()),
+        capacity,
+    };
+
+    let mut threads: Vec<_> = input.threads.iter().map(|ops| ops.iter()).collect();
+    if threads.is_empty() {
+        return;
+    }
+    let mut schedule = input.schedule.iter().cycle();
+    while !threads.is_empty() {
+        // Step whichever simulated thread the schedule picks next, retiring finished threads.
+        let pick = schedule.next().map_or(0, |&b| usize::from(b)) % threads.len();
+        let Some(op) = threads[pick].next() else {
+            drop(threads.swap_remove(pick));
+            continue;
+        };
+        match *op {
+            Op::Insert(key, value) => {
+                assert_eq!(map.insert(key, value), oracle.insert(key, value), "{op:?}");
+            }
+            Op::Get(key) => assert_eq!(map.get(&key), oracle.get(key), "{op:?}"),
+            Op::Update(key, value) => {
+                assert_eq!(map.update(&key, value), oracle.update(key, value), "{op:?}");
+            }
+            Op::ContainsKey(key) => {
+                assert_eq!(map.contains_key(&key), oracle.get(key).is_some(), "{op:?}");
+            }
+        }
+    }
+
+    assert_eq!(map.len(), oracle.map.lock().unwrap().len());
+    let mut seen = 0;
+    for (key, value) in &map {
+        assert_eq!(Some(value), oracle.get(*key));
+        seen += 1;
+    }
+    assert_eq!(seen, map.len());
+});
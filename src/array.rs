@@ -1,54 +1,212 @@
-use crate::generic_asserts;
+use crate::{generic_asserts, util};
 use alloc::alloc::{alloc, dealloc};
+use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::fmt::Debug;
 use core::mem::{align_of, size_of};
 use core::ptr::slice_from_raw_parts_mut;
-use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicU8, AtomicUsize};
 
 /// A Vector-like data structure that allows for concurrent access and insertion.
 /// It has a fixed capacity and cannot be resized.
 /// Once elements have been appended, they cannot be removed, unless it was the most recently inserted element.
+///
+/// Elements are only visible to [`get`](Self::get) once they have been published. Until then
+/// they belong to the thread which pushed them, which may still remove them.
 pub struct ConcurrentArray<T> {
     inner: ConcurrentArena<T>,
+    states: *mut AtomicU8,
     capacity: usize,
 }
 
+mod state {
+    /// The slot holds no element.
+    pub const VACANT: u8 = 0;
+    /// The slot holds an element which hasn't been published.
+    pub const WRITTEN: u8 = 1;
+    /// The slot holds an element which is visible to all threads, and can never be removed.
+    pub const PUBLISHED: u8 = 2;
+}
+
 impl<T> ConcurrentArray<T> {
-    pub fn new(capacity: usize) -> Self {
+    /// Returns None if the allocation fails.
+    pub fn try_new(capacity: usize) -> Option<Self> {
         generic_asserts!((T);
             NON_ZST: size_of::<T>() != 0;
             POWER_2_ALIGN: align_of::<T>().is_power_of_two();
         );
-        Self {
-            inner: ConcurrentArena::new(capacity),
-            capacity,
+        let inner = ConcurrentArena::try_new(capacity)?;
+        let states = util::allocate_zeroed::<AtomicU8>(capacity);
+        if states.is_null() {
+            return None;
         }
+        Some(Self {
+            inner,
+            states,
+            capacity,
+        })
     }
 
     pub fn push(&self, item: T) -> Option<(&T, usize)> {
         let alloc_res = self.inner.push()?;
         let ptr = alloc_res.bytes.cast::<T>();
+        let index = alloc_res.index / size_of::<T>();
         unsafe {
             ptr.write(item);
         }
-        Some((unsafe { &*ptr }, alloc_res.index / size_of::<T>()))
+        self.state(index).store(state::WRITTEN, Ordering::Relaxed);
+        Some((unsafe { &*ptr }, index))
+    }
+
+    /// Push and publish an item, without atomic read-modify-writes, because nothing else can
+    /// access the array. Returns the item's index, or None if the array is full.
+    pub fn push_mut(&mut self, item: T) -> Option<usize> {
+        let next = *self.inner.next.get_mut();
+        if next >= self.inner.byte_capacity {
+            return None;
+        }
+        let index = next / size_of::<T>();
+        unsafe { self.inner.slot(next).cast::<T>().write(item) };
+        *self.inner.next.get_mut() = next + size_of::<T>();
+        self.state(index).store(state::PUBLISHED, Ordering::Relaxed);
+        Some(index)
+    }
+
+    /// Make the item at `index` visible to [`get`](Self::get). Once published, an item can't be
+    /// removed.
+    pub fn publish(&self, index: usize) {
+        self.state(index).store(state::PUBLISHED, Ordering::Release);
     }
 
     /// Remove an item from the arena at the given index. Will only remove the item if it was
-    /// the most recently added item.
+    /// the most recently added item, and it hasn't been published.
     pub fn remove(&self, index: usize) -> bool {
-        if index >= self.capacity {
+        if index >= self.capacity || self.state(index).load(Ordering::Relaxed) != state::WRITTEN {
             return false;
         }
-        self.inner.try_remove(index * size_of::<T>())
+        // Move the item out before releasing the slot, because another thread may push into
+        // the slot as soon as it is released.
+        let ptr = unsafe { self.inner.slot(index * size_of::<T>()).cast::<T>() };
+        let item = unsafe { ptr.read() };
+        self.state(index).store(state::VACANT, Ordering::Relaxed);
+        if self.inner.try_remove(index * size_of::<T>()) {
+            drop(item);
+            true
+        } else {
+            unsafe { ptr.write(item) };
+            self.state(index).store(state::WRITTEN, Ordering::Relaxed);
+            false
+        }
     }
 
+    /// Get the published item at `index`.
     pub fn get(&self, index: usize) -> Option<&T> {
-        self.inner
-            .get(index * size_of::<T>())
-            .map(|ptr| unsafe { &*ptr.cast::<T>() })
+        if index >= self.capacity || self.state(index).load(Ordering::Acquire) != state::PUBLISHED {
+            return None;
+        }
+        Some(unsafe { self.get_unchecked(index) })
+    }
+
+    /// Get the published item at `index`, mutably.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.capacity || self.state(index).load(Ordering::Relaxed) != state::PUBLISHED {
+            return None;
+        }
+        Some(unsafe { &mut *self.inner.slot(index * size_of::<T>()).cast::<T>() })
+    }
+
+    /// Get the item at `index`, whether or not it has been published.
+    /// # Safety
+    /// `index` must have been returned by [`push`](Self::push), and the item must not have been
+    /// removed. The write of the item must happen-before this call.
+    pub unsafe fn get_unchecked(&self, index: usize) -> &T {
+        &*self.inner.slot(index * size_of::<T>()).cast::<T>()
+    }
+
+    /// The number of slots which have been pushed to, including any which were never published.
+    pub fn len(&self) -> usize {
+        self.inner.next.load(Ordering::Acquire) / size_of::<T>()
+    }
+
+    /// The number of slots the array can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drop every item at or after `len`, leaving `len` slots.
+    pub fn truncate(&mut self, len: usize) {
+        for index in len..self.len() {
+            if self.state(index).swap(state::VACANT, Ordering::Relaxed) != state::VACANT {
+                unsafe {
+                    self.inner
+                        .slot(index * size_of::<T>())
+                        .cast::<T>()
+                        .drop_in_place();
+                }
+            }
+        }
+        let next = self.inner.next.get_mut();
+        *next = (*next).min(len * size_of::<T>());
+    }
+
+    /// Keep only the items at `indexes`, which must be ascending, moving them to the front of the
+    /// array in the same order. Every other item is dropped.
+    pub fn retain_indexes(&mut self, indexes: &[usize]) {
+        let mut kept = indexes.iter().peekable();
+        for index in 0..self.len() {
+            if kept.next_if_eq(&&index).is_some() {
+                continue;
+            }
+            if self.state(index).swap(state::VACANT, Ordering::Relaxed) != state::VACANT {
+                unsafe {
+                    self.inner
+                        .slot(index * size_of::<T>())
+                        .cast::<T>()
+                        .drop_in_place();
+                }
+            }
+        }
+        for (new, &old) in indexes.iter().enumerate() {
+            debug_assert!(new <= old);
+            if new == old {
+                continue;
+            }
+            let state = self.state(old).swap(state::VACANT, Ordering::Relaxed);
+            unsafe {
+                let item = self.inner.slot(old * size_of::<T>()).cast::<T>().read();
+                self.inner
+                    .slot(new * size_of::<T>())
+                    .cast::<T>()
+                    .write(item);
+            }
+            self.state(new).store(state, Ordering::Relaxed);
+        }
+        *self.inner.next.get_mut() = indexes.len() * size_of::<T>();
+    }
+
+    /// Move every item out of the array, in index order. Slots which hold no item are skipped.
+    pub fn into_vec(mut self) -> Vec<T> {
+        let mut items = Vec::with_capacity(self.len());
+        for index in 0..self.len() {
+            if self.state(index).swap(state::VACANT, Ordering::Relaxed) != state::VACANT {
+                items.push(unsafe { self.inner.slot(index * size_of::<T>()).cast::<T>().read() });
+            }
+        }
+        *self.inner.next.get_mut() = 0;
+        items
+    }
+
+    fn state(&self, index: usize) -> &AtomicU8 {
+        debug_assert!(index < self.capacity);
+        unsafe { &*self.states.add(index) }
+    }
+}
+
+impl<T> Drop for ConcurrentArray<T> {
+    fn drop(&mut self) {
+        self.truncate(0);
+        util::deallocate(self.states, self.capacity);
     }
 }
 
@@ -69,16 +227,20 @@ struct ConcurrentArena<T> {
 
 impl<T> ConcurrentArena<T> {
     /// Construct a new `ConcurrentArena` which can hold `item_capacity` amount of T.
-    pub fn new(item_capacity: usize) -> Self {
+    /// Returns None if the allocation fails.
+    pub fn try_new(item_capacity: usize) -> Option<Self> {
         let layout = Self::full_layout(item_capacity);
         let bytes_ptr = unsafe { alloc(layout) };
+        if bytes_ptr.is_null() {
+            return None;
+        }
         let bytes_slice = slice_from_raw_parts_mut(bytes_ptr, layout.size());
-        Self {
+        Some(Self {
             bytes: bytes_slice,
             next: AtomicUsize::new(0),
             byte_capacity: layout.size(),
             _marker: core::marker::PhantomData,
-        }
+        })
     }
 
     pub fn push(&self) -> Option<AllocResult> {
@@ -96,12 +258,10 @@ impl<T> ConcurrentArena<T> {
             })
     }
 
-    pub fn get(&self, index: usize) -> Option<*mut u8> {
-        if index >= self.next.load(Ordering::Acquire) {
-            return None;
-        }
-
-        Some(unsafe { self.bytes.cast::<u8>().add(index) })
+    /// Get a pointer to the slot at `index`, which must be less than `byte_capacity`.
+    pub unsafe fn slot(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.byte_capacity);
+        self.bytes.cast::<u8>().add(index)
     }
 
     /// Remove an item from the arena at index. Will only succeed if the item was the most recently
@@ -113,10 +273,6 @@ impl<T> ConcurrentArena<T> {
             .is_ok()
     }
 
-    fn index_to_slice(&self, index: usize) -> *mut [u8] {
-        unsafe { slice_from_raw_parts_mut(self.bytes.cast::<u8>().add(index), size_of::<T>()) }
-    }
-
     fn elem_count(&self) -> usize {
         self.byte_capacity / size_of::<T>()
     }
@@ -135,8 +291,10 @@ impl<T> Drop for ConcurrentArena<T> {
     }
 }
 
-unsafe impl<T> Send for ConcurrentArena<T> {}
-unsafe impl<T> Sync for ConcurrentArena<T> {}
+unsafe impl<T: Send> Send for ConcurrentArena<T> {}
+unsafe impl<T: Send + Sync> Sync for ConcurrentArena<T> {}
+unsafe impl<T: Send> Send for ConcurrentArray<T> {}
+unsafe impl<T: Send + Sync> Sync for ConcurrentArray<T> {}
 
 impl<T> Debug for ConcurrentArena<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -152,6 +310,7 @@ impl<T> Debug for ConcurrentArray<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("ConcurrentArray")
             .field("inner", &self.inner)
+            .field("states", &self.states)
             .field("capcity", &self.capacity)
             .finish()
     }
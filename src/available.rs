@@ -0,0 +1,153 @@
+use crate::{Error, HashMap, HashT, InsertFailure, InsertKey};
+use bytemuck::NoUninit;
+use core::future::Future;
+use core::hash::{BuildHasher, Hash};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::sync::Mutex;
+use std::vec::Vec;
+
+/// The tasks waiting for room in a map, and the hashes of the keys they are inserting. Every task
+/// is woken whenever entries counted by the map are released, and a task is woken on its own when
+/// its key is removed, because the key can take back the tombstone it leaves.
+#[derive(Default)]
+pub struct Waiters {
+    wakers: Mutex<Vec<(Waker, HashT)>>,
+}
+
+impl Waiters {
+    fn register(&self, waker: &Waker, key_hash: HashT) {
+        let mut wakers = self.lock();
+        if !wakers
+            .iter()
+            .any(|(registered, hash)| *hash == key_hash && registered.will_wake(waker))
+        {
+            wakers.push((waker.clone(), key_hash));
+        }
+    }
+
+    /// Wake every waiting task. Each retries its insert, and those which still don't fit register
+    /// again.
+    pub fn wake_all(&self) {
+        // Taking the lock, rather than checking a flag first, orders the release of the count
+        // before any task's registration, so a task which registers after the release sees it.
+        let wakers = core::mem::take(&mut *self.lock());
+        for (waker, _) in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Wake the tasks waiting to insert a key with the hash `key_hash`, which was just removed.
+    pub fn wake_key(&self, key_hash: HashT) {
+        let mut wakers = self.lock();
+        let mut woken = Vec::new();
+        let mut i = 0;
+        while i < wakers.len() {
+            if wakers[i].1 == key_hash {
+                woken.push(wakers.swap_remove(i).0);
+            } else {
+                i += 1;
+            }
+        }
+        drop(wakers);
+        woken.into_iter().for_each(Waker::wake);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(Waker, HashT)>> {
+        self.wakers
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// The key of an [`InsertWhenAvailable`], which is only taken out of the future once the insert
+/// goes ahead, so an insert which doesn't fit can be tried again later.
+struct TakeKey<'a, K>(&'a mut Option<K>);
+
+impl<K: Hash + Eq> InsertKey<K> for TakeKey<'_, K> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        self.0.as_ref().expect(POLLED_AFTER_COMPLETION)
+    }
+
+    fn into_key(self) -> K {
+        self.0.take().expect(POLLED_AFTER_COMPLETION)
+    }
+}
+
+const POLLED_AFTER_COMPLETION: &str = "`InsertWhenAvailable` polled after completion";
+
+/// A future which inserts a key-value pair once the map has room for it. Created by
+/// [`HashMap::insert_when_available`].
+#[must_use = "futures do nothing unless polled"]
+pub struct InsertWhenAvailable<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    map: &'map HashMap<K, V, S>,
+    key: Option<K>,
+    value: V,
+}
+
+// The key is never pinned, only moved into the map.
+impl<K, V, S> Unpin for InsertWhenAvailable<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Insert a key-value pair, waiting for room if the map is full rather than failing with
+    /// [`Error::Full`]. The task is woken whenever room is given back to the map, such as by
+    /// [`Inserter::flush`](crate::Inserter::flush), and when the key is removed, because the key
+    /// can take back the tombstone it leaves. Other removals leave tombstones which still take up
+    /// the room, so they don't wake the task.
+    ///
+    /// The future resolves to the same result as [`try_insert`](Self::try_insert), other than
+    /// [`Error::Full`].
+    pub fn insert_when_available(&self, key: K, value: V) -> InsertWhenAvailable<'_, K, V, S> {
+        InsertWhenAvailable {
+            map: self,
+            key: Some(key),
+            value,
+        }
+    }
+}
+
+impl<K, V, S> Future for InsertWhenAvailable<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let map = this.map;
+        let key_hash = map._hash(this.key.as_ref().expect(POLLED_AFTER_COMPLETION));
+        let value = this.value;
+        let mut insert =
+            || map._insert_reserving(TakeKey(&mut this.key), key_hash, |_| value, true);
+        let result = match insert() {
+            Err(InsertFailure::Error(Error::Full)) => {
+                map.waiters.register(cx.waker(), key_hash);
+                // Room may have been given back before the waker was registered.
+                insert()
+            }
+            result => result,
+        };
+        match result {
+            Err(InsertFailure::Error(Error::Full)) => Poll::Pending,
+            result => Poll::Ready(result.map(|_| ()).map_err(InsertFailure::into_error)),
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{
+    constants, inject, key_offset, BatchError, DefaultHashBuilder, Entry, Error, HashMap,
+    HashedKey, Probe, Size, UnpushOnExit,
+};
+use alloc::vec;
+use alloc::vec::Vec;
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V> HashMap<K, V, DefaultHashBuilder>
+where
+    K: Hash + Eq + Clone,
+    V: Copy + NoUninit,
+{
+    /// Create a map holding the given key-value pairs, with exactly enough capacity for them.
+    /// See [`try_from_slices`](Self::try_from_slices).
+    /// # Errors
+    /// [`Error::DuplicateKey`] if a key is repeated, or an error from
+    /// [`try_with_capacity`](Self::try_with_capacity).
+    pub fn try_from_slice(pairs: &[(K, V)]) -> Result<Self, Error> {
+        Self::try_from_pairs(
+            pairs.len(),
+            pairs.iter().map(|(key, value)| (key.clone(), *value)),
+        )
+    }
+
+    /// Create a map holding `keys[i]` with `values[i]`, with exactly enough capacity for them.
+    ///
+    /// The map isn't shared while it is being built, so the pairs are inserted without any of the
+    /// atomic read-modify-writes a normal insert needs.
+    /// # Errors
+    /// [`Error::DuplicateKey`] if a key is repeated, or an error from
+    /// [`try_with_capacity`](Self::try_with_capacity).
+    /// # Panics
+    /// If `keys` and `values` have different lengths.
+    pub fn try_from_slices(keys: &[K], values: &[V]) -> Result<Self, Error> {
+        assert_eq!(
+            keys.len(),
+            values.len(),
+            "keys and values must be the same length"
+        );
+        Self::try_from_pairs(keys.len(), keys.iter().cloned().zip(values.iter().copied()))
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher + Default,
+{
+    /// Create a map with the given capacity, holding the pairs from `pairs`.
+    fn try_from_pairs<I>(capacity: usize, pairs: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::try_with_capacity_and_hasher(capacity, S::default())?;
+        for (key, value) in pairs {
+            map._insert_exclusive(key, value)?;
+        }
+        Ok(map)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Apply many updates in one call. Returns, for each update in the order given, the previous
+    /// value on success, or None if the key doesn't exist.
+    ///
+    /// The updates are applied in table order rather than the order given, so neighbouring keys
+    /// are updated together. If the same key is updated more than once, the updates to it are
+    /// still applied in the order given.
+    pub fn update_many<'q, Q, I>(&self, updates: I) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'q,
+        I: IntoIterator<Item = (&'q Q, V)>,
+    {
+        let mut hashed: Vec<_> = updates
+            .into_iter()
+            .enumerate()
+            .map(|(position, (key, value))| {
+                let (key_hash, index) = self._hash_and_index(key);
+                (index, position, key_hash, key, value)
+            })
+            .collect();
+        // A stable sort keeps repeated updates of one key in order.
+        hashed.sort_by_key(|&(index, ..)| index);
+
+        let mut results = vec![None; hashed.len()];
+        for (index, position, key_hash, key, value) in hashed {
+            if let Probe::Found(entry) = self._probe(key, key_hash, index) {
+                results[position] = self
+                    ._update_entry(entry, |current| {
+                        (!current.is_pending()).then_some(Entry { value, ..current })
+                    })
+                    .map(|previous| previous.value);
+            }
+        }
+        results
+    }
+
+    /// Insert a key into a map which isn't shared, without atomic read-modify-writes. Returns the
+    /// key's index in the key store.
+    pub(crate) fn _insert_exclusive(&mut self, key: K, value: V) -> Result<usize, Error> {
+        if self.len() + self._tombstones() >= usize::from(self.capacity) {
+            return Err(Error::Full);
+        }
+        let (key_hash, index) = self._hash_and_index(&key);
+        let key_index = self
+            .key_store
+            .push_mut(HashedKey { key_hash, key })
+            .ok_or(Error::KeyStoreFull)?;
+        // SAFETY: The key was just pushed, and nothing else can access the map.
+        let key = unsafe { self._stored_key(key_index) };
+        let entry = match self._probe(key, key_hash, index) {
+            Probe::Vacant(entry, _) => entry,
+            failed => {
+                let error = probe_error(&failed);
+                self.key_store.truncate(key_index);
+                return Err(error);
+            }
+        };
+        entry.store(
+            Entry {
+                key_hash,
+                key_offset: key_offset(key_index),
+                value,
+            },
+            Ordering::Relaxed,
+        );
+        self._touch(key_index);
+        // Can't overflow, because the count was checked against the capacity.
+        *self.count.get_mut() += 1;
+        Ok(key_index)
+    }
+
+    /// Insert every key-value pair in `pairs`, or none of them.
+    ///
+    /// The batch is checked for duplicate keys and room is reserved for all of it before anything
+    /// is inserted, other than for removed keys, which take back the tombstones they left. Its entries are then placed in the table as pending, which other threads
+    /// can't see, and are only committed once every entry has been placed. If a key is found to
+    /// already exist, the placed entries are rolled back.
+    ///
+    /// Other threads may see the keys appear one at a time as the batch commits. An insert of one
+    /// of the keys from another thread waits until the batch has committed or rolled back.
+    /// A rolled back entry leaves a tombstone in the table, which counts towards the map's
+    /// capacity until its key is inserted again.
+    /// # Errors
+    /// A [`BatchError`] with the position of the item which failed, and:
+    /// - [`Error::DuplicateKey`] if a key is repeated in `pairs`, or already exists.
+    /// - [`Error::Full`] or [`Error::KeyStoreFull`] if the batch doesn't fit.
+    /// - [`Error::ProbeLimitExceeded`] if a key can't be placed within the
+    ///   [probe limit](Self::set_probe_limit).
+    pub fn insert_all_or_nothing(&self, pairs: &[(K, V)]) -> Result<(), BatchError>
+    where
+        K: Clone,
+    {
+        let hashes: Vec<_> = pairs.iter().map(|(key, _)| self._hash(key)).collect();
+        if let Some(index) = first_repeated(pairs, &hashes) {
+            return Err(BatchError {
+                index: Some(index),
+                error: Error::DuplicateKey,
+            });
+        }
+        let whole = |error| BatchError { index: None, error };
+        // A removed key takes back its tombstone, which never becomes empty while the map is
+        // shared, so it needs no room of its own.
+        let removed = pairs
+            .iter()
+            .zip(&hashes)
+            .filter(|((key, _), &key_hash)| {
+                matches!(
+                    self._probe(key, key_hash, self._index(key_hash)),
+                    Probe::Removed(..)
+                )
+            })
+            .count();
+        let fresh = pairs.len() - removed;
+        let reserved = Size::try_from(fresh).map_err(|_| whole(Error::Full))?;
+        self._reserve_count(reserved).map_err(whole)?;
+        if self.key_store.capacity() - self.key_store.len() < fresh {
+            self._release_count(reserved);
+            return Err(whole(Error::KeyStoreFull));
+        }
+
+        // Placing a key calls `Eq`, which may panic. Until the batch commits, any exit rolls back.
+        let mut pending = RollBackOnExit {
+            map: self,
+            placed: Vec::with_capacity(pairs.len()),
+            reserved,
+        };
+        for (position, ((key, value), &key_hash)) in pairs.iter().zip(&hashes).enumerate() {
+            let entry = self
+                ._place_pending(key, key_hash, *value, &mut pending.reserved)
+                .map_err(|error| BatchError {
+                    index: Some(position),
+                    error,
+                })?;
+            pending.placed.push(entry);
+        }
+        let placed = core::mem::take(&mut pending.placed);
+        // Room is left over if a key was removed after it was counted as fresh.
+        self._release_count(pending.reserved);
+        core::mem::forget(pending);
+
+        for &(entry, key_index) in &placed {
+            let current = entry.load(Ordering::Relaxed);
+            entry.store(
+                Entry {
+                    key_hash: current.key_hash & !constants::PENDING,
+                    ..current
+                },
+                Ordering::Release,
+            );
+            self._touch(key_index);
+            self.key_store.publish(key_index);
+        }
+        Ok(())
+    }
+
+    /// Push `key` to the key store and place a pending entry for it in the table, using up one of
+    /// the entries `reserved` for it. Returns the entry and the key's index. A removed key takes
+    /// back its tombstone and its slot in the key store instead, and is counted without using up
+    /// a reserved entry.
+    ///
+    /// Unlike an insert, this doesn't wait for pending entries from other batches, which could
+    /// be waiting for this batch in turn.
+    pub(crate) fn _place_pending(
+        &self,
+        key: &K,
+        key_hash: crate::HashT,
+        value: V,
+        reserved: &mut Size,
+    ) -> Result<(&Atomic<Entry<V>>, usize), Error>
+    where
+        K: Clone,
+    {
+        let mut index = self._index(key_hash);
+        let (mut entry, next_index) = match self._probe(key, key_hash, index) {
+            Probe::Vacant(entry, next_index) => (entry, next_index),
+            Probe::Removed(removed, _) => {
+                let tombstone = removed.load(Ordering::Acquire);
+                let key_offset = tombstone.removed_key().ok_or(Error::DuplicateKey)?;
+                let pending = Entry {
+                    key_hash: key_hash | constants::PENDING,
+                    key_offset,
+                    value,
+                };
+                removed
+                    .compare_exchange(tombstone, pending, Ordering::Release, Ordering::Relaxed)
+                    .map_err(|_| Error::DuplicateKey)?;
+                self._count_taken_back(false);
+                return Ok((removed, crate::key_index(key_offset)));
+            }
+            failed => return Err(probe_error(&failed)),
+        };
+        // A key which had a tombstone when the batch reserved room still has it, so never gets
+        // this far without room reserved.
+        if *reserved == 0 {
+            return Err(Error::Full);
+        }
+        index = next_index;
+        let (stored, key_index) = (!inject::fail(inject::Point::KeyStorePush))
+            .then(|| {
+                self.key_store.push(HashedKey {
+                    key_hash,
+                    key: key.clone(),
+                })
+            })
+            .flatten()
+            .ok_or(Error::KeyStoreFull)?;
+        let key = &stored.key;
+        let pushed = UnpushOnExit {
+            map: self,
+            key_index,
+            reserved: 0,
+        };
+        let new_entry = Entry {
+            key_hash: key_hash | constants::PENDING,
+            key_offset: key_offset(key_index),
+            value,
+        };
+        while inject::fail(inject::Point::InsertCas)
+            || entry
+                .compare_exchange(
+                    Entry::EMPTY,
+                    new_entry,
+                    Ordering::Release,
+                    Ordering::Acquire,
+                )
+                .is_err()
+        {
+            match self._probe(key, key_hash, index) {
+                Probe::Vacant(next_entry, next_index) => {
+                    entry = next_entry;
+                    index = next_index;
+                }
+                failed => {
+                    drop(pushed);
+                    return Err(probe_error(&failed));
+                }
+            }
+        }
+        core::mem::forget(pushed);
+        *reserved -= 1;
+        Ok((entry, key_index))
+    }
+
+    /// Turn the `placed` entries of a failed batch into tombstones, and release the `reserved`
+    /// entries it didn't use. The batch's keys stay in the key store, because other threads may
+    /// be reading them through the entries, and an insert of one of the keys takes its tombstone
+    /// back.
+    pub(crate) fn _roll_back(&self, placed: &[(&Atomic<Entry<V>>, usize)], reserved: Size) {
+        // A batch has no more than `Size::MAX` keys. The tombstones are counted first, because
+        // inserts of their keys may take them back straight away.
+        let tombstones = Size::try_from(placed.len()).unwrap_or(Size::MAX);
+        self._add_tombstones(tombstones);
+        for &(entry, _) in placed {
+            let current = entry.load(Ordering::Relaxed);
+            entry.store(current.removed(), Ordering::Release);
+            #[cfg(feature = "async")]
+            self.waiters
+                .wake_key(current.key_hash & constants::HASH_MASK);
+        }
+        // The tombstones take up the room of the placed entries, so only tasks waiting to insert
+        // their keys can use it.
+        self._uncount(tombstones);
+        self._release_count(reserved);
+    }
+}
+
+/// Rolls back the entries a batch has `placed`, and releases its `reserved` count, if the batch
+/// fails or unwinds before it commits.
+struct RollBackOnExit<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    map: &'map HashMap<K, V, S>,
+    placed: Vec<(&'map Atomic<Entry<V>>, usize)>,
+    reserved: Size,
+}
+
+impl<K, V, S> Drop for RollBackOnExit<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.map._roll_back(&self.placed, self.reserved);
+    }
+}
+
+/// The error for a probe which didn't find an empty entry.
+fn probe_error<V>(probe: &Probe<'_, V>) -> Error {
+    match probe {
+        Probe::LimitExceeded => Error::ProbeLimitExceeded,
+        _ => Error::DuplicateKey,
+    }
+}
+
+/// Find the position of the first key in `pairs` which repeats an earlier key.
+fn first_repeated<K: Eq, V>(pairs: &[(K, V)], hashes: &[crate::HashT]) -> Option<usize> {
+    let mut order: Vec<usize> = (0..pairs.len()).collect();
+    order.sort_unstable_by_key(|&position| (hashes[position], position));
+    let mut repeated = None;
+    for (i, &later) in order.iter().enumerate() {
+        let earlier = order[..i]
+            .iter()
+            .rev()
+            .take_while(|&&earlier| hashes[earlier] == hashes[later]);
+        if earlier
+            .into_iter()
+            .any(|&earlier| pairs[earlier].0 == pairs[later].0)
+        {
+            repeated = Some(repeated.map_or(later, |repeated: usize| repeated.min(later)));
+        }
+    }
+    repeated
+}
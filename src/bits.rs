@@ -0,0 +1,97 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Conversion of a value to and from the two bytes a map entry holds, for value types which
+/// aren't [`NoUninit`](bytemuck::NoUninit), such as enums with fields or structs with padding.
+///
+/// `from_bits` is only ever passed bits returned by `to_bits`.
+pub trait ToBits: Copy {
+    fn to_bits(self) -> u16;
+    fn from_bits(bits: u16) -> Self;
+}
+
+/// A value stored as the bits returned by [`ToBits::to_bits`], so that maps can hold types which
+/// can't be copied into an entry byte for byte.
+///
+/// ```
+/// use folklore::{Bits, HashMap, ToBits};
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// enum State {
+///     Idle,
+///     Busy(u8),
+/// }
+///
+/// impl ToBits for State {
+///     fn to_bits(self) -> u16 {
+///         match self {
+///             State::Idle => 0,
+///             State::Busy(job) => 0x100 | u16::from(job),
+///         }
+///     }
+///
+///     fn from_bits(bits: u16) -> Self {
+///         match bits >> 8 {
+///             0 => State::Idle,
+///             _ => State::Busy(bits as u8),
+///         }
+///     }
+/// }
+///
+/// let map: HashMap<u32, Bits<State>> = HashMap::with_capacity(16);
+/// map.insert(1, Bits::new(State::Busy(7)));
+/// assert_eq!(map.get(&1).map(Bits::get), Some(State::Busy(7)));
+/// ```
+#[repr(transparent)]
+pub struct Bits<T> {
+    bits: u16,
+    _marker: PhantomData<T>,
+}
+
+impl<T: ToBits> Bits<T> {
+    #[must_use]
+    pub fn new(value: T) -> Self {
+        Self {
+            bits: value.to_bits(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Convert the bits back to a value.
+    #[must_use]
+    pub fn get(self) -> T {
+        T::from_bits(self.bits)
+    }
+}
+
+impl<T: ToBits> From<T> for Bits<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+// Implemented by hand, because deriving would require `T` to implement the traits too.
+impl<T> Clone for Bits<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Bits<T> {}
+
+impl<T> PartialEq for Bits<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<T> Eq for Bits<T> {}
+
+impl<T: ToBits + fmt::Debug> fmt::Debug for Bits<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Bits").field(&self.get()).finish()
+    }
+}
+
+// SAFETY: `Bits` is a transparent `u16`, which has no padding.
+unsafe impl<T: 'static> bytemuck::NoUninit for Bits<T> {}
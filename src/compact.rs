@@ -0,0 +1,275 @@
+use crate::raw::RawTable;
+use crate::{constants, Entry, Error, HashMap, HashT, HashedKey, InsertFailure, Size};
+use alloc::vec::Vec;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+use core::sync::atomic::Ordering;
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Copy + NoUninit,
+    S: BuildHasher + Clone,
+{
+    /// Build a new map with the same capacity and hasher, containing only the entries for which
+    /// `pred` returns true. Unlike [`remove`](Self::remove), this leaves no tombstones behind in
+    /// the new map.
+    ///
+    /// Keys are copied in insertion order, with their flags, and aren't rehashed.
+    ///
+    /// If the new map can't be allocated, this calls
+    /// [`handle_alloc_error`](alloc::alloc::handle_alloc_error), which aborts the process by
+    /// default. [`try_compact_filter_with_capacity`](Self::try_compact_filter_with_capacity)
+    /// returns [`Error::AllocationFailed`] instead.
+    #[must_use]
+    pub fn compact_filter<F>(&self, pred: F) -> Self
+    where
+        F: FnMut(&K, V) -> bool,
+    {
+        let new =
+            Self::with_capacity_and_hasher(usize::from(self.capacity), self.hash_builder.clone());
+        // The new map can hold every entry in this one, so copying can't fail.
+        let _ = self._copy_filtered(&new, pred);
+        new
+    }
+
+    /// Build a new map with the given capacity and the same hasher, containing only the entries
+    /// for which `pred` returns true. See [`compact_filter`](Self::compact_filter).
+    /// # Errors
+    /// - [`Error::CapacityTooLarge`] or [`Error::AllocationFailed`] if the new map can't be
+    ///   created.
+    /// - [`Error::Full`] if the entries which pass `pred` don't fit in `capacity`.
+    pub fn try_compact_filter_with_capacity<F>(
+        &self,
+        capacity: usize,
+        pred: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(&K, V) -> bool,
+    {
+        let new = Self::try_with_capacity_and_hasher(capacity, self.hash_builder.clone())?;
+        self._copy_filtered(&new, pred)?;
+        Ok(new)
+    }
+
+    /// Build a new map with the given capacity and the same hasher, containing every entry in this
+    /// map. This map is left unchanged, so a map can be grown by cloning it into a larger one,
+    /// then swapping the new map in (for example, behind an `Arc`).
+    ///
+    /// Keys are copied in insertion order, with their flags, and aren't rehashed. Entries inserted
+    /// or updated concurrently may or may not be copied.
+    /// # Errors
+    /// - [`Error::CapacityTooLarge`] or [`Error::AllocationFailed`] if the new map can't be
+    ///   created.
+    /// - [`Error::Full`] if the entries don't fit in `new_capacity`.
+    pub fn clone_resized(&self, new_capacity: usize) -> Result<Self, Error> {
+        self.try_compact_filter_with_capacity(new_capacity, |_, _| true)
+    }
+
+    /// Build a new map with the same keys, capacity and hasher, whose values are `f` of this map's
+    /// values. Flags are kept.
+    ///
+    /// Entries are copied to the same positions in the new table, so no key is hashed, compared or
+    /// probed for. Only keys in the map are copied, in insertion order. Entries inserted or updated
+    /// concurrently may or may not be copied.
+    /// ```
+    /// let counts = folklore::HashMap::<&str, u16>::with_capacity(8);
+    /// counts.insert("a", 3);
+    /// let bytes = counts.map_values(u16::to_le_bytes);
+    /// assert_eq!(bytes.get("a"), Some([3, 0]));
+    /// ```
+    #[must_use]
+    pub fn map_values<V2, F>(&self, mut f: F) -> HashMap<K, V2, S>
+    where
+        V2: Copy + NoUninit,
+        F: FnMut(V) -> V2,
+    {
+        let capacity = usize::from(self.capacity);
+        let mut new = HashMap::try_with_capacity_and_hasher_aligned(
+            capacity,
+            self.hash_builder.clone(),
+            self.table.align(),
+        )
+        .unwrap_or_else(|_| {
+            alloc::alloc::handle_alloc_error(RawTable::<Entry<V2>>::layout(self.table.slots()))
+        });
+        let mut live: Vec<(Size, Entry<V>)> = Vec::new();
+        let (mut count, mut tombstones) = (0, 0);
+        for (entry, index) in self.table.iter().zip(0..) {
+            let entry = entry.load(Ordering::Acquire);
+            if entry.is_live() {
+                live.push((index, entry));
+            } else if entry.key_offset != constants::EMPTY_KEY {
+                // Keep the entries which probes step over, so the copied entries can be found.
+                // An entry in an uncommitted batch is copied as if it rolled back.
+                let tombstone = Entry {
+                    key_hash: entry.key_hash & !constants::PENDING,
+                    key_offset: constants::TOMBSTONE,
+                    ..Entry::EMPTY
+                };
+                new.table.entry(index).store(tombstone, Ordering::Relaxed);
+                tombstones += 1;
+            }
+        }
+        live.sort_unstable_by_key(|(_, entry)| entry.key_offset);
+        for (index, entry) in live {
+            // SAFETY: The entry was loaded with `Acquire`.
+            let key = unsafe { self._entry_key(entry.key_offset) };
+            let stored = HashedKey {
+                key_hash: entry.key_hash & constants::HASH_MASK,
+                key: key.clone(),
+            };
+            // The new key store has the same capacity, and holds fewer keys.
+            let Some(key_index) = new.key_store.push_mut(stored) else {
+                break;
+            };
+            let copied = Entry {
+                key_hash: entry.key_hash,
+                key_offset: crate::key_offset(key_index),
+                value: f(entry.value),
+            };
+            new.table.entry(index).store(copied, Ordering::Relaxed);
+            count += 1;
+        }
+        *new.count.get_mut() = count;
+        *new.tombstones.get_mut() = tombstones;
+        new
+    }
+
+    /// Insert every entry for which `pred` returns true into `new`, which must use the same
+    /// hasher, reusing the hashes stored in this map's table.
+    fn _copy_filtered<F>(&self, new: &Self, mut pred: F) -> Result<(), Error>
+    where
+        F: FnMut(&K, V) -> bool,
+    {
+        for entry in self._entries_in_insertion_order() {
+            // SAFETY: `_occupied_entries` loads entries with `Acquire`.
+            let key = unsafe { self._entry_key(entry.key_offset) };
+            if pred(key, entry.value) {
+                new._insert_hashed(key.clone(), entry.key_hash, |_| entry.value)
+                    .map_err(InsertFailure::into_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Split the map's entries between `n` new maps, by the hash of each key. Use
+    /// [`shard_for`](Self::shard_for) to find which shard holds a key. This map is left
+    /// unchanged.
+    ///
+    /// Each shard has an equal share of this map's capacity, or more if it needs it to hold its
+    /// entries. Keys are copied in insertion order, with their flags, and aren't rehashed.
+    /// # Errors
+    /// [`Error::AllocationFailed`] if a shard can't be created.
+    /// # Panics
+    /// If `n` is zero.
+    pub fn split_into_shards(&self, n: usize) -> Result<Vec<Self>, Error> {
+        assert!(n > 0, "a map can't be split into zero shards");
+        let entries = self._entries_in_insertion_order();
+        let mut counts = alloc::vec![0; n];
+        for entry in &entries {
+            counts[shard_of(entry.key_hash, n)] += 1;
+        }
+        let share = (usize::from(self.capacity)).div_ceil(n);
+        let shards = counts
+            .into_iter()
+            .map(|count| {
+                Self::try_with_capacity_and_hasher(share.max(count), self.hash_builder.clone())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for entry in entries {
+            // SAFETY: `_entries_in_insertion_order` loads entries with `Acquire`.
+            let key = unsafe { self._entry_key(entry.key_offset) };
+            shards[shard_of(entry.key_hash, n)]
+                ._insert_hashed(key.clone(), entry.key_hash, |_| entry.value)
+                .map_err(InsertFailure::into_error)?;
+        }
+        Ok(shards)
+    }
+
+    /// The index of the shard which holds `key`, when this map, or a map with the same hasher, is
+    /// split into `n` shards by [`split_into_shards`](Self::split_into_shards).
+    pub fn shard_for<Q>(&self, key: &Q, n: usize) -> usize
+    where
+        K: core::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        shard_of(self._hash(key), n)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Rewrite the key store densely, dropping keys which no entry refers to, such as those left
+    /// behind by rolled-back batches, and removed keys. Returns the number of key store slots
+    /// reclaimed, which can then be used by new inserts. The tombstones of removed keys stay in
+    /// the table, but the keys can no longer take them back.
+    ///
+    /// Keys keep their insertion order, but their indexes in the key store change, so indexes
+    /// previously passed to a [`WriteLogger`](crate::WriteLogger) are no longer valid, and earlier
+    /// [`Checkpoint`](crate::Checkpoint)s can't be restored.
+    pub fn compact_key_store(&mut self) -> usize {
+        let mut referenced: Vec<usize> = self
+            .table
+            .iter()
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .filter(Entry::has_key)
+            .map(|entry| crate::key_index(entry.key_offset))
+            .collect();
+        referenced.sort_unstable();
+        let reclaimed = self.key_store.len() - referenced.len();
+        if reclaimed == 0 {
+            return 0;
+        }
+        // Ticks move down in the order their keys do, so none is overwritten before it has moved.
+        #[cfg(feature = "timestamps")]
+        for (new, &old) in referenced.iter().enumerate() {
+            self.timestamps.relocate(old, new);
+        }
+        for entry in self.table.iter() {
+            let mut loaded = entry.load(Ordering::Relaxed);
+            if loaded.removed_key().is_some() {
+                // The key is dropped, so the tombstone can't refer to it any more.
+                entry.store(
+                    Entry {
+                        key_hash: 0,
+                        ..loaded
+                    },
+                    Ordering::Relaxed,
+                );
+                continue;
+            }
+            if !loaded.has_key() {
+                continue;
+            }
+            let old = crate::key_index(loaded.key_offset);
+            // Every referenced index was collected above.
+            let new = referenced.binary_search(&old).unwrap_or(old);
+            loaded.key_offset = crate::key_offset(new);
+            entry.store(loaded, Ordering::Relaxed);
+        }
+        self.key_store.retain_indexes(&referenced);
+        self._invalidate_checkpoints();
+        reclaimed
+    }
+
+    /// The live entries in the table, sorted by the order their keys were inserted.
+    pub(crate) fn _entries_in_insertion_order(&self) -> Vec<Entry<V>> {
+        let mut entries: Vec<_> = self._occupied_entries().collect();
+        entries.sort_unstable_by_key(|entry| entry.key_offset);
+        entries
+    }
+}
+
+/// Pick a shard from the high bits of the hash. The low bits pick the entry in each shard's
+/// table, so sharding by them would leave every shard's keys clustered.
+fn shard_of(key_hash: HashT, n: usize) -> usize {
+    let hash = (key_hash & constants::HASH_MASK) as usize;
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = ((hash as u64 * n as u64) >> constants::HASH_MASK.count_ones()) as usize;
+    shard
+}
@@ -0,0 +1,189 @@
+use crate::reclaim::Reclaim;
+use crate::{GrowableHashMap, HashMap, Logged, ShardedHashMap, WriteLogger};
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// The operations shared by the concurrent maps in this crate which own their keys, so code which
+/// only needs those can be written once and run against any of them. It is implemented by
+/// [`HashMap`], a [`Logged`] map, [`ShardedHashMap`], [`GrowableHashMap`] and, with the `wide`
+/// feature, `WideHashMap`. The read-only maps, and
+/// [`ExternalKeyMap`](crate::ExternalKeyMap), which is keyed by index, don't implement it.
+pub trait ConcurrentMap {
+    type Key;
+    type Value: Copy;
+
+    /// Insert a key-value pair. Returns true if the key was inserted, false if the map is full or
+    /// the key already exists.
+    fn insert(&self, key: Self::Key, value: Self::Value) -> bool;
+
+    /// Get the value associated with a key, or None if the key doesn't exist.
+    fn get(&self, key: &Self::Key) -> Option<Self::Value>;
+
+    /// Replace the value associated with a key. Returns the previous value, or None if the key
+    /// doesn't exist.
+    fn update(&self, key: &Self::Key, value: Self::Value) -> Option<Self::Value>;
+
+    /// The number of key-value pairs in the map.
+    fn len(&self) -> usize;
+
+    /// The number of key-value pairs the map can hold.
+    fn capacity(&self) -> usize;
+
+    /// Returns true if the map contains no key-value pairs.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> ConcurrentMap for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    type Key = K;
+    type Value = V;
+
+    fn insert(&self, key: K, value: V) -> bool {
+        HashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        HashMap::get(self, key)
+    }
+
+    fn update(&self, key: &K, value: V) -> Option<V> {
+        HashMap::update(self, key, value)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        HashMap::capacity(self)
+    }
+}
+
+impl<K, V, S, L> ConcurrentMap for Logged<'_, K, V, S, L>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+    L: WriteLogger<K, V>,
+{
+    type Key = K;
+    type Value = V;
+
+    fn insert(&self, key: K, value: V) -> bool {
+        Logged::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.map().get(key)
+    }
+
+    fn update(&self, key: &K, value: V) -> Option<V> {
+        Logged::update(self, key, value)
+    }
+
+    fn len(&self) -> usize {
+        self.map().len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.map().capacity()
+    }
+}
+
+impl<K, V, S> ConcurrentMap for ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    type Key = K;
+    type Value = V;
+
+    fn insert(&self, key: K, value: V) -> bool {
+        ShardedHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        ShardedHashMap::get(self, key)
+    }
+
+    fn update(&self, key: &K, value: V) -> Option<V> {
+        ShardedHashMap::update(self, key, value)
+    }
+
+    fn len(&self) -> usize {
+        ShardedHashMap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        ShardedHashMap::capacity(self)
+    }
+}
+
+impl<K, V, S, R> ConcurrentMap for GrowableHashMap<K, V, S, R>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Copy + NoUninit + Send + 'static,
+    S: BuildHasher + Clone + Send + 'static,
+    R: Reclaim,
+{
+    type Key = K;
+    type Value = V;
+
+    fn insert(&self, key: K, value: V) -> bool {
+        GrowableHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        GrowableHashMap::get(self, key)
+    }
+
+    fn update(&self, key: &K, value: V) -> Option<V> {
+        GrowableHashMap::update(self, key, value)
+    }
+
+    fn len(&self) -> usize {
+        GrowableHashMap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        GrowableHashMap::capacity(self)
+    }
+}
+
+#[cfg(feature = "wide")]
+impl<K, V, S> ConcurrentMap for crate::WideHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    type Key = K;
+    type Value = V;
+
+    fn insert(&self, key: K, value: V) -> bool {
+        crate::WideHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        crate::WideHashMap::get(self, key)
+    }
+
+    fn update(&self, key: &K, value: V) -> Option<V> {
+        crate::WideHashMap::update(self, key, value)
+    }
+
+    fn len(&self) -> usize {
+        crate::WideHashMap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        crate::WideHashMap::capacity(self)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::HashMap;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// A position in a map's key store, which can be saved and resumed later without borrowing the
+/// map, for exporting a map a chunk at a time. Created by [`HashMap::cursor`].
+///
+/// A cursor yields the entries whose keys were inserted before it was created, in insertion
+/// order, and then stops. [`extend`](Self::extend) lets it carry on into keys inserted since.
+/// Its [`position`](Self::position) is a plain integer, which [`HashMap::resume_cursor`] turns
+/// back into a cursor. Positions are only valid for the map they came from, and not after its key
+/// store is [compacted](HashMap::compact_key_store).
+/// ```
+/// let map = folklore::HashMap::<u32, u16>::with_capacity(8);
+/// map.insert(1, 1);
+/// map.insert(2, 2);
+/// let mut cursor = map.cursor();
+/// assert_eq!(cursor.next(&map), Some((&1, 1)));
+/// let saved = cursor.position();
+///
+/// map.insert(3, 3);
+/// let mut cursor = map.resume_cursor(saved);
+/// let rest: Vec<_> = std::iter::from_fn(|| cursor.next(&map)).collect();
+/// assert_eq!(rest, [(&2, 2), (&3, 3)]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    position: usize,
+    end: usize,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Create a cursor at the start of the map, which yields the keys inserted so far.
+    pub fn cursor(&self) -> Cursor {
+        self.resume_cursor(0)
+    }
+
+    /// Create a cursor at a `position` saved from [`Cursor::position`], which yields the keys from
+    /// there up to those inserted so far.
+    pub fn resume_cursor(&self, position: usize) -> Cursor {
+        Cursor {
+            position,
+            end: self.key_store.len(),
+        }
+    }
+}
+
+impl Cursor {
+    /// The index in the key store of the next key the cursor will look at. Save it to resume the
+    /// cursor with [`HashMap::resume_cursor`].
+    #[must_use]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns true once the cursor has passed every key it was created to yield.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.end
+    }
+
+    /// Get the next key-value pair, advancing the cursor. Returns None once the cursor is
+    /// finished. `map` must be the map the cursor was created from.
+    pub fn next<'map, K, V, S>(&mut self, map: &'map HashMap<K, V, S>) -> Option<(&'map K, V)>
+    where
+        K: Hash + Eq,
+        V: Copy + NoUninit,
+        S: BuildHasher,
+    {
+        while !self.is_finished() {
+            let index = self.position;
+            self.position += 1;
+            if let Some(item) = map._get_stored(index) {
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    /// Let the cursor carry on into the keys inserted into `map` since it was created.
+    pub fn extend<K, V, S>(&mut self, map: &HashMap<K, V, S>)
+    where
+        K: Hash + Eq,
+        V: Copy + NoUninit,
+        S: BuildHasher,
+    {
+        self.end = self.end.max(map.key_store.len());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{Error, HashMap};
+use alloc::vec::Vec;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// A fixed-size type with a canonical little-endian encoding, used by [`HashMap::encode`] and
+/// [`HashMap::decode`]. The encoding is the same on every target, so a map encoded on a
+/// little-endian machine can be decoded on a big-endian one.
+pub trait LeBytes: Sized {
+    /// The number of bytes in the encoding.
+    const SIZE: usize;
+
+    /// Write the encoding into `bytes`, which is exactly [`SIZE`](Self::SIZE) bytes long.
+    fn write_le(&self, bytes: &mut [u8]);
+
+    /// Read a value back from `bytes`, which is exactly [`SIZE`](Self::SIZE) bytes long.
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_le_bytes {
+    ($($t:ty),+) => {
+        $(impl LeBytes for $t {
+            const SIZE: usize = core::mem::size_of::<$t>();
+
+            fn write_le(&self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                let mut array = [0; core::mem::size_of::<$t>()];
+                array.copy_from_slice(bytes);
+                <$t>::from_le_bytes(array)
+            }
+        })+
+    };
+}
+
+impl_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<const N: usize> LeBytes for [u8; N] {
+    const SIZE: usize = N;
+
+    fn write_le(&self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(self);
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        array
+    }
+}
+
+/// Identifies an encoded map, and the version of the format.
+const MAGIC: [u8; 4] = *b"flk\x01";
+/// The magic, then the key and value sizes, the capacity, and the number of entries as `u16`s.
+const HEADER_SIZE: usize = MAGIC.len() + 4 * 2;
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq + LeBytes,
+    V: Copy + NoUninit + LeBytes,
+    S: BuildHasher,
+{
+    /// Encode the map's capacity, keys, values and flags, in insertion order. Every integer is
+    /// little-endian, whatever the target, so the encoding can be [decoded](Self::decode) on any
+    /// machine.
+    ///
+    /// Entries are stored rather than the table itself, because a key's hash can depend on the
+    /// target's endianness, so the table is rebuilt when the map is decoded. The encoding is only
+    /// consistent if the map isn't modified while it is written.
+    pub fn encode(&self) -> Vec<u8> {
+        let entry_size = 1 + K::SIZE + V::SIZE;
+        let entries = self._entries_in_insertion_order();
+        let mut bytes = alloc::vec![0; HEADER_SIZE + entries.len() * entry_size];
+        let (header, body) = bytes.split_at_mut(HEADER_SIZE);
+        header[..MAGIC.len()].copy_from_slice(&MAGIC);
+        // The number of entries never exceeds the capacity, but a key or value too large for its
+        // size to fit is written as `u16::MAX`, which decoding rejects.
+        let to_u16 = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+        let fields = [
+            to_u16(K::SIZE),
+            to_u16(V::SIZE),
+            self.capacity,
+            to_u16(entries.len()),
+        ];
+        for (field, out) in fields.iter().zip(header[MAGIC.len()..].chunks_exact_mut(2)) {
+            field.write_le(out);
+        }
+        for (entry, out) in entries.into_iter().zip(body.chunks_exact_mut(entry_size)) {
+            let (flags, rest) = out.split_at_mut(1);
+            let (key, value) = rest.split_at_mut(K::SIZE);
+            flags[0] = entry.flags();
+            // SAFETY: `_entries_in_insertion_order` loads entries with `Acquire`.
+            unsafe { self._entry_key(entry.key_offset) }.write_le(key);
+            entry.value.write_le(value);
+        }
+        bytes
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq + LeBytes,
+    V: Copy + NoUninit + LeBytes,
+    S: BuildHasher + Default,
+{
+    /// Decode a map written by [`encode`](Self::encode), on this or any other target. The map
+    /// has the same capacity, and its keys are inserted in the same order.
+    /// # Errors
+    /// - [`Error::InvalidSnapshot`] if `bytes` isn't an encoding of a map with these key and
+    ///   value types.
+    /// - [`Error::DuplicateKey`], or an error from
+    ///   [`try_with_capacity`](Self::try_with_capacity), if `bytes` was corrupted.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let (header, body) = bytes
+            .split_at_checked(HEADER_SIZE)
+            .ok_or(Error::InvalidSnapshot)?;
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidSnapshot);
+        }
+        let field = |i: usize| usize::from(u16::read_le(&header[MAGIC.len() + 2 * i..][..2]));
+        let (key_size, value_size, capacity, len) = (field(0), field(1), field(2), field(3));
+        let entry_size = 1 + K::SIZE + V::SIZE;
+        if key_size != K::SIZE || value_size != V::SIZE || body.len() != len * entry_size {
+            return Err(Error::InvalidSnapshot);
+        }
+
+        let mut map = Self::try_with_capacity_and_hasher(capacity, S::default())?;
+        for entry in body.chunks_exact(entry_size) {
+            let (&flags, rest) = entry.split_first().ok_or(Error::InvalidSnapshot)?;
+            let (key_bytes, value_bytes) = rest.split_at(K::SIZE);
+            if u32::from(flags) >> crate::FLAG_BITS != 0 {
+                return Err(Error::InvalidSnapshot);
+            }
+            let key_index =
+                map._insert_exclusive(K::read_le(key_bytes), V::read_le(value_bytes))?;
+            if flags != 0 {
+                // SAFETY: The key was just inserted, and is never removed.
+                let key = unsafe { map._stored_key(key_index) };
+                map.set_flags(key, flags);
+            }
+        }
+        Ok(map)
+    }
+}
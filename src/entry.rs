@@ -0,0 +1,200 @@
+use crate::{inject, key_index, Entry, Error, HashMap, HashT, InsertFailure, Probe, Size};
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// A key's place in a map, returned by [`HashMap::entry`]. The key is hashed and probed for once,
+/// and the handle then reads, updates or inserts at the place it was found.
+pub enum MapEntry<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    Occupied(OccupiedEntry<'map, K, V, S>),
+    Vacant(VacantEntry<'map, K, V, S>),
+}
+
+/// The entry of a key which was found in the map. See [`MapEntry`].
+///
+/// Another thread may remove the key after it was found, after which every operation on the
+/// entry fails as it would for a missing key.
+pub struct OccupiedEntry<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    map: &'map HashMap<K, V, S>,
+    entry: &'map Atomic<Entry<V>>,
+    key_index: usize,
+}
+
+/// The place a key which wasn't found in the map would be inserted. See [`MapEntry`].
+pub struct VacantEntry<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    map: &'map HashMap<K, V, S>,
+    key: K,
+    key_hash: HashT,
+    /// Where the probe for the key stopped. Entries are never emptied again, so an insert can
+    /// carry on from here.
+    index: Size,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Find the entry for `key`, to read or update it if it is in the map, or insert it if it
+    /// isn't, without hashing or probing for the key again.
+    /// ```
+    /// use folklore::MapEntry;
+    ///
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(8);
+    /// for _ in 0..3 {
+    ///     match map.entry(1) {
+    ///         MapEntry::Occupied(entry) => {
+    ///             entry.fetch_update(|count| Some(count + 1));
+    ///         }
+    ///         MapEntry::Vacant(entry) => entry.insert(1).unwrap(),
+    ///     }
+    /// }
+    /// assert_eq!(map.get(&1), Some(3));
+    /// ```
+    pub fn entry(&self, key: K) -> MapEntry<'_, K, V, S> {
+        let (key_hash, home) = self._hash_and_index(&key);
+        loop {
+            return match self._probe_settled::<K>(&key, key_hash, home) {
+                Probe::Found(entry) => {
+                    inject::fail(inject::Point::EntryFound);
+                    let current = entry.load(Ordering::Acquire);
+                    // The key may have been removed since it was found, leaving a tombstone which
+                    // the next probe reports as removed.
+                    if !current.is_live() {
+                        continue;
+                    }
+                    MapEntry::Occupied(OccupiedEntry {
+                        map: self,
+                        entry,
+                        key_index: key_index(current.key_offset),
+                    })
+                }
+                // Inserting takes back the tombstone the key left at `index`.
+                Probe::Vacant(_, index) | Probe::Removed(_, index) => {
+                    MapEntry::Vacant(VacantEntry {
+                        map: self,
+                        key,
+                        key_hash,
+                        index,
+                    })
+                }
+                // Inserting will probe again and fail the same way.
+                Probe::LimitExceeded => MapEntry::Vacant(VacantEntry {
+                    map: self,
+                    key,
+                    key_hash,
+                    index: home,
+                }),
+            };
+        }
+    }
+}
+
+impl<K, V, S> MapEntry<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// The key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            MapEntry::Occupied(entry) => entry.key(),
+            MapEntry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<'map, K, V, S> OccupiedEntry<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// The key as stored in the map.
+    #[must_use]
+    pub fn key(&self) -> &'map K {
+        // SAFETY: The key was found in the table, so it was published, and keys are only removed
+        // from the key store through `&mut` access to the map.
+        unsafe { self.map._stored_key(self.key_index) }
+    }
+
+    /// The key's current value. Returns None if the key has been removed.
+    #[must_use]
+    pub fn get(&self) -> Option<V> {
+        let entry = self.entry.load(Ordering::Acquire);
+        entry.is_live().then_some(entry.value)
+    }
+
+    /// Replace the key's value. Returns the previous value, or None if the key has been removed.
+    pub fn update(&self, value: V) -> Option<V> {
+        self.fetch_update(|_| Some(value))
+    }
+
+    /// Update the key's value with `f`, as for [`HashMap::fetch_update`]. Returns the previous
+    /// value, or None if `f` returned None or the key has been removed.
+    pub fn fetch_update<F>(&self, mut f: F) -> Option<V>
+    where
+        F: FnMut(V) -> Option<V>,
+    {
+        self.map
+            ._update_entry(self.entry, |current| {
+                Some(Entry {
+                    value: f(current.value)?,
+                    ..current
+                })
+            })
+            .map(|previous| previous.value)
+    }
+
+    /// Remove the key from the map, as for [`HashMap::remove`]. Returns its value, or None if it
+    /// has already been removed.
+    #[must_use]
+    pub fn remove(self) -> Option<V> {
+        self.map._remove_entry(self.entry)
+    }
+}
+
+impl<K, V, S> VacantEntry<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// The key which would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Take back the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Insert the key with `value`, carrying on from where the probe for it stopped.
+    /// # Errors
+    /// As for [`HashMap::try_insert`]. [`Error::DuplicateKey`] means another thread inserted the
+    /// key since it wasn't found.
+    pub fn insert(self, value: V) -> Result<(), Error> {
+        self.map
+            ._insert_at(self.key, self.key_hash, self.index, |_| value, true)
+            .map(|_| ())
+            .map_err(InsertFailure::into_error)
+    }
+}
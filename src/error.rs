@@ -0,0 +1,109 @@
+use core::fmt;
+
+/// The error type for fallible operations on a [`HashMap`](crate::HashMap).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The requested capacity is larger than a map can hold (`i16::MAX`).
+    CapacityTooLarge,
+    /// The table or key store could not be allocated.
+    AllocationFailed,
+    /// The map already holds as many entries as its capacity allows.
+    Full,
+    /// The key already exists in the map.
+    DuplicateKey,
+    /// The key store has no free slots left. Slots can be used up by inserts which lost a race
+    /// with another thread, so this can happen before the map is [`Full`](Error::Full).
+    KeyStoreFull,
+    /// A snapshot or serialized map could not be read or written.
+    InvalidSnapshot,
+    /// The key's entry would be further from where its probe starts than the map's probe limit
+    /// allows. See [`HashMap::set_probe_limit`](crate::HashMap::set_probe_limit).
+    ProbeLimitExceeded,
+    /// The key isn't in the map.
+    KeyNotFound,
+    /// A [`PhfBuilder`](crate::PhfBuilder) found no layout which gives every key a slot of its
+    /// own.
+    LayoutNotFound,
+    /// The CPU has no lock-free 128-bit compare-and-swap, which a `WideHashMap` needs.
+    NotLockFree,
+    /// Another thread changed one of the keys in a group while
+    /// [`HashMap::update_atomic`](crate::HashMap::update_atomic) was writing it, so the group was
+    /// put back.
+    Conflict,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::CapacityTooLarge => "capacity is larger than the maximum supported capacity",
+            Error::AllocationFailed => "failed to allocate the map",
+            Error::Full => "the map is full",
+            Error::DuplicateKey => "the key already exists in the map",
+            Error::KeyStoreFull => "the key store is full",
+            Error::InvalidSnapshot => "invalid snapshot",
+            Error::ProbeLimitExceeded => "the probe limit was exceeded",
+            Error::KeyNotFound => "the key isn't in the map",
+            Error::LayoutNotFound => "no collision-free layout was found for the keys",
+            Error::NotLockFree => "128-bit compare-and-swap isn't lock-free on this CPU",
+            Error::Conflict => "another thread changed a key in the group",
+        })
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The error returned when a batch fails as a whole, such as
+/// [`HashMap::insert_all_or_nothing`](crate::HashMap::insert_all_or_nothing).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchError {
+    /// The position in the batch of the item which failed, or None if the batch as a whole
+    /// didn't fit.
+    pub index: Option<usize>,
+    /// Why the batch failed.
+    pub error: Error,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{} (batch item {index})", self.error),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The error returned by [`HashMap::try_insert_returning`](crate::HashMap::try_insert_returning),
+/// which hands back the key and value that weren't inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertError<K, V> {
+    /// Why the insert failed: [`Error::DuplicateKey`], [`Error::Full`], [`Error::KeyStoreFull`]
+    /// or [`Error::ProbeLimitExceeded`].
+    pub error: Error,
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> fmt::Display for InsertError<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> core::error::Error for InsertError<K, V> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<K, V> From<InsertError<K, V>> for Error {
+    fn from(error: InsertError<K, V>) -> Self {
+        error.error
+    }
+}
@@ -0,0 +1,300 @@
+use crate::raw::{RawProbe, RawTable};
+use crate::{
+    allocated_size, constants, hashing, DefaultHashBuilder, Entry, Error, KeyOffset, Size,
+};
+use atomic::Ordering;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::sync::atomic::AtomicU16;
+
+/// Resolves the indexes stored in an [`ExternalKeyMap`] to the keys they stand for, for callers
+/// which already own a table of keys, such as a string interner or a component registry.
+pub trait Resolve {
+    type Key: Hash + Eq + ?Sized;
+
+    /// The key at `index`, or None if there is no key there. Once a key is inserted into a map,
+    /// its index must resolve to the same key for as long as the map is used.
+    fn resolve(&self, index: usize) -> Option<&Self::Key>;
+}
+
+impl<K: Hash + Eq> Resolve for [K] {
+    type Key = K;
+
+    fn resolve(&self, index: usize) -> Option<&K> {
+        self.get(index)
+    }
+}
+
+impl<K: Hash + Eq> Resolve for alloc::vec::Vec<K> {
+    type Key = K;
+
+    fn resolve(&self, index: usize) -> Option<&K> {
+        self.get(index)
+    }
+}
+
+impl<R: Resolve + ?Sized> Resolve for &R {
+    type Key = R::Key;
+
+    fn resolve(&self, index: usize) -> Option<&Self::Key> {
+        (**self).resolve(index)
+    }
+}
+
+/// A map whose table stores the hash of each key and an index which stands for it, with no key
+/// store. Keys are compared by resolving indexes through the map's [`Resolve`]r, so a caller
+/// with its own table of keys doesn't store them twice.
+///
+/// Entries are the same single word as [`HashMap`](crate::HashMap)'s, and are inserted and updated
+/// with the same lock-free operations.
+/// ```
+/// use folklore::ExternalKeyMap;
+///
+/// let names = vec!["alice".to_string(), "bob".to_string()];
+/// let ages = ExternalKeyMap::<_, u16>::with_capacity(8, &names);
+/// assert!(ages.insert(1, 42));
+/// assert_eq!(ages.get("bob"), Some(42));
+/// assert_eq!(ages.find_index("bob"), Some(1));
+/// ```
+pub struct ExternalKeyMap<R, V, S = DefaultHashBuilder>
+where
+    R: Resolve,
+    V: Copy + NoUninit,
+{
+    table: RawTable<Entry<V>>,
+    capacity: Size,
+    count: AtomicU16,
+    resolver: R,
+    hash_builder: S,
+}
+
+impl<R, V> ExternalKeyMap<R, V, DefaultHashBuilder>
+where
+    R: Resolve,
+    V: Copy + NoUninit,
+{
+    /// Create a map with the given capacity, which resolves indexes with `resolver`.
+    /// # Panics
+    /// If `capacity > i16::MAX`, or the allocation fails.
+    #[must_use]
+    pub fn with_capacity(capacity: usize, resolver: R) -> Self {
+        Self::try_with_capacity_and_hasher(capacity, resolver, DefaultHashBuilder::default())
+            .expect("failed to create map")
+    }
+}
+
+impl<R, V, S> ExternalKeyMap<R, V, S>
+where
+    R: Resolve,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Create a map with the given capacity, which resolves indexes with `resolver` and hashes
+    /// keys with `hash_builder`.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity_and_hasher(
+        capacity: usize,
+        resolver: R,
+        hash_builder: S,
+    ) -> Result<Self, Error> {
+        let Ok(capacity) = i16::try_from(capacity).map(i16::unsigned_abs) else {
+            return Err(Error::CapacityTooLarge);
+        };
+        let table = RawTable::try_new(allocated_size(usize::from(capacity)))
+            .ok_or(Error::AllocationFailed)?;
+        Ok(Self {
+            table,
+            capacity,
+            count: AtomicU16::new(0),
+            resolver,
+            hash_builder,
+        })
+    }
+
+    /// Insert the key at `index` in the resolver, with `value`.
+    /// Returns true if the key was inserted, false if it couldn't be. See
+    /// [`try_insert`](Self::try_insert).
+    pub fn insert(&self, index: usize, value: V) -> bool {
+        self.try_insert(index, value).is_ok()
+    }
+
+    /// Insert the key at `index` in the resolver, with `value`, returning the reason on failure.
+    /// # Errors
+    /// - [`Error::KeyNotFound`] if `index` doesn't resolve to a key, or is too large to store in
+    ///   an entry (`u16::MAX - 1` or more).
+    /// - [`Error::DuplicateKey`] if the key already exists, at this or any other index.
+    /// - [`Error::Full`] if the map already holds `capacity` entries.
+    pub fn try_insert(&self, index: usize, value: V) -> Result<(), Error> {
+        let key_offset = Size::try_from(index)
+            .ok()
+            .and_then(|index| index.checked_add(constants::MIN_KEY))
+            .filter(|&offset| KeyOffset(offset) != constants::TOMBSTONE)
+            .ok_or(Error::KeyNotFound)?;
+        let key = self.resolver.resolve(index).ok_or(Error::KeyNotFound)?;
+        let key_hash = self._hash(key);
+        let mut start = self.table.index_for(key_hash);
+        let mut reserved = false;
+        loop {
+            let (slot, slot_index) = match self._probe(key, key_hash, start) {
+                RawProbe::Vacant(slot, slot_index) => (slot, slot_index),
+                found => {
+                    if reserved {
+                        self.count.fetch_sub(1, Ordering::Relaxed);
+                    }
+                    return Err(match found {
+                        RawProbe::Found(..) => Error::DuplicateKey,
+                        _ => Error::Full,
+                    });
+                }
+            };
+            if !reserved {
+                self.count
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                        (count < self.capacity).then_some(count + 1)
+                    })
+                    .map_err(|_| Error::Full)?;
+                reserved = true;
+            }
+            let entry = Entry {
+                key_hash,
+                key_offset: KeyOffset(key_offset),
+                value,
+            };
+            // If another thread fills the entry first, carry on from it, in case it inserted the
+            // same key.
+            if slot
+                .compare_exchange(Entry::EMPTY, entry, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(());
+            }
+            start = slot_index;
+        }
+    }
+
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).map(|(entry, _)| entry.value)
+    }
+
+    /// Get the index the key was inserted with. Returns None if the key doesn't exist.
+    pub fn find_index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).map(|(_, index)| index)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).is_some()
+    }
+
+    /// Update the value associated with a key. Returns the previous value, or None if the key
+    /// doesn't exist.
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let key_hash = self._hash(key);
+        let RawProbe::Found(slot, _) = self._probe(key, key_hash, self.table.index_for(key_hash))
+        else {
+            return None;
+        };
+        slot.fetch_update(Ordering::AcqRel, Ordering::Acquire, |entry| {
+            Some(Entry { value, ..entry })
+        })
+        .ok()
+        .map(|previous| previous.value)
+    }
+
+    /// Iterate over the indexes and values in the map, in table order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, V)> + '_ {
+        self.table.iter().filter_map(|entry| {
+            let entry = entry.load(Ordering::Acquire);
+            entry
+                .is_live()
+                .then(|| (crate::key_index(entry.key_offset), entry.value))
+        })
+    }
+
+    /// The resolver which keys are looked up through.
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Get the count of key-value pairs in the map.
+    pub fn len(&self) -> usize {
+        usize::from(self.count.load(Ordering::Relaxed))
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most key-value pairs the map can hold.
+    pub fn capacity(&self) -> usize {
+        usize::from(self.capacity)
+    }
+
+    fn _find<Q>(&self, key: &Q) -> Option<(Entry<V>, usize)>
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let key_hash = self._hash(key);
+        match self._probe(key, key_hash, self.table.index_for(key_hash)) {
+            RawProbe::Found(slot, _) => {
+                let entry = slot.load(Ordering::Acquire);
+                Some((entry, crate::key_index(entry.key_offset)))
+            }
+            _ => None,
+        }
+    }
+
+    fn _probe<Q>(&self, key: &Q, key_hash: u32, start: Size) -> RawProbe<'_, Entry<V>>
+    where
+        R::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.table.probe(start, |entry| {
+            entry.key_hash == key_hash
+                && self
+                    .resolver
+                    .resolve(crate::key_index(entry.key_offset))
+                    .is_some_and(|stored| stored.borrow() == key)
+        })
+    }
+
+    fn _hash<Q: Hash + ?Sized>(&self, key: &Q) -> u32 {
+        hashing::hash(key, &self.hash_builder)
+    }
+}
+
+// The table holds only hashes, indexes and values, so the map can be shared if the resolver can.
+unsafe impl<R, V, S> Send for ExternalKeyMap<R, V, S>
+where
+    R: Resolve + Send,
+    V: Copy + NoUninit + Send,
+    S: Send,
+{
+}
+unsafe impl<R, V, S> Sync for ExternalKeyMap<R, V, S>
+where
+    R: Resolve + Sync,
+    V: Copy + NoUninit + Send + Sync,
+    S: Sync,
+{
+}
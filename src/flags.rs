@@ -0,0 +1,74 @@
+use crate::HashMap;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// The number of user flag bits stored alongside each value.
+/// Flags are stored in the top bits of the entry's key hash, so they are read and written
+/// atomically with the value.
+pub const FLAG_BITS: u32 = 4;
+
+const FLAGS_MASK: u8 = (1 << FLAG_BITS) - 1;
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Get the flags associated with a key. Returns None if the key doesn't exist.
+    /// Flags are zero when a key is inserted.
+    pub fn get_flags<Q>(&self, key: &Q) -> Option<u8>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with_flags(key).map(|(_, flags)| flags)
+    }
+
+    /// Get the value and flags associated with a key, read together. Returns None if the key
+    /// doesn't exist.
+    pub fn get_with_flags<Q>(&self, key: &Q) -> Option<(V, u8)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find_entry(key)
+            .map(|entry| entry.load(atomic::Ordering::Relaxed))
+            .map(|entry| (entry.value, entry.flags()))
+    }
+
+    /// Set the flags associated with a key, leaving its value unchanged. Returns the previous
+    /// flags, or None if the key doesn't exist.
+    /// # Panics
+    /// If `flags` has any bits set above the lowest [`FLAG_BITS`].
+    pub fn set_flags<Q>(&self, key: &Q, flags: u8) -> Option<u8>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.fetch_update_with_flags(key, |value, _| Some((value, flags)))
+            .map(|(_, previous)| previous)
+    }
+
+    /// Update the value and flags associated with a key together, using an update function.
+    /// Returns the previous value and flags on success, or None on failure.
+    /// The update function should return the new value and flags, or None if they should not be
+    /// updated. It may be called more than once if the entry is concurrently modified.
+    /// # Panics
+    /// If the update function returns flags with any bits set above the lowest [`FLAG_BITS`].
+    pub fn fetch_update_with_flags<Q, F>(&self, key: &Q, mut f: F) -> Option<(V, u8)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(V, u8) -> Option<(V, u8)>,
+    {
+        self._fetch_update(key, |current| {
+            f(current.value, current.flags()).map(|(value, flags)| {
+                assert!(flags & !FLAGS_MASK == 0, "flags out of range: {flags:#x}");
+                crate::Entry { value, ..current }.with_flags(flags)
+            })
+        })
+        .map(|previous| (previous.value, previous.flags()))
+    }
+}
@@ -0,0 +1,160 @@
+use crate::raw::next_index;
+use crate::{constants, hashing, key_index, util, Entry, HashMap, HashT, KeyOffset, Size};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use atomic::Ordering;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// A read-only copy of a map, returned by [`HashMap::freeze`], for maps which are built by many
+/// threads and then only read.
+///
+/// The table is a plain array rather than atomics, so lookups are ordinary loads, and entries
+/// don't need the 8-byte alignment a single-word CAS requires. The keys are stored densely, in
+/// insertion order. Lookups probe the same sequence as the map they were frozen from.
+pub struct FrozenHashMap<K, V, S> {
+    entries: Box<[FrozenEntry<V>]>,
+    keys: Vec<K>,
+    size_mask: Size,
+    hash_builder: S,
+}
+
+#[derive(Clone, Copy)]
+struct FrozenEntry<V> {
+    /// The hash of the key, with the user flags in the top bits.
+    key_hash: HashT,
+    key_offset: KeyOffset,
+    value: V,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Consume the map, and turn it into a [`FrozenHashMap`] which can only be read. Values and
+    /// flags are kept, and nothing is rehashed.
+    #[must_use]
+    pub fn freeze(mut self) -> FrozenHashMap<K, V, S> {
+        // Drop the keys no entry refers to, so every key's index is its position in `keys`.
+        self.compact_key_store();
+        let size_mask = Size::try_from(self.table.slots() - 1).unwrap_or(Size::MAX);
+        let entries = self
+            .table
+            .iter()
+            .map(|entry| {
+                let entry = entry.load(Ordering::Relaxed);
+                FrozenEntry {
+                    key_hash: entry.key_hash,
+                    // Nothing can commit a pending entry now, so it is as good as rolled back.
+                    key_offset: if entry.is_pending() {
+                        constants::TOMBSTONE
+                    } else {
+                        entry.key_offset
+                    },
+                    value: entry.value,
+                }
+            })
+            .collect();
+        let HashMap {
+            key_store,
+            hash_builder,
+            ..
+        } = self;
+        FrozenHashMap {
+            entries,
+            keys: key_store
+                .into_vec()
+                .into_iter()
+                .map(|stored| stored.key)
+                .collect(),
+            size_mask,
+            hash_builder,
+        }
+    }
+}
+
+impl<K, V, S> FrozenHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy,
+    S: BuildHasher,
+{
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).map(|entry| entry.value)
+    }
+
+    /// Get the value and flags associated with a key. Returns None if the key doesn't exist.
+    pub fn get_with_flags<Q>(&self, key: &Q) -> Option<(V, u8)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).map(|entry| {
+            let flags = Entry {
+                key_hash: entry.key_hash,
+                key_offset: entry.key_offset,
+                value: entry.value,
+            }
+            .flags();
+            (entry.value, flags)
+        })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).is_some()
+    }
+
+    /// Iterate over the key-value pairs, in the order the keys were inserted.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_ {
+        self.keys
+            .iter()
+            .filter_map(|key| Some((key, self.get(key)?)))
+    }
+
+    /// Get the count of key-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn _find<Q>(&self, key: &Q) -> Option<&FrozenEntry<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let key_hash = hashing::hash(key, &self.hash_builder);
+        #[allow(clippy::cast_possible_truncation)]
+        let mut index = util::wrap(key_hash as usize, usize::from(self.size_mask)) as Size;
+        // The table always has an empty entry, but don't rely on it.
+        for _ in 0..self.entries.len() {
+            let entry = &self.entries[usize::from(index)];
+            if entry.key_offset == constants::EMPTY_KEY {
+                return None;
+            }
+            if entry.key_offset != constants::TOMBSTONE
+                && entry.key_hash & constants::HASH_MASK == key_hash
+                && self.keys[key_index(entry.key_offset)].borrow() == key
+            {
+                return Some(entry);
+            }
+            index = next_index(index, self.size_mask);
+        }
+        None
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{DefaultHashBuilder, HashMap};
+use bytemuck::NoUninit;
+use core::cell::UnsafeCell;
+use core::hash::Hash;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+mod state {
+    /// The map hasn't been initialised.
+    pub const UNINIT: u8 = 0;
+    /// A thread is initialising the map.
+    pub const INITIALISING: u8 = 1;
+    /// The map has been initialised, and can be read by any thread.
+    pub const READY: u8 = 2;
+}
+
+/// A map which is initialised once, and can then be shared from a `static`, without `once_cell`
+/// or any unsafe code.
+///
+/// ```
+/// static MAP: folklore::Global<u32, u16> = folklore::Global::new();
+///
+/// let map = MAP.get_or_init(|| folklore::HashMap::with_capacity(16));
+/// assert!(map.insert(1, 1));
+/// assert_eq!(MAP.get().unwrap().get(&1), Some(1));
+/// ```
+pub struct Global<K, V, S = DefaultHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    state: AtomicU8,
+    map: UnsafeCell<MaybeUninit<HashMap<K, V, S>>>,
+}
+
+impl<K, V, S> Global<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// Create an uninitialised `Global`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(state::UNINIT),
+            map: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Get the map, or None if it hasn't been initialised yet.
+    pub fn get(&self) -> Option<&HashMap<K, V, S>> {
+        (self.state.load(Ordering::Acquire) == state::READY)
+            .then(|| unsafe { (*self.map.get()).assume_init_ref() })
+    }
+
+    /// Initialise the global with `map`.
+    /// # Errors
+    /// Returns `map` if the global was already initialised, or another thread is initialising it.
+    // The map is handed back as it was given, like `OnceCell::set`.
+    #[allow(clippy::result_large_err)]
+    pub fn init(&self, map: HashMap<K, V, S>) -> Result<&HashMap<K, V, S>, HashMap<K, V, S>> {
+        if self
+            .state
+            .compare_exchange(
+                state::UNINIT,
+                state::INITIALISING,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return Err(map);
+        }
+        Ok(self.write(map))
+    }
+
+    /// Get the map, initialising it with the map returned by `f` if it hasn't been initialised.
+    /// If another thread is initialising the map, waits for it to finish.
+    pub fn get_or_init<F>(&self, f: F) -> &HashMap<K, V, S>
+    where
+        F: FnOnce() -> HashMap<K, V, S>,
+    {
+        loop {
+            match self.state.compare_exchange_weak(
+                state::UNINIT,
+                state::INITIALISING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(state::READY) => return unsafe { (*self.map.get()).assume_init_ref() },
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+        // If `f` panics, let another thread try to initialise the map.
+        let guard = ResetOnUnwind(&self.state);
+        let map = f();
+        core::mem::forget(guard);
+        self.write(map)
+    }
+
+    /// Write the map and mark it as ready. The caller must have moved the state to
+    /// `INITIALISING`.
+    fn write(&self, map: HashMap<K, V, S>) -> &HashMap<K, V, S> {
+        let map = unsafe { (*self.map.get()).write(map) };
+        self.state.store(state::READY, Ordering::Release);
+        map
+    }
+}
+
+impl<K, V, S> Default for Global<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> Drop for Global<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    fn drop(&mut self) {
+        if *self.state.get_mut() == state::READY {
+            unsafe { self.map.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+// The map is only ever shared once it is initialised, and may be initialised by any thread.
+unsafe impl<K, V, S> Sync for Global<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    HashMap<K, V, S>: Send + Sync,
+{
+}
+
+struct ResetOnUnwind<'a>(&'a AtomicU8);
+
+impl Drop for ResetOnUnwind<'_> {
+    fn drop(&mut self) {
+        self.0.store(state::UNINIT, Ordering::Release);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{Entry, Error, HashMap};
+use alloc::vec::Vec;
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::sync::atomic::{fence, AtomicU32};
+
+/// A sequence lock for updating groups of entries together. The sequence is odd while a group is
+/// being written, and each write moves it on by two, so a reader which sees the same even
+/// sequence before and after its loads saw no group half written.
+#[derive(Default)]
+pub struct GroupLock {
+    sequence: AtomicU32,
+}
+
+/// Ends the write of a group when dropped, even if the update function panics.
+struct Writing<'lock> {
+    sequence: &'lock AtomicU32,
+    start: u32,
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        self.sequence
+            .store(self.start.wrapping_add(2), Ordering::Release);
+    }
+}
+
+impl GroupLock {
+    fn write(&self) -> Writing<'_> {
+        loop {
+            let start = self.sequence.load(Ordering::Relaxed);
+            if start.is_multiple_of(2)
+                && self
+                    .sequence
+                    .compare_exchange_weak(start, start + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // Order the group's writes after the odd sequence.
+                fence(Ordering::Release);
+                return Writing {
+                    sequence: &self.sequence,
+                    start,
+                };
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn read<T>(&self, mut load: impl FnMut() -> T) -> T {
+        loop {
+            let start = self.sequence.load(Ordering::Acquire);
+            if start.is_multiple_of(2) {
+                let loaded = load();
+                fence(Ordering::Acquire);
+                if self.sequence.load(Ordering::Relaxed) == start {
+                    return loaded;
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Update the values of several keys together, so that [`get_atomic`](Self::get_atomic) never
+    /// sees the group half written, though [`get`](Self::get) and other single-key reads can.
+    /// `f` is passed their current values, in the order of `keys`, and the values it leaves are
+    /// written back. Groups are applied one at a time, so a transfer between two keys can't be
+    /// torn.
+    ///
+    /// Groups take a lock which is shared by the whole map, and are meant to be brief. Other
+    /// operations don't take it, so each value is only written back if its entry hasn't changed
+    /// since it was read. If one has, the values already written are put back and the group
+    /// fails, to be retried by the caller. A key changed in the moment between the group writing
+    /// it and failing keeps that change, made on top of the group's value. The keys should be
+    /// distinct; a repeated key takes the last of its values.
+    /// ```
+    /// let balances = folklore::HashMap::<&str, u16>::with_capacity(8);
+    /// balances.insert("alice", 10);
+    /// balances.insert("bob", 0);
+    /// balances.update_atomic(&["alice", "bob"], |values| {
+    ///     values[0] -= 4;
+    ///     values[1] += 4;
+    /// }).unwrap();
+    /// assert_eq!(balances.get_atomic(&["alice", "bob"]), [Some(6), Some(4)]);
+    /// ```
+    /// # Errors
+    /// In either case nothing is updated.
+    /// - [`Error::KeyNotFound`] if any of the keys doesn't exist, or one was removed or moved away
+    ///   by [`rekey`](Self::rekey) before the group was written.
+    /// - [`Error::Conflict`] if another thread changed one of the keys while the group was
+    ///   written.
+    pub fn update_atomic<Q, F>(&self, keys: &[&Q], f: F) -> Result<(), Error>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&mut [V]),
+    {
+        let entries: Vec<&Atomic<Entry<V>>> = keys
+            .iter()
+            .map(|key| self._find_entry(*key).ok_or(Error::KeyNotFound))
+            .collect::<Result<_, _>>()?;
+        let _writing = self.groups.write();
+        let read: Vec<Entry<V>> = entries
+            .iter()
+            .map(|entry| entry.load(Ordering::Acquire))
+            .collect();
+        if !read.iter().all(Entry::is_live) {
+            return Err(Error::KeyNotFound);
+        }
+        let mut values: Vec<V> = read.iter().map(|entry| entry.value).collect();
+        f(&mut values);
+
+        // The index and new value of each entry written, so it can be put back.
+        let mut written: Vec<(usize, Entry<V>)> = Vec::with_capacity(entries.len());
+        for (i, &entry) in entries.iter().enumerate() {
+            // Only the last copy of a repeated key is written.
+            if entries[i + 1..]
+                .iter()
+                .any(|&later| core::ptr::eq(later, entry))
+            {
+                continue;
+            }
+            let new = Entry {
+                value: values[i],
+                ..read[i]
+            };
+            if let Err(changed) =
+                entry.compare_exchange(read[i], new, Ordering::Release, Ordering::Acquire)
+            {
+                for &(j, new) in written.iter().rev() {
+                    let _ = entries[j].compare_exchange(
+                        new,
+                        read[j],
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                }
+                return Err(if changed.is_live() {
+                    Error::Conflict
+                } else {
+                    Error::KeyNotFound
+                });
+            }
+            written.push((i, new));
+        }
+        for (i, _) in written {
+            self._touch(crate::key_index(read[i].key_offset));
+        }
+        Ok(())
+    }
+
+    /// Get the values of several keys together, as of a moment when no group from
+    /// [`update_atomic`](Self::update_atomic) was half written. Each value is None if its key
+    /// doesn't exist.
+    pub fn get_atomic<Q>(&self, keys: &[&Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entries: Vec<_> = keys.iter().map(|key| self._find_entry(*key)).collect();
+        self.groups.read(|| {
+            entries
+                .iter()
+                .map(|entry| {
+                    let entry = (*entry)?.load(Ordering::Relaxed);
+                    entry.is_live().then_some(entry.value)
+                })
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,424 @@
+use crate::reclaim::{NeverFree, Reclaim};
+use crate::{constants, DefaultHashBuilder, Entry, Error, HashMap, Probe, Size};
+use alloc::boxed::Box;
+use atomic::Atomic;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+
+/// The number of table entries a thread claims at a time while moving entries to a larger table.
+const MIGRATION_CHUNK: usize = 64;
+
+/// A map which moves to a table twice the size when it fills up, rather than failing inserts, so
+/// its capacity doesn't need to be known up front. The capacity is still limited to `i16::MAX`.
+///
+/// The move is incremental and cooperative: the insert which finds the table full allocates the
+/// larger one, and every insert which arrives during the move helps, by claiming chunks of the
+/// old table and moving their entries one at a time. Inserts wait for the move to finish before
+/// inserting into the new table. Reads, updates and removes never wait for the move, and see each
+/// key in exactly one of the two tables.
+///
+/// Old tables are handed to `R` once no new operation can reach them. The default,
+/// [`NeverFree`], leaks them, which costs less than the final table, since each is half the size
+/// of the next. `Epoch`, with the `epoch` feature, frees them.
+///
+/// ```
+/// let map = folklore::GrowableHashMap::<u32, u16>::with_capacity(4);
+/// for i in 0..100 {
+///     assert!(map.insert(i, 1));
+/// }
+/// assert_eq!(map.len(), 100);
+/// assert!(map.capacity() >= 100);
+/// ```
+pub struct GrowableHashMap<K, V, S = DefaultHashBuilder, R = NeverFree>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    R: Reclaim,
+{
+    /// The table inserts go to. Tables before it have been retired.
+    current: AtomicPtr<Generation<K, V, S>>,
+    reclaim: R,
+    /// The map owns its tables, so it is only `Send` and `Sync` if they are.
+    tables: PhantomData<Box<Generation<K, V, S>>>,
+}
+
+/// One of the tables of a [`GrowableHashMap`], and the state of moving its entries to the next.
+struct Generation<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    map: HashMap<K, V, S>,
+    /// The larger table this one's entries are being moved to, once this one has filled up.
+    next: AtomicPtr<Generation<K, V, S>>,
+    /// The inserts in progress on `map`. Moving entries waits for these to finish.
+    writers: AtomicUsize,
+    /// The first table index no thread has claimed to move yet.
+    claimed: AtomicUsize,
+    /// The number of table indexes whose entries have been moved.
+    moved: AtomicUsize,
+}
+
+impl<K, V, S> Generation<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    fn new(map: HashMap<K, V, S>) -> Self {
+        Self {
+            map,
+            next: AtomicPtr::new(core::ptr::null_mut()),
+            writers: AtomicUsize::new(0),
+            claimed: AtomicUsize::new(0),
+            moved: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<K, V> GrowableHashMap<K, V, DefaultHashBuilder, NeverFree>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// Create a map whose first table has the given capacity.
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_hasher_and_reclaim(capacity, DefaultHashBuilder::default(), NeverFree)
+    }
+}
+
+impl<K, V, S, R> GrowableHashMap<K, V, S, R>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+    R: Reclaim,
+{
+    /// Create a map whose first table has the given capacity, which hashes keys with
+    /// `hash_builder` (cloned into each larger table), and retires old tables to `reclaim`.
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    pub fn with_capacity_hasher_and_reclaim(capacity: usize, hash_builder: S, reclaim: R) -> Self {
+        let map = HashMap::with_capacity_and_hasher(capacity, hash_builder);
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(Generation::new(map)))),
+            reclaim,
+            tables: PhantomData,
+        }
+    }
+
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._search(key, |_, entry| {
+            let entry = entry.load(Ordering::Acquire);
+            entry.is_live().then_some(entry.value)
+        })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Update the value associated with a key. Returns the previous value, or None if the key
+    /// doesn't exist.
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.fetch_update(key, |_| Some(value))
+    }
+
+    /// Update the value associated with a key with `f`, as for [`HashMap::fetch_update`].
+    /// Returns the previous value, or None if the key doesn't exist or `f` returned None.
+    pub fn fetch_update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(V) -> Option<V>,
+    {
+        self._search(key, |map, entry| {
+            match map._update_entry(entry, |current| {
+                Some(Entry {
+                    value: f(current.value)?,
+                    ..current
+                })
+            }) {
+                Some(previous) => Some(Some(previous.value)),
+                // Unless the key was removed or moved to the next table, `f` returned None.
+                None => entry.load(Ordering::Acquire).is_live().then_some(None),
+            }
+        })
+        .flatten()
+    }
+
+    /// Remove a key from the map, returning its value, or None if it doesn't exist. The removal
+    /// leaves a tombstone, which is dropped when the entries are next moved to a larger table.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._search(key, HashMap::_remove_entry)
+    }
+
+    /// Get the count of key-value pairs in the map. While entries are being moved to a larger
+    /// table, a key which is part way through moving may be counted twice.
+    pub fn len(&self) -> usize {
+        let guard = self.reclaim.pin();
+        let mut generation = self._current(&guard);
+        let mut len = generation.map.len();
+        while let Some(next) = Self::_next(generation) {
+            generation = next;
+            len += generation.map.len();
+        }
+        len
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of key-value pairs the current table can hold before the map grows again.
+    pub fn capacity(&self) -> usize {
+        let guard = self.reclaim.pin();
+        let generation = self._current(&guard);
+        Self::_next(generation).unwrap_or(generation).map.capacity()
+    }
+
+    /// Find `key`, starting at the current table, and call `op` with the table and entry it is
+    /// found in. If `op` returns None, the entry was removed or moved before `op` could use it,
+    /// and the search carries on in the next table.
+    fn _search<Q, T, F>(&self, key: &Q, mut op: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(&HashMap<K, V, S>, &Atomic<Entry<V>>) -> Option<T>,
+    {
+        let guard = self.reclaim.pin();
+        let mut generation = self._current(&guard);
+        loop {
+            let map = &generation.map;
+            let (key_hash, index) = map._hash_and_index(key);
+            // Waits for a key which is being moved into this table to be committed.
+            if let Probe::Found(entry) = map._probe_settled(key, key_hash, index) {
+                if let Some(result) = op(map, entry) {
+                    return Some(result);
+                }
+            }
+            // A key is placed in the next table before it is removed from this one, so if it was
+            // missing because it had moved, the next table is visible, and has it.
+            fence(Ordering::Acquire);
+            generation = Self::_next(generation)?;
+        }
+    }
+
+    fn _current<'guard>(&'guard self, _guard: &'guard R::Guard) -> &'guard Generation<K, V, S> {
+        // SAFETY: The current table is only retired once it is no longer current, and retired
+        // tables aren't freed while the guard is held.
+        unsafe { &*self.current.load(Ordering::Acquire) }
+    }
+
+    fn _next(generation: &Generation<K, V, S>) -> Option<&Generation<K, V, S>> {
+        // SAFETY: The next table becomes current before `generation` is retired, so it is retired
+        // after `generation`, and lives at least as long.
+        unsafe { generation.next.load(Ordering::Acquire).as_ref() }
+    }
+}
+
+impl<K, V, S, R> GrowableHashMap<K, V, S, R>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Copy + NoUninit + Send + 'static,
+    S: BuildHasher + Clone + Send + 'static,
+    R: Reclaim,
+{
+    /// Insert a key-value pair into the map, growing it if it is full.
+    /// Returns true if the key was inserted, false if the key already exists or the map can't grow.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, growing it if it is full, and returning the reason
+    /// on failure.
+    /// # Errors
+    /// - [`Error::DuplicateKey`] if the key already exists.
+    /// - [`Error::Full`] if the map already holds `i16::MAX` entries.
+    /// - [`Error::AllocationFailed`] if a larger table can't be allocated.
+    // An insert into a full table would drop the key, so it is cloned into the table which takes
+    // it instead, but taken by value to match `HashMap::try_insert`.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), Error> {
+        let guard = self.reclaim.pin();
+        loop {
+            let generation = self._current(&guard);
+            // Pairs with the swap of `next` in `_grow`: either the migration sees this insert as
+            // a writer and waits for it, or this insert sees the migration and doesn't start.
+            generation.writers.fetch_add(1, Ordering::SeqCst);
+            let result = generation
+                .next
+                .load(Ordering::SeqCst)
+                .is_null()
+                .then(|| generation.map.try_insert_ref(&key, value));
+            generation.writers.fetch_sub(1, Ordering::Release);
+            match result {
+                Some(Err(Error::Full | Error::KeyStoreFull | Error::ProbeLimitExceeded)) => {
+                    Self::_grow(generation)?;
+                }
+                Some(result) => return result,
+                None => {}
+            }
+            self._migrate(generation, &guard);
+        }
+    }
+
+    /// Allocate the table `generation` moves to, unless another thread already has.
+    fn _grow(generation: &Generation<K, V, S>) -> Result<(), Error> {
+        if !generation.next.load(Ordering::Acquire).is_null() {
+            return Ok(());
+        }
+        let map = &generation.map;
+        let capacity = (map.capacity().max(1) * 2).min(i16::MAX as usize);
+        // A full table of tombstones can still move to one of the same size.
+        if map.len() >= capacity {
+            return Err(Error::Full);
+        }
+        let next = HashMap::try_with_capacity_and_hasher(capacity, map.hash_builder.clone())?;
+        let next = Box::into_raw(Box::new(Generation::new(next)));
+        if generation
+            .next
+            .compare_exchange(
+                core::ptr::null_mut(),
+                next,
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // SAFETY: The table was never shared.
+            drop(unsafe { Box::from_raw(next) });
+        }
+        Ok(())
+    }
+
+    /// Help move the entries of `generation` to its next table, then make the next table current.
+    /// Returns once every entry has been moved, by this thread or others.
+    fn _migrate(&self, generation: &Generation<K, V, S>, guard: &R::Guard) {
+        let next = generation.next.load(Ordering::SeqCst);
+        // SAFETY: `next` was set before this is called, and is never unset.
+        let to = unsafe { &(*next).map };
+        // Inserts which started before `next` was set may still be writing to the table.
+        while generation.writers.load(Ordering::SeqCst) != 0 {
+            core::hint::spin_loop();
+        }
+        let slots = generation.map.table.slots();
+        loop {
+            let start = generation
+                .claimed
+                .fetch_add(MIGRATION_CHUNK, Ordering::Relaxed);
+            if start >= slots {
+                break;
+            }
+            let end = (start + MIGRATION_CHUNK).min(slots);
+            for index in start..end {
+                // A table has at most `1 << 16` entries, so every index fits.
+                #[allow(clippy::cast_possible_truncation)]
+                move_entry(&generation.map, index as Size, to);
+            }
+            generation.moved.fetch_add(end - start, Ordering::Release);
+        }
+        while generation.moved.load(Ordering::Acquire) < slots {
+            core::hint::spin_loop();
+        }
+        let old = core::ptr::from_ref(generation).cast_mut();
+        if self
+            .current
+            .compare_exchange(old, next, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            // SAFETY: The old table came from `Box::into_raw`, and is no longer current, so only
+            // threads which pinned before this can reach it. Only the thread which replaced it
+            // retires it.
+            unsafe { self.reclaim.retire(guard, old) };
+        }
+    }
+}
+
+/// Move the entry at `index` in `from`, if it holds a key, to `to`.
+///
+/// The key is first placed in `to` as pending, which other threads can't see, then removed from
+/// `from`, and then committed with its value at the time of removal. Until it is removed, it is
+/// only found in `from`, and afterwards only in `to`. If another thread removes it first, the
+/// pending entry is rolled back.
+fn move_entry<K, V, S>(from: &HashMap<K, V, S>, index: Size, to: &HashMap<K, V, S>)
+where
+    K: Hash + Eq + Clone,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    let entry = from.table.entry(index);
+    let current = entry.load(Ordering::Acquire);
+    if !current.is_live() {
+        return;
+    }
+    // SAFETY: The entry was loaded with `Acquire`.
+    let key = unsafe { from._entry_key(current.key_offset) };
+    let key_hash = to._hash(key);
+    // `to` has room for every key in `from`, and nothing else is inserted into it until every key
+    // has been moved.
+    let placed = to
+        ._reserve_count(1)
+        .and_then(|()| to._place_pending(key, key_hash, current.value, &mut 1));
+    let Ok((placed, key_index)) = placed else {
+        unreachable!("the next table has no room for a key being moved to it")
+    };
+    if let Some(value) = from._remove_entry(entry) {
+        let pending = placed.load(Ordering::Relaxed);
+        placed.store(
+            Entry {
+                key_hash: pending.key_hash & !constants::PENDING,
+                value,
+                ..pending
+            },
+            Ordering::Release,
+        );
+        to._touch(key_index);
+        to.key_store.publish(key_index);
+    } else {
+        to._roll_back(&[(placed, key_index)], 0);
+    }
+}
+
+impl<K, V, S, R> Drop for GrowableHashMap<K, V, S, R>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    R: Reclaim,
+{
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        // SAFETY: The map owns its current table, and the one being moved to if there is one.
+        // Older tables were retired.
+        unsafe {
+            let mut current = Box::from_raw(current);
+            let next = *current.next.get_mut();
+            if !next.is_null() {
+                drop(Box::from_raw(next));
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! The hashing scheme, which decides where each key is placed in a map's table.
+//!
+//! Code which keeps a table's layout beyond the life of one process depends on this scheme:
+//! snapshots, a table attached in shared memory, or code across an FFI boundary which probes the
+//! table itself. The scheme is identified by [`SCHEME_VERSION`]. Any change to the steps below
+//! changes the version, and is only made in a breaking release.
+//!
+//! # Version 1
+//! 1. The key is hashed with the map's [`BuildHasher`], through [`BuildHasher::hash_one`].
+//! 2. The 64-bit result is folded to 32 bits, as `(hash ^ (hash >> 32)) as u32`. Hashers which
+//!    only produce 32 bits are left unchanged.
+//! 3. The folded hash is masked to its low [`HASH_BITS`] bits. This is the hash stored in the
+//!    key's entry, whose top bits hold the entry's flags.
+//! 4. A map with capacity `c` has [`table_slots(c)`](table_slots) entries: `c / 0.6` rounded
+//!    up to a power of two, and never fewer than one bucket.
+//! 5. A key's probe starts at [`home_index`], the low bits of its hash, and moves forward one
+//!    entry at a time, wrapping around at the end of the table.
+//!
+//! # Seeds
+//! A layout can only be shared between processes if its hasher gives the same hashes in each.
+//! [`SeededHashBuilder`](crate::SeededHashBuilder) does: it is 32-bit FNV-1a, starting from the
+//! standard offset basis, over the seed's 8 bytes in native byte order and then the key. The
+//! default hasher without `std` is the same FNV-1a with no seed. The default hasher with `std` is
+//! seeded randomly for every map, so its layouts are never stable.
+//!
+//! The scheme covers what the map does with the bytes a key's [`Hash`] implementation writes,
+//! not those bytes themselves, which are up to the key type. Native byte order also means that
+//! layouts only carry between targets of the same endianness.
+
+use crate::{allocated_size, constants};
+use core::hash::{BuildHasher, Hash};
+
+/// The version of the hashing scheme described in the [module docs](self).
+pub const SCHEME_VERSION: u32 = 1;
+
+/// The number of bits of each hash which are kept, and which decide a key's place in the table.
+pub const HASH_BITS: u32 = constants::HASH_MASK.count_ones();
+
+/// Hash `key` as a map with `hash_builder` does, returning the hash stored in its entry.
+/// ```
+/// use folklore::{hashing, HashMap, SeededHashBuilder};
+///
+/// let hash_builder = SeededHashBuilder::new(7);
+/// let hash = hashing::hash(&42_u64, &hash_builder);
+/// assert!(hash < 1 << hashing::HASH_BITS);
+/// ```
+pub fn hash<Q, S>(key: &Q, hash_builder: &S) -> u32
+where
+    Q: Hash + ?Sized,
+    S: BuildHasher,
+{
+    let hash = hash_builder.hash_one(key);
+    #[allow(clippy::cast_possible_truncation)]
+    let folded = (hash ^ (hash >> 32)) as u32;
+    folded & constants::HASH_MASK
+}
+
+/// The number of entries in the table of a map created with `capacity`.
+#[must_use]
+pub const fn table_slots(capacity: usize) -> usize {
+    allocated_size(capacity)
+}
+
+/// The index at which the probe for a key with `hash` starts, in a table of `slots` entries.
+/// `slots` is always a power of two.
+#[must_use]
+pub const fn home_index(hash: u32, slots: usize) -> usize {
+    crate::util::wrap(hash as usize, slots - 1)
+}
@@ -0,0 +1,98 @@
+//! Failure injection, for driving the insert protocol down branches which are normally only taken
+//! when threads race.
+//!
+//! Faults are set per thread, so tests running in parallel don't see each other's faults.
+
+/// A place in the insert protocol where a fault can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Point {
+    /// Pushing a key to the key store. Failing it acts as if the key store were full.
+    KeyStorePush,
+    /// Writing an insert's entry into the empty entry it found. Failing it acts as if another
+    /// thread had filled the entry first, so the insert probes again.
+    InsertCas,
+    /// Reading the entry [`HashMap::entry`](crate::HashMap::entry) found for a key. Only
+    /// [`Fault::Delay`] has an effect, giving another thread time to remove the key.
+    EntryFound,
+}
+
+#[cfg(feature = "test-util")]
+pub use faults::{clear, set, Fault};
+
+#[cfg(feature = "test-util")]
+mod faults {
+    use super::Point;
+    use core::cell::RefCell;
+    use std::thread_local;
+    use std::time::Duration;
+    use std::vec::Vec;
+
+    /// What happens when a thread reaches a [`Point`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Fault {
+        /// The next `times` attempts fail.
+        Fail { times: u32 },
+        /// Every attempt sleeps first, widening the window for other threads to race it.
+        Delay(Duration),
+    }
+
+    thread_local! {
+        static FAULTS: RefCell<Vec<(Point, Fault)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Inject `fault` at `point` for the current thread, replacing any fault already there.
+    /// ```
+    /// use folklore::inject::{self, Fault, Point};
+    ///
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(8);
+    /// inject::set(Point::KeyStorePush, Fault::Fail { times: 1 });
+    /// assert_eq!(map.try_insert(1, 1), Err(folklore::Error::KeyStoreFull));
+    /// assert_eq!(map.try_insert(1, 1), Ok(()));
+    /// ```
+    pub fn set(point: Point, fault: Fault) {
+        FAULTS.with_borrow_mut(|faults| {
+            faults.retain(|&(set, _)| set != point);
+            faults.push((point, fault));
+        });
+    }
+
+    /// Remove every fault injected for the current thread.
+    pub fn clear() {
+        FAULTS.with_borrow_mut(Vec::clear);
+    }
+
+    /// Apply any fault injected at `point`. Returns true if the attempt should fail.
+    pub(crate) fn fail(point: Point) -> bool {
+        let fault = FAULTS.with_borrow_mut(|faults| {
+            let position = faults.iter().position(|&(set, _)| set == point)?;
+            let fault = faults[position].1;
+            match &mut faults[position].1 {
+                Fault::Fail { times: 0 | 1 } => {
+                    faults.swap_remove(position);
+                }
+                Fault::Fail { times } => *times -= 1,
+                Fault::Delay(_) => {}
+            }
+            Some(fault)
+        });
+        match fault {
+            Some(Fault::Fail { times }) => times > 0,
+            Some(Fault::Delay(duration)) => {
+                std::thread::sleep(duration);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(feature = "test-util")]
+pub(crate) use faults::fail;
+
+/// Faults can only be injected with the `test-util` feature, so this compiles away.
+#[cfg(not(feature = "test-util"))]
+#[inline]
+pub(crate) fn fail(_point: Point) -> bool {
+    false
+}
@@ -0,0 +1,98 @@
+use crate::{Error, HashMap, InsertFailure, Size};
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// A handle for inserting many keys from one thread, which updates the map's shared count once per
+/// batch rather than once per insert. Created by [`HashMap::inserter`].
+///
+/// The handle reserves room for a batch of keys up front, and uses it up locally as keys are
+/// inserted. Unused room is returned by [`flush`](Self::flush), or when the handle is dropped.
+/// Until then, [`len`](HashMap::len) counts the room the handle holds as if it were used, and
+/// inserts from other threads may fail with [`Error::Full`] while it is held.
+pub struct Inserter<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    map: &'map HashMap<K, V, S>,
+    batch: Size,
+    /// The number of entries counted by the map which this handle hasn't inserted yet.
+    reserved: Size,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Create a handle which inserts into the map, reserving room for `batch` keys at a time.
+    /// This takes the shared count off the path of most inserts, which matters when many threads
+    /// insert at once. A `batch` of zero is treated as one.
+    pub fn inserter(&self, batch: usize) -> Inserter<'_, K, V, S> {
+        Inserter {
+            map: self,
+            batch: Size::try_from(batch).unwrap_or(Size::MAX).max(1),
+            reserved: 0,
+        }
+    }
+}
+
+impl<'map, K, V, S> Inserter<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// The map which is being inserted into.
+    #[must_use]
+    pub fn map(&self) -> &'map HashMap<K, V, S> {
+        self.map
+    }
+
+    /// Insert a key-value pair into the map. See [`HashMap::insert`].
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, returning the reason on failure.
+    /// # Errors
+    /// See [`HashMap::try_insert`]. [`Error::Full`] is returned once the handle can't reserve
+    /// room for even one more key.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), Error> {
+        if self.reserved == 0 {
+            // If a whole batch doesn't fit, there may still be room for this key.
+            self.map
+                ._reserve_count(self.batch)
+                .map(|()| self.reserved = self.batch)
+                .or_else(|_| self.map._reserve_count(1).map(|()| self.reserved = 1))?;
+        }
+        let key_hash = self.map._hash(&key);
+        self.map
+            ._insert_reserving(key, key_hash, |_| value, false)
+            .map_err(InsertFailure::into_error)?;
+        self.reserved -= 1;
+        Ok(())
+    }
+
+    /// Return the room reserved for keys which haven't been inserted, so that
+    /// [`len`](HashMap::len) is exact again.
+    pub fn flush(&mut self) {
+        if self.reserved > 0 {
+            self.map._release_count(self.reserved);
+            self.reserved = 0;
+        }
+    }
+}
+
+impl<K, V, S> Drop for Inserter<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,53 @@
+use crate::HashMap;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+use serde::{Serialize, Serializer};
+use std::string::String;
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq + Serialize,
+    V: Copy + NoUninit + Serialize,
+    S: BuildHasher,
+{
+    /// Render the map as a JSON object on one line, with keys in the order they were inserted,
+    /// for logs and bug reports. Entries inserted or updated concurrently may or may not be
+    /// included, as for [`iter`](Self::iter).
+    /// ```
+    /// let map = folklore::HashMap::<&str, u16>::with_capacity(8);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// assert_eq!(map.to_json_string().unwrap(), r#"{"a":1,"b":2}"#);
+    /// ```
+    /// # Errors
+    /// If a key doesn't serialize to a JSON string or number, since JSON object keys must be
+    /// strings, or if a key or value fails to serialize.
+    pub fn to_json_string(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&Json(self))
+    }
+
+    /// Render the map as an indented JSON object. See [`to_json_string`](Self::to_json_string).
+    /// # Errors
+    /// See [`to_json_string`](Self::to_json_string).
+    pub fn to_json_string_pretty(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(&Json(self))
+    }
+}
+
+/// Serializes a map as a JSON object, without committing the map itself to a serde
+/// representation.
+struct Json<'map, K, V, S>(&'map HashMap<K, V, S>)
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit;
+
+impl<K, V, S> Serialize for Json<'_, K, V, S>
+where
+    K: Hash + Eq + Serialize,
+    V: Copy + NoUninit + Serialize,
+    S: BuildHasher,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serializer.collect_map(self.0.iter())
+    }
+}
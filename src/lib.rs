@@ -1,43 +1,192 @@
 #![no_std]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 mod array;
+#[cfg(feature = "async")]
+mod available;
+mod batch;
+mod bits;
+mod compact;
+mod concurrent_map;
+mod cursor;
+mod encode;
+mod entry;
+mod error;
+mod external;
+mod flags;
+mod frozen;
+mod global;
+mod group;
+mod growable;
+pub mod hashing;
+#[cfg(feature = "test-util")]
+pub mod inject;
+#[cfg(not(feature = "test-util"))]
+mod inject;
+mod inserter;
+#[cfg(feature = "json")]
+mod json;
+mod log;
+mod phf;
+mod pinned;
+mod pod_key;
+pub mod raw;
+pub mod reclaim;
+mod rehash;
+mod rekey;
+mod reverse_index;
+mod rom;
+#[cfg(feature = "rand")]
+mod sample;
+mod seeded;
+mod set;
+mod set_ops;
+mod sharded;
+mod small_string;
+mod snapshot;
+mod stats;
+#[cfg(feature = "test-util")]
+pub mod stress;
 #[cfg(test)]
 mod test;
+#[cfg(feature = "timestamps")]
+mod timestamps;
+mod transaction;
 mod util;
+#[cfg(kani)]
+mod verification;
+mod watch;
+#[cfg(feature = "wide")]
+mod wide;
 
 use crate::array::ConcurrentArray;
+#[cfg(feature = "async")]
+pub use crate::available::InsertWhenAvailable;
+pub use crate::bits::{Bits, ToBits};
+pub use crate::concurrent_map::ConcurrentMap;
+pub use crate::cursor::Cursor;
+pub use crate::encode::LeBytes;
+pub use crate::entry::{MapEntry, OccupiedEntry, VacantEntry};
+pub use crate::error::{BatchError, Error, InsertError};
+pub use crate::external::{ExternalKeyMap, Resolve};
+pub use crate::flags::FLAG_BITS;
+pub use crate::frozen::FrozenHashMap;
+pub use crate::global::Global;
+pub use crate::growable::GrowableHashMap;
+pub use crate::inserter::Inserter;
+pub use crate::log::{Logged, WriteLogger};
+pub use crate::phf::{PhfBuilder, PhfMap};
+pub use crate::pinned::{MapRef, ValueRef};
+pub use crate::pod_key::PodKey;
+use crate::raw::{RawEntry, RawProbe, RawTable, BUCKET_CAPACITY};
+pub use crate::reverse_index::ReverseIndex;
+pub use crate::rom::{RomBuilder, RomMap};
+pub use crate::seeded::SeededHashBuilder;
+pub use crate::set::HashSet;
+pub use crate::sharded::ShardedHashMap;
+pub use crate::small_string::SmallString;
+pub use crate::snapshot::{Change, Checkpoint};
+#[cfg(feature = "raw-iter")]
+pub use crate::stats::RawSlot;
+pub use crate::stats::{KeyStoreStats, Occupancy};
+pub use crate::transaction::Transaction;
+pub use crate::watch::Watcher;
+#[cfg(feature = "wide")]
+pub use crate::wide::WideHashMap;
+use alloc::borrow::{Cow, ToOwned};
 use atomic::{Atomic, Ordering};
 use bytemuck::NoUninit;
 use core::borrow::Borrow;
-use core::hash::Hash;
-use core::mem::size_of;
-use core::ptr::slice_from_raw_parts;
-use core::sync::atomic::AtomicU16;
-use hash32::FnvHasher;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::sync::atomic::{fence, AtomicBool, AtomicU16, AtomicU8};
+
+/// The hasher used by maps which aren't given one.
+/// Without the `std` feature this is a deterministic FNV hasher.
+#[cfg(not(feature = "std"))]
+pub type DefaultHashBuilder = core::hash::BuildHasherDefault<hash32::FnvHasher>;
+/// The hasher used by maps which aren't given one.
+/// With the `std` feature every map is seeded randomly, like `std::collections::HashMap`, to
+/// resist collision attacks.
+#[cfg(feature = "std")]
+pub type DefaultHashBuilder = std::collections::hash_map::RandomState;
 
 type Size = u16;
 type HashT = u32;
 
+// Every entry is updated with a single 64-bit CAS, and the largest table (`1 << 16` slots)
+// doesn't fit in a 16-bit address space.
+#[cfg(not(target_has_atomic = "64"))]
+compile_error!("folklore needs a target with 64-bit atomics");
+#[cfg(target_pointer_width = "16")]
+compile_error!("folklore needs a target with 32-bit or 64-bit pointers");
+
 const DEFAULT_CAPACITY: usize = 64;
-const BUCKET_CAPACITY: Size = 8;
 const LOAD_FACTOR: f64 = 0.6;
 
-/// A `HashMap` which doesn't allow any deletion, and only allows for 2-byte values
-pub struct HashMap<K, V>
+/// A `HashMap` which only allows for 2-byte values, and whose removals leave tombstones until
+/// the removed keys are inserted again, or the tombstones are [purged](Self::purge_tombstones)
+///
+/// # Large keys
+/// Every slot of the key store is as large as `K`, including slots used up by inserts which
+/// failed. For keys of hundreds of bytes, store them boxed instead: `Box<K>` hashes and compares
+/// like `K`, and borrows as one, so a `HashMap<Box<K>, V>` is still queried with a `&K`, while
+/// each slot holds only a pointer.
+/// ```
+/// let map = folklore::HashMap::<Box<[u8; 512]>, u16>::with_capacity(16);
+/// map.insert(Box::new([1; 512]), 1);
+/// assert_eq!(map.get(&[1; 512]), Some(1));
+/// ```
+///
+/// # Thread safety
+/// The map is only `Send` and `Sync` if its keys are, so it can't be used to share keys like
+/// `Rc<str>` between threads.
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<folklore::HashMap<std::rc::Rc<str>, u16>>();
+/// ```
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<folklore::HashMap<std::rc::Rc<str>, u16>>();
+/// ```
+pub struct HashMap<K, V, S = DefaultHashBuilder>
 where
     K: Hash + Eq,
     V: Copy + NoUninit,
 {
-    table: *mut Bucket<V>,
-    key_store: ConcurrentArray<K>,
-    size_mask: Size,
+    table: RawTable<Entry<V>>,
+    key_store: ConcurrentArray<HashedKey<K>>,
     capacity: Size,
     count: AtomicU16,
+    /// The number of entries which were removed, rolled back or rekeyed, and left as tombstones.
+    /// Tombstones still occupy the table, so they count towards the capacity.
+    tombstones: AtomicU16,
+    /// The most entries a probe visits, set by [`set_probe_limit`](Self::set_probe_limit).
+    /// `Size::MAX` if there is no limit, other than the size of the table.
+    probe_limit: Size,
+    /// The occupancy set by [`set_watermark`](Self::set_watermark). `Size::MAX` if there is none.
+    watermark: Size,
+    /// Set by the first insert to find the map at or over its watermark.
+    watermark_crossed: AtomicBool,
+    /// Stamped on each [`Checkpoint`], which can only be restored while the stamp matches.
+    /// Replaced whenever the map is changed in a way which would make restoring an earlier
+    /// checkpoint unsafe. See [`snapshot::next_epoch`].
+    checkpoint_epoch: u64,
+    hash_builder: S,
+    #[cfg(feature = "timestamps")]
+    timestamps: timestamps::Timestamps,
+    /// Held while a group from [`update_atomic`](Self::update_atomic) is written.
+    groups: group::GroupLock,
+    /// Tasks waiting in [`insert_when_available`](Self::insert_when_available).
+    #[cfg(feature = "async")]
+    waiters: available::Waiters,
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V> HashMap<K, V, DefaultHashBuilder>
 where
     K: Hash + Eq,
     V: Copy + NoUninit,
@@ -47,90 +196,379 @@ where
     /// If `capacity > i16::MAX`
     #[must_use]
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+
+    /// Create a map with the given capacity, returning an error instead of panicking if the
+    /// capacity is too large or the allocation fails.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        Self::try_with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// The number of bytes a map with the given capacity allocates on the heap, for its table, its
+    /// key store, and the key store's slot states, plus each key's tick with the `timestamps`
+    /// feature. Accounts for the load factor, and for rounding the table and key store up to a
+    /// power of two.
+    ///
+    /// This is a `const fn`, so it can be used to check a memory budget at compile time.
+    /// ```
+    /// const BYTES: usize = folklore::HashMap::<u32, u16>::table_bytes_for(100);
+    /// const _: () = assert!(BYTES <= 4096);
+    /// ```
+    #[must_use]
+    pub const fn table_bytes_for(capacity: usize) -> usize {
+        let table = raw::table_bytes::<Entry<V>>(allocated_size(capacity));
+        let key_store =
+            key_store_capacity(capacity) * (size_of::<HashedKey<K>>() + size_of::<AtomicU8>());
+        #[cfg(feature = "timestamps")]
+        let key_store =
+            key_store + key_store_capacity(capacity) * size_of::<core::sync::atomic::AtomicU32>();
+        table + key_store
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Create a map with the default capacity, which uses `hash_builder` to hash keys. Any
+    /// [`BuildHasher`] can be used, such as a keyed hasher, or a faster one for trusted keys.
+    /// ```
+    /// use core::hash::BuildHasherDefault;
+    ///
+    /// type Fnv = BuildHasherDefault<hash32::FnvHasher>;
+    /// let map = folklore::HashMap::<u32, u16, Fnv>::with_hasher(Fnv::default());
+    /// assert!(map.insert(1, 1));
+    /// ```
+    #[must_use]
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(DEFAULT_CAPACITY, hash_builder)
+    }
+
+    /// Create a map with the given capacity, which uses `hash_builder` to hash keys.
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    #[must_use]
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        // Panic if capacity > i16::MAX
+        assert!(i16::try_from(capacity).is_ok());
+        match Self::try_with_capacity_and_hasher(capacity, hash_builder) {
+            Ok(map) => map,
+            Err(_) => alloc::alloc::handle_alloc_error(RawTable::<Entry<V>>::layout(
+                allocated_size(capacity),
+            )),
+        }
+    }
+
+    /// Create a map with the given capacity, which uses `hash_builder` to hash keys, returning an
+    /// error instead of panicking if the capacity is too large or the allocation fails.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Result<Self, Error> {
+        Self::try_with_capacity_and_hasher_aligned(capacity, hash_builder, align_of::<Entry<V>>())
+    }
+
+    /// Create a map like [`try_with_capacity_and_hasher`](Self::try_with_capacity_and_hasher),
+    /// whose table is aligned to at least `table_align` bytes and padded to a multiple of it.
+    /// With a page-sized alignment (usually 4096), the table occupies whole pages, so it can be
+    /// mapped, shared or registered with a DMA engine without copying. Clones of the map keep the
+    /// alignment.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`] if
+    /// `table_align` isn't a power of two or the allocation fails.
+    pub fn try_with_capacity_and_hasher_aligned(
+        capacity: usize,
+        hash_builder: S,
+        table_align: usize,
+    ) -> Result<Self, Error> {
         // This assertion is only ran at compile time
         generic_asserts!((V);
             VALUE_SIZE: size_of::<V>() == size_of::<Size>();
             ONE_WORD: size_of::<Entry<V>>() == size_of::<u64>();
         );
-        // Panic if capacity > i16::MAX
-        assert!(i16::try_from(capacity).is_ok());
-        // The allocated size of the table is larger than the capacity to allow for LOAD_FACTOR,
-        // which improves performance. This also means there will always be empty entrys, which
-        // means the unconditional loops in get/insert will never be infinite.
-        #[allow(clippy::cast_sign_loss)]
-        #[allow(clippy::cast_possible_truncation)]
-        #[allow(clippy::cast_precision_loss)]
-        let allocated_size = ((capacity as f64 / LOAD_FACTOR) as usize).next_power_of_two();
+        let Ok(max_count) = i16::try_from(capacity).map(i16::unsigned_abs) else {
+            return Err(Error::CapacityTooLarge);
+        };
+        let allocated_size = allocated_size(capacity);
         // Ensure the highest possible offset won't overflow
-        debug_assert!(allocated_size - 1 <= Size::MAX as usize);
+        debug_assert!(allocated_size - 1 <= usize::from(Size::MAX));
 
-        #[allow(clippy::cast_possible_truncation)]
-        Self {
-            table: create_table(allocated_size),
-            key_store: ConcurrentArray::new(capacity.next_power_of_two()),
-            size_mask: (allocated_size - 1) as Size,
-            capacity: capacity as Size,
+        let key_store = ConcurrentArray::try_new(key_store_capacity(capacity))
+            .ok_or(Error::AllocationFailed)?;
+        #[cfg(feature = "timestamps")]
+        let timestamps = timestamps::Timestamps::try_new(key_store_capacity(capacity))
+            .ok_or(Error::AllocationFailed)?;
+        let table = RawTable::try_new_aligned(allocated_size, table_align)
+            .ok_or(Error::AllocationFailed)?;
+
+        Ok(Self {
+            table,
+            key_store,
+            capacity: max_count,
             count: AtomicU16::new(0),
-        }
+            tombstones: AtomicU16::new(0),
+            probe_limit: Size::MAX,
+            watermark: Size::MAX,
+            watermark_crossed: AtomicBool::new(false),
+            checkpoint_epoch: snapshot::next_epoch(),
+            hash_builder,
+            #[cfg(feature = "timestamps")]
+            timestamps,
+            groups: group::GroupLock::default(),
+            #[cfg(feature = "async")]
+            waiters: available::Waiters::default(),
+        })
     }
 
     /// Insert a key-value pair into the map.
     /// Returns true if the key was inserted, false if the map is full or the key already exists.
     pub fn insert(&self, key: K, value: V) -> bool {
-        if self.count.load(Ordering::Relaxed) >= self.capacity {
-            return false;
-        }
+        self.try_insert(key, value).is_ok()
+    }
 
-        let Some(entry) = self._find_empty_entry(&key) else {
-            return false;
-        };
+    /// Insert a key into the map, with the value returned by `f`.
+    /// `f` is only called once the key is known not to be in the map, so an expensive value
+    /// isn't computed for a duplicate key.
+    /// Returns true if the key was inserted, false if the map is full or the key already exists.
+    pub fn insert_with<F>(&self, key: K, f: F) -> bool
+    where
+        F: FnOnce() -> V,
+    {
+        self._insert_with(key, |_| f()).is_ok()
+    }
 
-        let key_hash = self._hash(&key);
+    /// Insert a key-value pair into the map, returning the reason on failure.
+    /// # Errors
+    /// - [`Error::DuplicateKey`] if the key already exists.
+    /// - [`Error::Full`] if the map already holds `capacity` entries.
+    /// - [`Error::KeyStoreFull`] if there is no room left to store the key.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), Error> {
+        self._insert_with(key, |_| value)
+            .map(|_| ())
+            .map_err(InsertFailure::into_error)
+    }
 
-        let Some((_, key_index)) = self.key_store.push(key) else {
-            return false;
-        };
+    /// Insert a key-value pair into the map, cloning the key only if it is inserted. This saves
+    /// a clone when most inserts are of keys which are already in the map.
+    /// Returns true if the key was inserted, false if the map is full or the key already exists.
+    pub fn insert_ref(&self, key: &K, value: V) -> bool
+    where
+        K: Clone,
+    {
+        self.try_insert_ref(key, value).is_ok()
+    }
 
-        let key_offset = key_index as Size + constants::MIN_KEY;
+    /// Insert a key-value pair into the map, cloning the key only if it is inserted, and
+    /// returning the reason on failure.
+    /// # Errors
+    /// See [`try_insert`](Self::try_insert).
+    pub fn try_insert_ref(&self, key: &K, value: V) -> Result<(), Error>
+    where
+        K: Clone,
+    {
+        self._insert_with(key, |_| value)
+            .map(|_| ())
+            .map_err(InsertFailure::into_error)
+    }
 
-        if entry
-            .compare_exchange(
-                Entry::EMPTY,
-                Entry {
-                    key_hash,
-                    key_offset,
-                    value,
+    /// Insert a key-value pair into the map, handing the pair back on failure, so the caller can
+    /// react to the reason and still has the key, to retry it or put it elsewhere. The key is
+    /// cloned into the map if it is inserted, as for [`try_insert_ref`](Self::try_insert_ref).
+    /// ```
+    /// use folklore::Error;
+    ///
+    /// let map = folklore::HashMap::<String, u16>::with_capacity(1);
+    /// map.try_insert_returning("a".to_string(), 1).unwrap();
+    /// let failed = map.try_insert_returning("b".to_string(), 2).unwrap_err();
+    /// assert_eq!(failed.error, Error::Full);
+    /// assert_eq!(failed.key, "b");
+    /// ```
+    /// # Errors
+    /// An [`InsertError`] with the key and value, and the reason, as for
+    /// [`try_insert`](Self::try_insert).
+    pub fn try_insert_returning(&self, key: K, value: V) -> Result<(), InsertError<K, V>>
+    where
+        K: Clone,
+    {
+        match self._insert_with(&key, |_| value) {
+            Ok(_) => Ok(()),
+            Err(failure) => Err(InsertError {
+                error: failure.into_error(),
+                key,
+                value,
+            }),
+        }
+    }
+
+    /// Insert a key-value pair into the map, where the key may be borrowed. A borrowed key is
+    /// only turned into an owned one (such as a `&str` into a `String`) if it is inserted.
+    /// Returns true if the key was inserted, false if the map is full or the key already exists.
+    /// ```
+    /// use std::borrow::Cow;
+    ///
+    /// let map = folklore::HashMap::<String, u16>::with_capacity(8);
+    /// assert!(map.insert_cow(Cow::Borrowed("a"), 1));
+    /// assert!(!map.insert_cow(Cow::Borrowed("a"), 2));
+    /// assert!(map.insert_cow(Cow::<str>::Owned("b".to_string()), 2));
+    /// ```
+    pub fn insert_cow<Q>(&self, key: Cow<'_, Q>, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.try_insert_cow(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, where the key may be borrowed, returning the reason
+    /// on failure. See [`insert_cow`](Self::insert_cow).
+    /// # Errors
+    /// See [`try_insert`](Self::try_insert).
+    pub fn try_insert_cow<Q>(&self, key: Cow<'_, Q>, value: V) -> Result<(), Error>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        match key {
+            Cow::Borrowed(key) => self._insert_with(
+                BorrowedKey {
+                    key,
+                    owned: PhantomData,
                 },
-                Ordering::Release,
-                Ordering::Acquire,
-            )
-            .is_ok()
-        {
-            self.count.fetch_add(1, Ordering::Relaxed);
-            true
-        } else {
-            // Attempt to remove the key which we no longer need.
-            // May fail if another key was inserted since we added it.
-            self.key_store.remove(key_index);
-            false
+                |_| value,
+            ),
+            Cow::Owned(key) => {
+                let key_hash = self._hash::<K>(&key);
+                self._insert_hashed(key, key_hash, |_| value)
+            }
+        }
+        .map(|_| ())
+        .map_err(InsertFailure::into_error)
+    }
+
+    /// Get the value associated with a key, inserting the value returned by `f` if the key
+    /// doesn't exist. `f` is only called if the key isn't already in the map.
+    /// # Errors
+    /// [`Error::Full`] or [`Error::KeyStoreFull`] if the key doesn't exist and can't be inserted.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Error>
+    where
+        F: FnOnce() -> V,
+    {
+        self.get_or_insert_with_key(key, |_| f())
+    }
+
+    /// Get the value associated with a key, inserting the value returned by `f` if the key
+    /// doesn't exist. `f` is passed the key which is about to be inserted, and is only called if
+    /// the key isn't already in the map.
+    /// # Errors
+    /// [`Error::Full`] or [`Error::KeyStoreFull`] if the key doesn't exist and can't be inserted.
+    pub fn get_or_insert_with_key<F>(&self, key: K, f: F) -> Result<V, Error>
+    where
+        F: FnOnce(&K) -> V,
+    {
+        match self._insert_with(key, f) {
+            Ok((value, _)) => Ok(value),
+            Err(InsertFailure::Exists(entry)) => Ok(entry.load(Ordering::Acquire).value),
+            Err(InsertFailure::Error(error)) => Err(error),
+        }
+    }
+
+    /// Insert a key-value pair, or overwrite the key's value if it is already in the map, with
+    /// one probe of the table. Returns the value which was replaced, or None if the key was
+    /// inserted.
+    ///
+    /// The key is kept until the insert or update succeeds, in case the key is removed by another
+    /// thread between being found and being updated, and is cloned into the map if it is inserted.
+    /// ```
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(8);
+    /// assert_eq!(map.insert_or_update(1, 10), Ok(None));
+    /// assert_eq!(map.insert_or_update(1, 11), Ok(Some(10)));
+    /// assert_eq!(map.get(&1), Some(11));
+    /// ```
+    /// # Errors
+    /// [`Error::Full`] or [`Error::KeyStoreFull`] if the key doesn't exist and can't be inserted.
+    // The key is borrowed for every attempt, and cloned by the one which inserts it.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn insert_or_update(&self, key: K, value: V) -> Result<Option<V>, Error>
+    where
+        K: Clone,
+    {
+        loop {
+            match self._insert_with(&key, |_| value) {
+                Ok(_) => return Ok(None),
+                Err(InsertFailure::Exists(entry)) => {
+                    let replaced =
+                        self._update_entry(entry, |current| Some(Entry { value, ..current }));
+                    if let Some(previous) = replaced {
+                        return Ok(Some(previous.value));
+                    }
+                    // The key was removed since it was found, so try to insert it again.
+                }
+                Err(InsertFailure::Error(error)) => return Err(error),
+            }
         }
     }
 
     /// Get the value associated with a key. Returns None if the key doesn't exist.
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<V>
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self._find_entry(key)
             .map(|e| e.load(Ordering::Relaxed))
             .map(|e| e.value)
     }
 
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    /// Get the value associated with a key, loading its entry with `order`. [`get`](Self::get)
+    /// loads with [`Relaxed`](Ordering::Relaxed); pass [`Acquire`](Ordering::Acquire) to pair
+    /// with a release store or fence made by the thread which wrote the value.
+    /// # Panics
+    /// If `order` is [`Release`](Ordering::Release) or [`AcqRel`](Ordering::AcqRel), as for an
+    /// atomic load.
+    pub fn get_with_ordering<Q>(&self, key: &Q, order: Ordering) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find_entry(key).map(|e| e.load(order).value)
+    }
+
+    /// Get the key as stored in the map, and its value, from a key in any borrowed form. Returns
+    /// None if the key doesn't exist.
+    /// ```
+    /// let map = folklore::HashMap::<String, u16>::with_capacity(8);
+    /// map.insert("a".to_string(), 1);
+    /// let (key, value) = map.get_key_value("a").unwrap();
+    /// assert_eq!((key.as_str(), value), ("a", 1));
+    /// ```
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self._find_entry(key)?.load(Ordering::Acquire);
+        // The key may have been removed since it was found, leaving no key offset to follow.
+        if !entry.is_live() {
+            return None;
+        }
+        // SAFETY: The entry was loaded with `Acquire`.
+        Some((unsafe { self._entry_key(entry.key_offset) }, entry.value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self._find_entry(key).is_some()
     }
@@ -138,19 +576,156 @@ where
     /// Get the key at the given index in the map's key store.
     /// Keys are stored in the order they were inserted.
     pub fn get_key(&self, index: usize) -> Option<&K> {
-        self.key_store.get(index)
+        self.key_store.get(index).map(|stored| &stored.key)
+    }
+
+    /// Find the position of a key's entry in the table, for reading it again with
+    /// [`get_by_index`](Self::get_by_index) or
+    /// [`get_unchecked_by_index`](Self::get_unchecked_by_index) without hashing or probing.
+    /// Entries never move, so the position stays valid for the life of the map.
+    pub fn find_index<Q>(&self, key: &Q) -> Option<EntryIndex>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key_hash, index) = self._hash_and_index(key);
+        match self._probe_raw(key, key_hash, index) {
+            RawProbe::Found(entry, index) if !entry.load(Ordering::Relaxed).is_pending() => {
+                Some(EntryIndex(index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the key and value of the entry at `index`, returned by
+    /// [`find_index`](Self::find_index). Returns None if `index` isn't the position of an entry
+    /// in this map.
+    pub fn get_by_index(&self, index: EntryIndex) -> Option<(&K, V)> {
+        if usize::from(index.0) >= self.table.slots() {
+            return None;
+        }
+        let entry = self.table.entry(index.0).load(Ordering::Acquire);
+        if !entry.is_live() {
+            return None;
+        }
+        let key = self.get_key(key_index(entry.key_offset))?;
+        Some((key, entry.value))
+    }
+
+    /// Get the key and value of the entry at `index`, without checking that it is an entry in
+    /// this map.
+    /// # Safety
+    /// `index` must have been returned by [`find_index`](Self::find_index) on this map, and its key
+    /// must not have been [removed](Self::remove) or [rekeyed](Self::rekey) since, nor the map
+    /// [restored](Self::restore), [purged](Self::purge_tombstones),
+    /// [compacted](Self::compact_key_store) or [cleared](Self::clear).
+    pub unsafe fn get_unchecked_by_index(&self, index: EntryIndex) -> (&K, V) {
+        let entry = self.table.entry_unchecked(index.0).load(Ordering::Acquire);
+        (self._entry_key(entry.key_offset), entry.value)
     }
 
     /// Get the count of key-value pairs in the map.
     pub fn len(&self) -> usize {
-        self.count.load(Ordering::Relaxed) as usize
+        usize::from(self.count.load(Ordering::Relaxed))
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every key-value pair, dropping the keys, so the map can be reused without
+    /// reallocating. The capacity, hasher, probe limit and watermark are kept, and the watermark is
+    /// re-armed, as by [`set_watermark`](Self::set_watermark). Earlier
+    /// [`Checkpoint`]s can't be restored.
+    /// ```
+    /// let mut map = folklore::HashMap::<u32, u16>::with_capacity(8);
+    /// map.insert(1, 1);
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// assert!(map.insert(1, 2));
+    /// assert_eq!(map.get(&1), Some(2));
+    /// ```
+    pub fn clear(&mut self) {
+        for entry in self.table.iter() {
+            entry.store(Entry::EMPTY, Ordering::Relaxed);
+        }
+        self.key_store.truncate(0);
+        self._invalidate_checkpoints();
+        *self.count.get_mut() = 0;
+        *self.tombstones.get_mut() = 0;
+        *self.watermark_crossed.get_mut() = false;
+    }
+
+    /// The hasher the map hashes keys with.
+    #[must_use]
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// The number of key-value pairs the map can hold.
+    pub fn capacity(&self) -> usize {
+        usize::from(self.capacity)
+    }
+
+    /// Returns true if `n` more inserts of keys which aren't in the map are guaranteed to succeed,
+    /// because both the map and its key store have room for them. Concurrent inserts from other
+    /// threads use up the same room, so the guarantee only holds while no other thread inserts.
+    /// Inserts can still fail if a [probe limit](Self::set_probe_limit) is set.
+    pub fn will_fit(&self, n: usize) -> bool {
+        let entries = usize::from(self.capacity) - self.len() - self._tombstones();
+        let keys = self.key_store.capacity() - self.key_store.len();
+        n <= entries.min(keys)
+    }
+
+    /// Hash a key exactly as the map does, with the map's hasher. The result only has the low
+    /// 27 bits set, because the rest of an entry's hash holds its flags and pending bit.
+    ///
+    /// Maps with equal hashers (a clone, or maps built with the same
+    /// [`SeededHashBuilder`] seed) give the same hash for a key, so this can be used to shard
+    /// keys between maps consistently. A map with the default hasher under the `std` feature is
+    /// seeded randomly, so its hashes differ from every other map's.
+    pub fn hash_key<Q>(&self, key: &Q) -> u32
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._hash(key)
+    }
+
+    /// Limit how many entries a probe visits. An insert which would place a key further along its
+    /// probe sequence fails with [`Error::ProbeLimitExceeded`] instead, so no key is ever further
+    /// than the limit, and a lookup which reaches the limit can stop. This bounds the worst case
+    /// of every operation, at the cost of inserts failing before the map is full.
+    ///
+    /// The limit isn't carried over to clones, or to maps rebuilt from this one.
+    /// # Errors
+    /// [`Error::ProbeLimitExceeded`] if a key already in the map is further along its probe
+    /// sequence than `limit`. The limit is left unchanged.
+    pub fn set_probe_limit(&mut self, limit: usize) -> Result<(), Error> {
+        let limit = Size::try_from(limit).unwrap_or(Size::MAX);
+        let beyond_limit = self.table.iter().zip(0..).any(|(entry, index)| {
+            let entry = entry.load(Ordering::Relaxed);
+            let home = self._index(entry.key_hash & constants::HASH_MASK);
+            entry.has_key() && self.table.distance(home, index) >= limit
+        });
+        if beyond_limit {
+            return Err(Error::ProbeLimitExceeded);
+        }
+        self.probe_limit = limit;
+        Ok(())
+    }
+
+    /// The limit set by [`set_probe_limit`](Self::set_probe_limit), if any.
+    pub fn probe_limit(&self) -> Option<usize> {
+        (self.probe_limit != Size::MAX).then_some(usize::from(self.probe_limit))
     }
 
     /// Update the value associated with a key. Returns the previous value on success, or None on failure.
-    pub fn update<Q: ?Sized>(&self, key: &Q, value: V) -> Option<V>
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self._fetch_update(key, |current| {
             Some(Entry {
@@ -162,14 +737,94 @@ where
         .map(|previous| previous.value)
     }
 
+    /// Update the value associated with a key, with the orderings of the compare-and-swap which
+    /// writes it. [`update`](Self::update) uses [`Release`](Ordering::Release) on success and
+    /// [`Acquire`](Ordering::Acquire) on failure. Returns the previous value on success, or None
+    /// on failure.
+    /// # Panics
+    /// If `failure` is [`Release`](Ordering::Release) or [`AcqRel`](Ordering::AcqRel), as for
+    /// an atomic compare-and-swap.
+    pub fn update_with_orderings<Q>(
+        &self,
+        key: &Q,
+        value: V,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self._find_entry(key)?;
+        self._update_entry_with_orderings(entry, success, failure, |current| {
+            Some(Entry {
+                key_hash: current.key_hash,
+                key_offset: current.key_offset,
+                value,
+            })
+        })
+        .map(|previous| previous.value)
+    }
+
+    /// Remove a key from the map, returning its value, or None if the key doesn't exist.
+    ///
+    /// The key's entry is replaced with a tombstone, which still refers to the key, and the key is
+    /// left in the key store, where iteration skips it. Both still take up room: the tombstone
+    /// counts against the map's capacity, and the key against the key store's. Inserting the same
+    /// key again takes back both its entry and its slot in the key store, so a shared map can
+    /// remove and insert a set of keys any number of times. Other keys can't use that room, so a
+    /// shared map can only ever hold as many distinct keys as its key store has slots, until
+    /// [`purge_tombstones`](Self::purge_tombstones) or
+    /// [`compact_key_store`](Self::compact_key_store) reclaims the rest.
+    /// ```
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(1);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.remove(&1), Some(10));
+    /// assert_eq!(map.remove(&1), None);
+    /// assert!(!map.insert(2, 20));
+    /// assert!(map.insert(1, 11));
+    /// ```
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._remove_entry(self._find_entry(key)?)
+    }
+
+    /// Replace a live `entry` with a tombstone, and count it as one, returning its value.
+    fn _remove_entry(&self, entry: &Atomic<Entry<V>>) -> Option<V> {
+        self._kill_entry(entry).map(|removed| removed.value)
+    }
+
+    /// Replace a live `entry` with the tombstone left by removing its key, and count it as one,
+    /// returning the entry it held.
+    pub(crate) fn _kill_entry(&self, entry: &Atomic<Entry<V>>) -> Option<Entry<V>> {
+        // Counted first, because an insert of the key may take the tombstone back straight away.
+        self._add_tombstones(1);
+        let Some(removed) = self._update_entry(entry, |current| {
+            current.is_live().then(|| current.removed())
+        }) else {
+            self._sub_tombstones(1);
+            return None;
+        };
+        // The tombstone still takes up the room, so only a task waiting to insert the same key
+        // can use it.
+        self._uncount(1);
+        #[cfg(feature = "async")]
+        self.waiters
+            .wake_key(removed.key_hash & constants::HASH_MASK);
+        Some(removed)
+    }
+
     /// Update the value associated with a key using an update function. Returns the previous value on success, or None on failure.
     /// The update function should return Some(V) with the new value, or None if the value should not be updated.
     /// # Errors
     /// If the key doesn't exist in the map, or the function `f` returned None.
-    pub fn fetch_update<Q: ?Sized, F>(&self, key: &Q, mut f: F) -> Option<V>
+    pub fn fetch_update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
         F: FnMut(V) -> Option<V>,
     {
         self._fetch_update(key, |current| {
@@ -182,241 +837,976 @@ where
         .map(|previous| previous.value)
     }
 
+    /// Update the value associated with a key using an update function which is also passed the
+    /// stored key. Returns the previous value on success, or None on failure.
+    /// The update function should return Some(V) with the new value, or None if the value should
+    /// not be updated. It may be called more than once if the value is concurrently modified.
+    pub fn update_with_key<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(&K, V) -> Option<V>,
+    {
+        let entry = self._find_entry(key)?;
+        let loaded = entry.load(Ordering::Acquire);
+        // The key may have been removed since it was found, leaving no key offset to follow.
+        if !loaded.is_live() {
+            return None;
+        }
+        // SAFETY: The entry was loaded with `Acquire`, and a published key stays in the key store
+        // while the map is shared.
+        let stored_key = unsafe { self._entry_key(loaded.key_offset) };
+        self._update_entry_with_orderings(entry, Ordering::Release, Ordering::Acquire, |current| {
+            f(stored_key, current.value).map(|value| Entry { value, ..current })
+        })
+        .map(|previous| previous.value)
+    }
+
+    /// Iterate over the key-value pairs in the map, in the order the keys were inserted.
+    ///
+    /// The iterator takes a snapshot of the key store's length when it is created. It yields
+    /// every entry inserted before then, and never yields a key pushed afterwards, so concurrent
+    /// inserts can't make it run indefinitely. Inserts which were in progress when it was
+    /// created may or may not be yielded, but a key is only ever yielded once it is fully
+    /// inserted. Values are read as the iterator reaches each entry.
     #[inline]
     pub fn iter(&self) -> <&Self as IntoIterator>::IntoIter {
         self.into_iter()
     }
 
+    /// Iterate over the keys in the map, in the order they were inserted, with the same snapshot
+    /// semantics as [`iter`](Self::iter).
+    /// ```
+    /// let map = folklore::HashMap::<&str, u16>::with_capacity(8);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// assert!(map.keys().eq(&["a", "b"]));
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Iterate over the values in the map, in the order their keys were inserted, with the same
+    /// snapshot semantics as [`iter`](Self::iter).
+    /// ```
+    /// let map = folklore::HashMap::<&str, u16>::with_capacity(8);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// assert_eq!(map.values().sum::<u16>(), 3);
+    /// ```
+    pub fn values(&self) -> impl Iterator<Item = V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Move the map to the heap and leak it, returning a `&'static` reference, like
+    /// [`Box::leak`](alloc::boxed::Box::leak). The map, its table and its keys are never freed.
+    ///
+    /// This is useful for a map which is built once at startup and then shared with other threads
+    /// for the rest of the program, without the overhead of an `Arc`.
+    pub fn leak(self) -> &'static Self
+    where
+        Self: 'static,
+    {
+        alloc::boxed::Box::leak(alloc::boxed::Box::new(self))
+    }
+
+    /// Insert `key`, with the value returned by `f`. `f` is only called once the key is known
+    /// not to be in the map. Returns the inserted value, and the index of the key in the key store.
+    fn _insert_with<I, F>(&self, key: I, f: F) -> Result<(V, usize), InsertFailure<'_, V>>
+    where
+        I: InsertKey<K>,
+        K: Borrow<I::Key>,
+        F: FnOnce(&K) -> V,
+    {
+        let key_hash = self._hash(key.key());
+        self._insert_reserving(key, key_hash, f, true)
+    }
+
+    /// Insert `key`, whose hash has already been computed with this map's hasher, as for
+    /// [`_insert_with`](Self::_insert_with). Any flags in the top bits of `entry_hash` are stored
+    /// with the entry.
+    fn _insert_hashed<F>(
+        &self,
+        key: K,
+        entry_hash: HashT,
+        f: F,
+    ) -> Result<(V, usize), InsertFailure<'_, V>>
+    where
+        F: FnOnce(&K) -> V,
+    {
+        self._insert_reserving(key, entry_hash, f, true)
+    }
+
+    /// Insert `key` as for [`_insert_hashed`](Self::_insert_hashed). If `reserve` is false, the
+    /// caller has already counted the key with [`_reserve_count`](Self::_reserve_count), and keeps
+    /// that reservation if the insert fails.
+    fn _insert_reserving<I, F>(
+        &self,
+        key: I,
+        entry_hash: HashT,
+        f: F,
+        reserve: bool,
+    ) -> Result<(V, usize), InsertFailure<'_, V>>
+    where
+        I: InsertKey<K>,
+        K: Borrow<I::Key>,
+        F: FnOnce(&K) -> V,
+    {
+        let index = self._index(entry_hash & constants::HASH_MASK);
+        self._insert_at(key, entry_hash, index, f, reserve)
+    }
+
+    /// Insert `key` as for [`_insert_reserving`](Self::_insert_reserving), probing from `index`
+    /// rather than the start of the key's probe sequence. Every entry before `index` in the
+    /// sequence must have been found to hold another key.
+    fn _insert_at<I, F>(
+        &self,
+        key: I,
+        entry_hash: HashT,
+        index: Size,
+        f: F,
+        reserve: bool,
+    ) -> Result<(V, usize), InsertFailure<'_, V>>
+    where
+        I: InsertKey<K>,
+        K: Borrow<I::Key>,
+        F: FnOnce(&K) -> V,
+    {
+        let key_hash = entry_hash & constants::HASH_MASK;
+        let (mut entry, mut index) = match self._probe_settled(key.key(), key_hash, index) {
+            Probe::Vacant(entry, index) => (entry, index),
+            Probe::Found(entry) => return Err(InsertFailure::Exists(entry)),
+            Probe::Removed(entry, _) => return self._take_back(entry, entry_hash, f, !reserve),
+            Probe::LimitExceeded => return Err(InsertFailure::Error(Error::ProbeLimitExceeded)),
+        };
+
+        let reserved = Size::from(reserve);
+        if reserve {
+            self._reserve_count(1).map_err(InsertFailure::Error)?;
+        }
+
+        let pushed = (!inject::fail(inject::Point::KeyStorePush))
+            .then(|| {
+                self.key_store.push(HashedKey {
+                    key_hash,
+                    key: key.into_key(),
+                })
+            })
+            .flatten();
+        let Some((stored, key_index)) = pushed else {
+            self._release_count(reserved);
+            return Err(InsertFailure::Error(Error::KeyStoreFull));
+        };
+        // `Eq` is called while probing again below, and may panic.
+        let mut pushed = UnpushOnExit {
+            map: self,
+            key_index,
+            reserved,
+        };
+
+        // The key is only owned once it is stored, so `f` is passed the stored key. If it panics,
+        // `pushed` removes the key again.
+        let key = &stored.key;
+        let value = f(key);
+        let new_entry = Entry {
+            key_hash: entry_hash,
+            key_offset: key_offset(key_index),
+            value,
+        };
+
+        // If another thread fills the empty entry first, carry on probing from that entry, in
+        // case the other thread was inserting the same key.
+        while inject::fail(inject::Point::InsertCas)
+            || entry
+                .compare_exchange(
+                    Entry::EMPTY,
+                    new_entry,
+                    Ordering::Release,
+                    Ordering::Acquire,
+                )
+                .is_err()
+        {
+            match self._probe_settled::<K>(key, key_hash, index) {
+                Probe::Vacant(next_entry, next_index) => {
+                    entry = next_entry;
+                    index = next_index;
+                }
+                // Another thread inserted the key and removed it again, so the key takes back
+                // that entry rather than the copy pushed for it. The reservation is kept.
+                Probe::Removed(removed, _) => {
+                    pushed.reserved = 0;
+                    drop(pushed);
+                    return self._take_back(removed, entry_hash, |_| value, true);
+                }
+                failed => {
+                    drop(pushed);
+                    return Err(match failed {
+                        Probe::Found(existing) => InsertFailure::Exists(existing),
+                        _ => InsertFailure::Error(Error::ProbeLimitExceeded),
+                    });
+                }
+            }
+        }
+        core::mem::forget(pushed);
+        self._touch(key_index);
+        self.key_store.publish(key_index);
+        Ok((value, key_index))
+    }
+
+    /// Insert a key into the `entry` holding the tombstone it left when it was removed, writing
+    /// it with `entry_hash` and the value returned by `f`. The key takes back its slot in the key
+    /// store too, so nothing is pushed. The entry is added to the map's length unless `counted`
+    /// is true, because the caller has reserved it already.
+    fn _take_back<'map, F>(
+        &'map self,
+        entry: &'map Atomic<Entry<V>>,
+        entry_hash: HashT,
+        f: F,
+        counted: bool,
+    ) -> Result<(V, usize), InsertFailure<'map, V>>
+    where
+        F: FnOnce(&K) -> V,
+    {
+        // Wait for a batch which took the entry back to commit or roll back, as a probe would.
+        let settled = || loop {
+            let current = entry.load(Ordering::Acquire);
+            if !current.is_pending() {
+                break current;
+            }
+            core::hint::spin_loop();
+        };
+        let mut current = settled();
+        let Some(key_offset) = current.removed_key() else {
+            // Another insert of the key took the entry back first.
+            return Err(InsertFailure::Exists(entry));
+        };
+        // SAFETY: The tombstone was loaded with `Acquire`, and the removed key stays in the key
+        // store until the map is borrowed mutably.
+        let value = f(unsafe { self._entry_key(key_offset) });
+        let new_entry = Entry {
+            key_hash: entry_hash,
+            key_offset,
+            value,
+        };
+        // The key may be inserted and removed again in between, leaving a new tombstone.
+        while entry
+            .compare_exchange(current, new_entry, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            current = settled();
+            if current.removed_key().is_none() {
+                return Err(InsertFailure::Exists(entry));
+            }
+        }
+        self._count_taken_back(counted);
+        let key_index = key_index(key_offset);
+        self._touch(key_index);
+        self.key_store.publish(key_index);
+        Ok((value, key_index))
+    }
+
+    /// Find the entry with the smallest value according to `compare`, scanning the table once.
+    /// Returns None if the map is empty.
+    pub fn min_by_value<F>(&self, mut compare: F) -> Option<(&K, V)>
+    where
+        F: FnMut(&V, &V) -> core::cmp::Ordering,
+    {
+        self._occupied_entries()
+            .min_by(|a, b| compare(&a.value, &b.value))
+            .map(|entry| (unsafe { self._entry_key(entry.key_offset) }, entry.value))
+    }
+
+    /// Find the entry with the largest value according to `compare`, scanning the table once.
+    /// Returns None if the map is empty.
+    pub fn max_by_value<F>(&self, mut compare: F) -> Option<(&K, V)>
+    where
+        F: FnMut(&V, &V) -> core::cmp::Ordering,
+    {
+        self._occupied_entries()
+            .max_by(|a, b| compare(&a.value, &b.value))
+            .map(|entry| (unsafe { self._entry_key(entry.key_offset) }, entry.value))
+    }
+
+    /// Iterate over every live entry in the table, in table order.
+    /// Entries are loaded with `Acquire`, so their keys may be read with `_entry_key`.
+    fn _occupied_entries(&self) -> impl Iterator<Item = Entry<V>> + '_ {
+        self.table
+            .iter()
+            .map(|entry| entry.load(Ordering::Acquire))
+            .filter(Entry::is_live)
+    }
+
     #[inline]
-    fn _fetch_update<Q: ?Sized, F>(&self, key: &Q, f: F) -> Option<Entry<V>>
+    fn _fetch_update<Q, F>(&self, key: &Q, f: F) -> Option<Entry<V>>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
         F: FnMut(Entry<V>) -> Option<Entry<V>>,
     {
-        let entry = self._find_entry(key)?;
+        self._update_entry(self._find_entry(key)?, f)
+    }
 
-        entry
-            .fetch_update(Ordering::Release, Ordering::Acquire, f)
-            .ok()
+    /// Update `entry` with `f`, returning the previous entry, or None if `f` returned None.
+    #[inline]
+    fn _update_entry<F>(&self, entry: &Atomic<Entry<V>>, f: F) -> Option<Entry<V>>
+    where
+        F: FnMut(Entry<V>) -> Option<Entry<V>>,
+    {
+        self._update_entry_with_orderings(entry, Ordering::Release, Ordering::Acquire, f)
     }
 
-    fn _find_entry<Q: ?Sized>(&self, key: &Q) -> Option<&Atomic<Entry<V>>>
+    /// [`_update_entry`](Self::_update_entry), with the given compare-and-swap orderings.
+    #[inline]
+    fn _update_entry_with_orderings<F>(
+        &self,
+        entry: &Atomic<Entry<V>>,
+        success: Ordering,
+        failure: Ordering,
+        mut f: F,
+    ) -> Option<Entry<V>>
+    where
+        F: FnMut(Entry<V>) -> Option<Entry<V>>,
+    {
+        // A tombstone no longer holds its key, so it can't be updated.
+        let previous = entry
+            .fetch_update(success, failure, |current| {
+                (current.key_offset != constants::TOMBSTONE)
+                    .then(|| f(current))
+                    .flatten()
+            })
+            .ok()?;
+        self._touch(key_index(previous.key_offset));
+        Some(previous)
+    }
+
+    /// Record that the key at `key_index` was just inserted or updated.
+    #[inline]
+    #[allow(clippy::unused_self)]
+    fn _touch(&self, key_index: usize) {
+        #[cfg(feature = "timestamps")]
+        self.timestamps.touch(key_index);
+        #[cfg(not(feature = "timestamps"))]
+        let _ = key_index;
+    }
+
+    fn _find_entry<Q>(&self, key: &Q) -> Option<&Atomic<Entry<V>>>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        let (key_hash, mut index) = self._hash_and_index(key);
-
-        let buckets = self._bucket_slice();
-
-        for _ in 0..self.size_mask {
-            let entry = get_entry(buckets, index, self.size_mask);
-            match entry.load(Ordering::Relaxed) {
-                Entry {
-                    key_offset: constants::EMPTY_KEY,
-                    ..
-                } => return None,
-                Entry {
-                    key_offset,
-                    key_hash: entry_hash,
-                    ..
-                } if key_hash == entry_hash => {
-                    let key_offset = key_offset - constants::MIN_KEY;
-                    if let Some(existing_key) = self.key_store.get(key_offset as usize) {
-                        if key == existing_key.borrow() {
-                            return Some(entry);
-                        }
-                    }
-                }
-                _ => {}
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find_entry_hashed(key, self._hash(key))
+    }
+
+    /// Find the entry for `key`, whose hash has already been computed with this map's hasher.
+    fn _find_entry_hashed<Q>(&self, key: &Q, key_hash: HashT) -> Option<&Atomic<Entry<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self._probe(key, key_hash, self._index(key_hash)) {
+            // An entry which is part of an uncommitted batch isn't in the map yet.
+            Probe::Found(entry) if !entry.load(Ordering::Relaxed).is_pending() => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Probe the table for `key`, starting at `index`.
+    fn _probe<Q>(&self, key: &Q, key_hash: HashT, index: Size) -> Probe<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self._probe_raw(key, key_hash, index) {
+            RawProbe::Found(entry, index)
+                if entry.load(Ordering::Relaxed).removed_key().is_some() =>
+            {
+                Probe::Removed(entry, index)
+            }
+            RawProbe::Found(entry, _) => Probe::Found(entry),
+            RawProbe::Vacant(entry, index) => Probe::Vacant(entry, index),
+            RawProbe::Exhausted => {
+                debug_assert!(
+                    usize::from(self.probe_limit) < self.table.slots() - 1,
+                    "There cannot be 0 empty entries, because the usable capacity is less than the allocated capacity."
+                );
+                Probe::LimitExceeded
             }
-            index = self._next_index(index);
         }
-        unreachable!("There cannot be 0 empty entries, because the usable capacity is less than the allocated capacity.")
     }
 
-    fn _find_empty_entry<Q: ?Sized>(&self, key: &Q) -> Option<&Atomic<Entry<V>>>
+    fn _probe_raw<Q>(&self, key: &Q, key_hash: HashT, index: Size) -> RawProbe<'_, Entry<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Probes may resume part way along the sequence, so only the rest of the limit is left.
+        let probed = self.table.distance(self._index(key_hash), index);
+        let max_len = self.probe_limit.saturating_sub(probed);
+        self.table.probe_within(index, max_len, |entry| {
+            // A tombstone left by a remove still refers to its key, whose hash is cached beside it
+            // in the key store. The key was written before the entry it was removed from.
+            if let Some(key_offset) = entry.removed_key() {
+                fence(Ordering::Acquire);
+                let stored = unsafe { self.key_store.get_unchecked(key_index(key_offset)) };
+                return key_hash == stored.key_hash && key == stored.key.borrow();
+            }
+            if entry.key_offset == constants::TOMBSTONE
+                || key_hash != entry.key_hash & constants::HASH_MASK
+            {
+                return false;
+            }
+            // Synchronise with the insertion of the entry, so the key is fully written.
+            fence(Ordering::Acquire);
+            let existing_key = unsafe { self._entry_key(entry.key_offset) };
+            key == existing_key.borrow()
+        })
+    }
+
+    /// Probe the table for `key`, like [`_probe`](Self::_probe), but if the key is found in an
+    /// entry which is part of an uncommitted batch, wait for the batch to commit or roll back.
+    /// Inserts use this, so that a key is never inserted twice if a batch containing it commits.
+    fn _probe_settled<Q>(&self, key: &Q, key_hash: HashT, index: Size) -> Probe<'_, V>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        let (key_hash, mut index) = self._hash_and_index(key);
-
-        let buckets = self._bucket_slice();
-
-        for _ in 0..self.size_mask {
-            let entry = get_entry(buckets, index, self.size_mask);
-            match entry.load(Ordering::Relaxed) {
-                Entry {
-                    key_offset: constants::EMPTY_KEY,
-                    ..
-                } => return Some(entry),
-                Entry {
-                    key_offset,
-                    key_hash: entry_hash,
-                    ..
-                } if key_hash == entry_hash => {
-                    let key_offset = key_offset - constants::MIN_KEY;
-                    if let Some(existing_key) = self.key_store.get(key_offset as usize) {
-                        if key == existing_key.borrow() {
-                            return None;
-                        }
+        Q: Hash + Eq + ?Sized,
+    {
+        loop {
+            match self._probe(key, key_hash, index) {
+                Probe::Found(entry) => {
+                    let mut current = entry.load(Ordering::Acquire);
+                    while current.is_pending() {
+                        core::hint::spin_loop();
+                        current = entry.load(Ordering::Acquire);
+                    }
+                    // A rolled back entry is a tombstone, which the next probe reports as removed.
+                    if current.key_offset != constants::TOMBSTONE {
+                        return Probe::Found(entry);
                     }
                 }
-                _ => {}
+                other => return other,
+            }
+        }
+    }
+
+    /// Get the key referenced by the `key_offset` of a non-empty entry.
+    /// # Safety
+    /// The entry must have been loaded from the table, and synchronised with its insertion
+    /// (by an `Acquire` load or fence). The key was pushed before the entry was inserted, and
+    /// keys in the table, or referred to by its tombstones, are never removed from the key store
+    /// while it is shared.
+    unsafe fn _entry_key(&self, key_offset: KeyOffset) -> &K {
+        self._stored_key(key_index(key_offset))
+    }
+
+    /// Get the key at `key_index` in the key store, whether or not it has been published.
+    /// # Safety
+    /// See [`ConcurrentArray::get_unchecked`].
+    unsafe fn _stored_key(&self, key_index: usize) -> &K {
+        &self.key_store.get_unchecked(key_index).key
+    }
+
+    /// Find the entry for the published key at `key_index`, using the hash cached beside it in the
+    /// key store rather than hashing it again. Returns the key and its current value.
+    fn _get_stored(&self, key_index: usize) -> Option<(&K, V)> {
+        self._get_stored_entry(key_index)
+            .map(|(key, entry)| (key, entry.value))
+    }
+
+    /// As for [`_get_stored`](Self::_get_stored), but returns the key's whole entry, flags
+    /// included.
+    fn _get_stored_entry(&self, key_index: usize) -> Option<(&K, Entry<V>)> {
+        let stored = self.key_store.get(key_index)?;
+        let index = self._index(stored.key_hash);
+        match self._probe(&stored.key, stored.key_hash, index) {
+            Probe::Found(entry) => {
+                let entry = entry.load(Ordering::Relaxed);
+                // If the key is stored more than once, only the copy its entry refers to is in
+                // the map.
+                (!entry.is_pending() && crate::key_index(entry.key_offset) == key_index)
+                    .then_some((&stored.key, entry))
             }
-            index = self._next_index(index);
+            _ => None,
         }
-        unreachable!("There cannot be 0 empty entries, because the usable capacity is less than the allocated capacity.")
     }
 
-    fn _bucket_slice(&self) -> &[Bucket<V>] {
-        unsafe { &*slice_from_raw_parts(self.table, self.size_mask as usize + 1) }
+    /// Reserve space for `n` more entries, failing if they would take the map over capacity.
+    /// Tombstones occupy the table, so they are counted against the capacity too.
+    fn _reserve_count(&self, n: Size) -> Result<(), Error> {
+        self.count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                let used = usize::from(count) + self._tombstones() + usize::from(n);
+                (used <= usize::from(self.capacity))
+                    .then(|| count.checked_add(n))
+                    .flatten()
+            })
+            .map(|_| ())
+            .map_err(|_| Error::Full)
     }
 
-    fn _next_index(&self, index: Size) -> Size {
-        crate::wrap!(<Size>: index as usize + 1, self.size_mask as usize + 1)
+    /// Release `n` entries reserved by [`_reserve_count`](Self::_reserve_count), waking any tasks
+    /// waiting for room.
+    fn _release_count(&self, n: Size) {
+        self._uncount(n);
+        #[cfg(feature = "async")]
+        if n > 0 {
+            self.waiters.wake_all();
+        }
+    }
+
+    /// Take `n` entries off the count. The count saturates at zero rather than wrapping, so
+    /// [`len`](Self::len) can't jump to `u16::MAX`.
+    fn _uncount(&self, n: Size) {
+        let previous = self
+            .count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_sub(n))
+            })
+            .unwrap_or_default();
+        debug_assert!(
+            previous >= n,
+            "released {n} entries, but only {previous} were counted"
+        );
+    }
+
+    /// Count `n` more tombstones, saturating rather than wrapping.
+    fn _add_tombstones(&self, n: Size) {
+        let previous = self
+            .tombstones
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tombstones| {
+                Some(tombstones.saturating_add(n))
+            })
+            .unwrap_or_default();
+        debug_assert!(
+            previous.checked_add(n).is_some(),
+            "tombstone count overflowed"
+        );
+    }
+
+    /// Count a tombstone taken back by its key as an entry again, unless the caller has
+    /// `counted` it already. The tombstone held its room, so the count can't go over capacity.
+    fn _count_taken_back(&self, counted: bool) {
+        if !counted {
+            self.count
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                    Some(count.saturating_add(1))
+                })
+                .ok();
+        }
+        self._sub_tombstones(1);
+    }
+
+    /// Count `n` fewer tombstones, saturating at zero rather than wrapping.
+    fn _sub_tombstones(&self, n: Size) {
+        let previous = self
+            .tombstones
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tombstones| {
+                Some(tombstones.saturating_sub(n))
+            })
+            .unwrap_or_default();
+        debug_assert!(
+            previous >= n,
+            "took back {n} tombstones, but only {previous} were counted"
+        );
+    }
+
+    fn _tombstones(&self) -> usize {
+        usize::from(self.tombstones.load(Ordering::Relaxed))
     }
 
     /// Hash the key, returning a value of type [`HashT`].
     #[inline]
-    fn _hash<Q: ?Sized>(&self, key: &Q) -> HashT
+    fn _hash<Q>(&self, key: &Q) -> HashT
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
-        util::hash::<_, FnvHasher>(key)
+        hashing::hash(key, &self.hash_builder)
     }
 
     /// Hash the key, and derive the table index from the hash.
     /// Return (hash, index).
     #[inline]
-    fn _hash_and_index<Q: ?Sized>(&self, key: &Q) -> (HashT, Size)
+    fn _hash_and_index<Q>(&self, key: &Q) -> (HashT, Size)
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         let hash = self._hash(key);
-        (
-            hash,
-            crate::wrap!(<Size>: hash, self.size_mask as usize + 1),
-        )
+        (hash, self._index(hash))
+    }
+
+    /// Derive the table index from a key hash.
+    #[inline]
+    fn _index(&self, key_hash: HashT) -> Size {
+        self.table.index_for(key_hash)
     }
 }
 
-impl<'map, K, V> IntoIterator for &'map HashMap<K, V>
+impl<'map, K, V, S> IntoIterator for &'map HashMap<K, V, S>
 where
     K: Hash + Eq,
     V: Copy + NoUninit,
+    S: BuildHasher,
 {
     type Item = (&'map K, V);
-    type IntoIter = Iter<'map, K, V>;
+    type IntoIter = Iter<'map, K, V, S>;
 
     fn into_iter(self) -> Self::IntoIter {
         Iter {
             map: self,
             index: 0,
+            end: self.key_store.len(),
         }
     }
 }
 
-pub struct Iter<'map, K, V>
+pub struct Iter<'map, K, V, S = DefaultHashBuilder>
 where
     K: Hash + Eq,
     V: Copy + NoUninit,
 {
-    map: &'map HashMap<K, V>,
+    map: &'map HashMap<K, V, S>,
     index: usize,
+    end: usize,
 }
 
-impl<'map, K, V> Iterator for Iter<'map, K, V>
+impl<'map, K, V, S> Iterator for Iter<'map, K, V, S>
 where
     K: Hash + Eq,
     V: Copy + NoUninit,
+    S: BuildHasher,
 {
     type Item = (&'map K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
         // Loop over the map's keys, because they are stored congiguously.
         // This is faster than looping over the entries, which are scattered between many empty entries.
-        let key = self.map.get_key(self.index)?;
-        let value = self.map.get(key)?;
-        self.index += 1;
-        Some((key, value))
+        // Slots which were never published (because the insert lost a race) are skipped.
+        while self.index < self.end {
+            let index = self.index;
+            self.index += 1;
+            if let Some(item) = self.map._get_stored(index) {
+                return Some(item);
+            }
+        }
+        None
     }
 }
 
-impl<K, V> Clone for HashMap<K, V>
+/// Cloning makes a deep copy, with its own table and key store, so the copy can be read while the
+/// original keeps taking writes. Keys are copied in insertion order, each with its value and
+/// [flags](HashMap::get_flags), and keep the hash cached beside them rather than being hashed
+/// again. The copy has the same snapshot semantics as [`iter`](HashMap::iter): a key written
+/// while the map is being cloned may or may not be copied, but each copied key has a value it held
+/// at some point. Removed keys leave no tombstones in the copy.
+impl<K, V, S> Clone for HashMap<K, V, S>
 where
     K: Hash + Eq + Clone,
     V: Copy + NoUninit,
+    S: BuildHasher + Clone,
 {
     fn clone(&self) -> Self {
-        let new = Self::with_capacity(self.capacity as usize);
-        for (key, value) in self {
-            new.insert(key.clone(), value);
+        let capacity = usize::from(self.capacity);
+        let new = Self::try_with_capacity_and_hasher_aligned(
+            capacity,
+            self.hash_builder.clone(),
+            self.table.align(),
+        )
+        .unwrap_or_else(|_| {
+            alloc::alloc::handle_alloc_error(RawTable::<Entry<V>>::layout(allocated_size(capacity)))
+        });
+        for key_index in 0..self.key_store.len() {
+            if let Some((key, entry)) = self._get_stored_entry(key_index) {
+                // Fails only if the map was written while it was cloned, and then only for keys
+                // which didn't share the map at any one moment.
+                let _ = new._insert_hashed(key.clone(), entry.key_hash, |_| entry.value);
+            }
         }
         new
     }
 }
 
-impl<K: Hash + Eq, V: Copy + NoUninit> Drop for HashMap<K, V> {
-    fn drop(&mut self) {
-        util::deallocate(self.table, self.size_mask as usize + 1);
-    }
-}
-
-impl<K: Hash + Eq, V: Copy + NoUninit> Default for HashMap<K, V> {
+impl<K: Hash + Eq, V: Copy + NoUninit, S: BuildHasher + Default> Default for HashMap<K, V, S> {
     fn default() -> Self {
-        Self::with_capacity(DEFAULT_CAPACITY)
+        Self::with_capacity_and_hasher(DEFAULT_CAPACITY, S::default())
     }
 }
 
-unsafe impl<K: Hash + Eq, V: Copy + NoUninit> Send for HashMap<K, V> {}
-unsafe impl<K: Hash + Eq, V: Copy + NoUninit> Sync for HashMap<K, V> {}
+// Keys inserted by one thread can be borrowed by, and are eventually owned by, any other thread
+// with access to the map.
+unsafe impl<K, V, S> Send for HashMap<K, V, S>
+where
+    K: Hash + Eq + Send,
+    V: Copy + NoUninit + Send,
+    S: Send,
+{
+}
+unsafe impl<K, V, S> Sync for HashMap<K, V, S>
+where
+    K: Hash + Eq + Send + Sync,
+    V: Copy + NoUninit + Send + Sync,
+    S: Sync,
+{
+}
 
 mod constants {
-    use super::Size;
+    use super::{HashT, KeyOffset, Size};
 
-    pub const EMPTY_KEY: Size = 0;
+    pub const EMPTY_KEY: KeyOffset = KeyOffset(0);
     pub const MIN_KEY: Size = 1;
+    /// The key offset of an entry whose key was removed, rolled back, or moved by `rekey`.
+    /// Probes skip over it, and no other key reuses it, because a key inserted after it may be
+    /// further along the same probe sequence. Only the key it held can take it back, if the
+    /// tombstone still refers to it (see [`REMOVED`]).
+    pub const TOMBSTONE: KeyOffset = KeyOffset(Size::MAX);
+    /// The position of the user flags in the top bits of an entry's `key_hash`.
+    pub const FLAG_SHIFT: u32 = HashT::BITS - crate::FLAG_BITS;
+    /// Set in an entry's `key_hash` while the entry is part of an uncommitted batch.
+    pub const PENDING: HashT = 1 << (FLAG_SHIFT - 1);
+    /// Set in the `key_hash` of a tombstone which still refers to the key it held, whose offset is
+    /// then in the low bits. A tombstone is never pending, so this shares the pending bit.
+    pub const REMOVED: HashT = PENDING;
+    /// The bits of an entry's `key_hash` which hold the hash of its key.
+    pub const HASH_MASK: HashT = PENDING - 1;
+}
+
+/// The number of entries allocated in the table of a map with the given capacity.
+/// The allocated size of the table is larger than the capacity to allow for `LOAD_FACTOR`,
+/// which improves performance. This also means there will always be empty entries, which
+/// means the unconditional loops in get/insert will never be infinite.
+/// The table always holds at least one full bucket.
+const fn allocated_size(capacity: usize) -> usize {
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    let size = ((capacity as f64 / LOAD_FACTOR) as usize).next_power_of_two();
+    if size < BUCKET_CAPACITY {
+        BUCKET_CAPACITY
+    } else {
+        size
+    }
+}
+
+/// The number of keys the key store of a map with the given capacity can hold.
+const fn key_store_capacity(capacity: usize) -> usize {
+    capacity.next_power_of_two()
+}
+
+/// A key in the key store, with its hash (without flags), so the key never needs to be hashed
+/// again once it is in the map.
+struct HashedKey<K> {
+    key_hash: HashT,
+    key: K,
+}
+
+/// A reference from an [`Entry`] to its key in the key store. This is a separate type from the
+/// `Size` indexes into the table, so the two can't be mixed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(transparent)]
+struct KeyOffset(Size);
+
+/// Convert an index in the key store to the `key_offset` stored in an [`Entry`].
+fn key_offset(key_index: usize) -> KeyOffset {
+    let offset = Size::try_from(key_index)
+        .ok()
+        .and_then(|index| index.checked_add(constants::MIN_KEY))
+        .filter(|&offset| offset != constants::TOMBSTONE.0);
+    // The key store never holds more than `1 << 15` keys.
+    KeyOffset(offset.expect("key index out of range"))
+}
+
+/// Convert the `key_offset` of a non-empty [`Entry`] back to an index in the key store.
+fn key_index(key_offset: KeyOffset) -> usize {
+    usize::from(key_offset.0 - constants::MIN_KEY)
+}
+
+/// The position of an entry in a map's table, returned by [`HashMap::find_index`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryIndex(Size);
+
+/// The result of probing the table for a key.
+enum Probe<'map, V> {
+    /// The key is stored in this entry.
+    Found(&'map Atomic<Entry<V>>),
+    /// The key was removed, leaving this tombstone (at the given index), which still refers to
+    /// it. The key isn't anywhere else in the table, and an insert of it takes the entry back.
+    Removed(&'map Atomic<Entry<V>>, Size),
+    /// The key isn't in the table. This is the first empty entry (at the given index) in the
+    /// key's probe sequence.
+    Vacant(&'map Atomic<Entry<V>>, Size),
+    /// The key isn't within the probe limit, and nor is an empty entry.
+    LimitExceeded,
+}
+
+/// Undoes an insert which has pushed its key, if the insert fails or unwinds before its entry is
+/// written: releases the `reserved` count, and removes the key, which no entry refers to.
+struct UnpushOnExit<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    map: &'map HashMap<K, V, S>,
+    key_index: usize,
+    reserved: Size,
+}
+
+impl<K, V, S> Drop for UnpushOnExit<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        if self.reserved > 0 {
+            self.map._release_count(self.reserved);
+        }
+        // May fail if another key was pushed since, leaving this one unpublished.
+        self.map.key_store.remove(self.key_index);
+    }
+}
+
+/// Why an insert failed.
+enum InsertFailure<'map, V> {
+    /// The key is already stored in this entry.
+    Exists(&'map Atomic<Entry<V>>),
+    Error(Error),
+}
+
+impl<V> InsertFailure<'_, V> {
+    fn into_error(self) -> Error {
+        match self {
+            InsertFailure::Exists(_) => Error::DuplicateKey,
+            InsertFailure::Error(error) => error,
+        }
+    }
 }
 
-fn create_table<V>(capacity: usize) -> *mut Bucket<V> {
-    let bucket_count = capacity >> BUCKET_CAPACITY.ilog2();
-    util::allocate_zeroed::<Bucket<V>>(bucket_count)
+/// A key passed to an insert, which is only turned into an owned `K` once the insert is known to
+/// go ahead, so a duplicate key doesn't cost a clone.
+trait InsertKey<K> {
+    /// The form the key is probed for in, before it is owned.
+    type Key: Hash + Eq + ?Sized;
+
+    fn key(&self) -> &Self::Key;
+
+    fn into_key(self) -> K;
 }
 
-const fn get_bucket_index(index: Size, size_mask: Size) -> Size {
-    (index & size_mask) >> BUCKET_CAPACITY.ilog2()
+impl<K: Hash + Eq> InsertKey<K> for K {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        self
+    }
+
+    fn into_key(self) -> K {
+        self
+    }
 }
 
-const fn get_entry_index(index: Size) -> Size {
-    index & (BUCKET_CAPACITY - 1)
+impl<K: Hash + Eq + Clone> InsertKey<K> for &K {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        self
+    }
+
+    fn into_key(self) -> K {
+        self.clone()
+    }
 }
 
-const fn get_entry<V>(buckets: &[Bucket<V>], index: Size, size_mask: Size) -> &Atomic<Entry<V>> {
-    let bucket_index = get_bucket_index(index, size_mask);
-    let entry_index = get_entry_index(index);
-    &buckets[bucket_index as usize].entries[entry_index as usize]
+/// A borrowed key, which is converted to an owned one only if it is inserted. The owned type is
+/// part of the wrapper, so it can never be the `K` of the owned impl above.
+struct BorrowedKey<'a, Q: ?Sized, K> {
+    key: &'a Q,
+    owned: PhantomData<fn() -> K>,
 }
 
-struct Bucket<V> {
-    entries: [Atomic<Entry<V>>; BUCKET_CAPACITY as usize],
+impl<K, Q> InsertKey<K> for BorrowedKey<'_, Q, K>
+where
+    Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+{
+    type Key = Q;
+
+    fn key(&self) -> &Q {
+        self.key
+    }
+
+    fn into_key(self) -> K {
+        self.key.to_owned()
+    }
 }
 
 #[derive(Clone, Copy)]
 // align(8) is necessary to enable the use of single-instruction atomic operations.
 #[repr(align(8))]
 struct Entry<V> {
+    /// The hash of the key, with the user flags in the top [`FLAG_BITS`] bits.
     key_hash: u32,
-    key_offset: Size,
+    key_offset: KeyOffset,
     value: V,
 }
 
 unsafe impl<V: Copy + NoUninit> NoUninit for Entry<V> {}
 
+// SAFETY: The all-zero entry has `key_offset == EMPTY_KEY`, and no other entry does.
+unsafe impl<V: Copy + NoUninit> RawEntry for Entry<V> {
+    fn is_empty(&self) -> bool {
+        self.key_offset == constants::EMPTY_KEY
+    }
+}
+
 impl<V> Entry<V> {
     pub const EMPTY: Self = unsafe { core::mem::zeroed() };
+
+    fn flags(&self) -> u8 {
+        #[allow(clippy::cast_possible_truncation)]
+        let flags = (self.key_hash >> constants::FLAG_SHIFT) as u8;
+        flags
+    }
+
+    fn with_flags(self, flags: u8) -> Self {
+        Self {
+            key_hash: (self.key_hash & !(HashT::MAX << constants::FLAG_SHIFT))
+                | (HashT::from(flags) << constants::FLAG_SHIFT),
+            ..self
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.key_hash & constants::PENDING != 0 && self.key_offset != constants::TOMBSTONE
+    }
+
+    /// Turn a live entry into the tombstone its key leaves when it is removed, which still refers
+    /// to the key.
+    fn removed(self) -> Self {
+        Self {
+            key_hash: constants::REMOVED | HashT::from(self.key_offset.0),
+            key_offset: constants::TOMBSTONE,
+            value: self.value,
+        }
+    }
+
+    /// The offset of the key a tombstone left by [`removed`](Self::removed) refers to, or None if
+    /// this isn't such a tombstone.
+    fn removed_key(&self) -> Option<KeyOffset> {
+        #[allow(clippy::cast_possible_truncation)]
+        let key_offset = KeyOffset(self.key_hash as Size);
+        (self.key_offset == constants::TOMBSTONE && self.key_hash & constants::REMOVED != 0)
+            .then_some(key_offset)
+    }
+
+    /// Whether the entry holds a key which is in the map.
+    fn is_live(&self) -> bool {
+        self.key_offset != constants::EMPTY_KEY
+            && self.key_offset != constants::TOMBSTONE
+            && !self.is_pending()
+    }
+
+    /// Whether the entry refers to a key in the key store, even if the key is still pending.
+    fn has_key(&self) -> bool {
+        self.key_offset != constants::EMPTY_KEY && self.key_offset != constants::TOMBSTONE
+    }
 }
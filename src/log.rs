@@ -0,0 +1,128 @@
+use crate::{key_index, Entry, Error, HashMap, InsertFailure};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// Receives every successful mutation made through a [`Logged`] map, so that a durable
+/// append-only log of the map can be kept outside of it.
+///
+/// Keys are identified by their index in the map's key store. To replay a log, insert the logged
+/// keys in order, and apply each update or remove to the key which was logged with the same
+/// index. A key which is removed and inserted again takes back its index, so it is logged with
+/// the same index both times.
+pub trait WriteLogger<K, V> {
+    /// Called after `key` is inserted at `key_index` with `value`.
+    fn log_insert(&self, key_index: usize, key: &K, value: V);
+    /// Called after the value of the key at `key_index` is replaced with `value`.
+    fn log_update(&self, key_index: usize, value: V);
+    /// Called after the key at `key_index` is removed.
+    fn log_remove(&self, key_index: usize);
+}
+
+/// A map with a [`WriteLogger`] attached, returned by [`HashMap::with_logger`].
+///
+/// Only mutations made through this handle are logged. A map which is never wrapped doesn't pay
+/// anything for logging.
+pub struct Logged<'map, K, V, S, L>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    map: &'map HashMap<K, V, S>,
+    logger: L,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Wrap the map, so that every successful insert, update or remove made through the returned
+    /// handle is passed to `logger`.
+    pub fn with_logger<L>(&self, logger: L) -> Logged<'_, K, V, S, L>
+    where
+        L: WriteLogger<K, V>,
+    {
+        Logged { map: self, logger }
+    }
+}
+
+impl<'map, K, V, S, L> Logged<'map, K, V, S, L>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+    L: WriteLogger<K, V>,
+{
+    /// The map which is being logged.
+    pub fn map(&self) -> &'map HashMap<K, V, S> {
+        self.map
+    }
+
+    /// The logger which mutations are passed to.
+    pub fn logger(&self) -> &L {
+        &self.logger
+    }
+
+    /// Insert a key-value pair into the map, logging it if it was inserted.
+    /// See [`HashMap::insert`].
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, logging it if it was inserted.
+    /// # Errors
+    /// See [`HashMap::try_insert`].
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), Error> {
+        let (value, index) = self
+            .map
+            ._insert_with(key, |_| value)
+            .map_err(InsertFailure::into_error)?;
+        // SAFETY: The key was just published, and is never removed.
+        let key = unsafe { self.map._stored_key(index) };
+        self.logger.log_insert(index, key, value);
+        Ok(())
+    }
+
+    /// Update the value associated with a key, logging the new value if it was updated.
+    /// See [`HashMap::update`].
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.fetch_update(key, |_| Some(value))
+    }
+
+    /// Update the value associated with a key using an update function, logging the new value if
+    /// it was updated. See [`HashMap::fetch_update`].
+    pub fn fetch_update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(V) -> Option<V>,
+    {
+        let mut new = None;
+        let previous = self.map._fetch_update(key, |current| {
+            new = f(current.value);
+            new.map(|value| Entry { value, ..current })
+        })?;
+        if let Some(value) = new {
+            self.logger
+                .log_update(key_index(previous.key_offset), value);
+        }
+        Some(previous.value)
+    }
+
+    /// Remove a key from the map, logging it if it was removed. See [`HashMap::remove`].
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self.map._kill_entry(self.map._find_entry(key)?)?;
+        self.logger.log_remove(key_index(removed.key_offset));
+        Some(removed.value)
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{Error, SeededHashBuilder, Size};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// The average number of keys hashed to each bucket. Larger buckets need fewer seeds, but take
+/// longer to place.
+const KEYS_PER_BUCKET: usize = 4;
+/// The number of seeds tried for one bucket before starting over with a different hasher.
+const SEEDS_PER_BUCKET: u32 = 1 << 16;
+/// The number of hashers tried before giving up.
+const ATTEMPTS: u64 = 16;
+/// Marks a slot which holds no key. Otherwise a slot holds its key's index plus one.
+const EMPTY_SLOT: Size = 0;
+
+/// Builds a [`PhfMap`] for a key set which is known in advance.
+///
+/// Keys are hashed into small buckets, and each bucket is given a seed which moves its keys to
+/// slots no other key uses. This takes longer than inserting into a [`HashMap`](crate::HashMap),
+/// but the map it builds finds any key with a single probe.
+///
+/// ```
+/// use folklore::PhfBuilder;
+///
+/// let mut builder = PhfBuilder::new();
+/// builder.insert("red", 1);
+/// builder.insert("green", 2);
+/// let map = builder.build().unwrap();
+/// assert_eq!(map.get("green"), Some(2));
+/// assert_eq!(map.get("blue"), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PhfBuilder<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    seed: u64,
+}
+
+/// A read-only map with a perfect hash, built by [`PhfBuilder`]. Every lookup hashes the key once
+/// and reads exactly one slot.
+///
+/// Keys and values are stored in the order they were given to the builder.
+#[derive(Debug, Clone)]
+pub struct PhfMap<K, V> {
+    hash_builder: SeededHashBuilder,
+    /// The seed of each bucket, which decides the slots of the keys in it.
+    seeds: Box<[u32]>,
+    slots: Box<[Size]>,
+    keys: Box<[K]>,
+    values: Box<[V]>,
+}
+
+impl<K, V> Default for PhfBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> PhfBuilder<K, V> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// Create a builder whose first attempt hashes with `seed`. The same keys and seed always
+    /// build the same layout.
+    #[must_use]
+    pub const fn with_seed(seed: u64) -> Self {
+        Self {
+            keys: Vec::new(),
+            values: Vec::new(),
+            seed,
+        }
+    }
+
+    /// Add a key-value pair. Duplicate keys are reported by [`build`](Self::build).
+    pub fn insert(&mut self, key: K, value: V) {
+        self.keys.push(key);
+        self.values.push(value);
+    }
+
+    /// The number of key-value pairs added so far.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if no key-value pairs have been added.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<K: Hash + Eq, V> PhfBuilder<K, V> {
+    /// Find a collision-free layout for the keys, and build the map.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if there are more than `i16::MAX` keys,
+    /// [`Error::DuplicateKey`] if a key was added twice, or [`Error::LayoutNotFound`] if no
+    /// layout was found, which only happens when keys' hashes are identical under every seed.
+    pub fn build(self) -> Result<PhfMap<K, V>, Error> {
+        if self.keys.len() > i16::MAX as usize {
+            return Err(Error::CapacityTooLarge);
+        }
+        let bucket_count = self.keys.len().div_ceil(KEYS_PER_BUCKET).max(1);
+        let slot_count = (self.keys.len() + self.keys.len() / 4)
+            .next_power_of_two()
+            .max(1);
+
+        for attempt in 0..ATTEMPTS {
+            let hash_builder = SeededHashBuilder::new(self.seed.wrapping_add(attempt));
+            let hashes: Vec<u32> = self
+                .keys
+                .iter()
+                .map(|key| hash(key, hash_builder))
+                .collect();
+            let mut buckets = vec![Vec::new(); bucket_count];
+            for (index, &hash) in hashes.iter().enumerate() {
+                buckets[bucket_of(hash, bucket_count)].push(index);
+            }
+            // Equal keys always share a bucket, so this finds every duplicate.
+            for bucket in &buckets {
+                for (i, &a) in bucket.iter().enumerate() {
+                    if bucket[i + 1..]
+                        .iter()
+                        .any(|&b| self.keys[a] == self.keys[b])
+                    {
+                        return Err(Error::DuplicateKey);
+                    }
+                }
+            }
+            if let Some((seeds, slots)) = place(&buckets, &hashes, slot_count) {
+                return Ok(PhfMap {
+                    hash_builder,
+                    seeds,
+                    slots,
+                    keys: self.keys.into_boxed_slice(),
+                    values: self.values.into_boxed_slice(),
+                });
+            }
+        }
+        Err(Error::LayoutNotFound)
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PhfBuilder<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut builder = Self::new();
+        builder.extend(iter);
+        builder
+    }
+}
+
+impl<K, V> Extend<(K, V)> for PhfBuilder<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// The seed of each bucket, and the key offset in each slot.
+type Layout = (Box<[u32]>, Box<[Size]>);
+
+/// Give each bucket a seed which places its keys in free slots, largest buckets first, while the
+/// most slots are free. Returns the seeds and the slots, or None if a bucket couldn't be placed.
+fn place(buckets: &[Vec<usize>], hashes: &[u32], slot_count: usize) -> Option<Layout> {
+    let slot_mask = slot_count - 1;
+    let mut order: Vec<usize> = (0..buckets.len()).collect();
+    order.sort_unstable_by_key(|&bucket| core::cmp::Reverse(buckets[bucket].len()));
+    let mut seeds = vec![0; buckets.len()];
+    let mut slots = vec![EMPTY_SLOT; slot_count];
+    let mut placed = Vec::with_capacity(KEYS_PER_BUCKET);
+
+    for bucket in order {
+        let keys = &buckets[bucket];
+        if keys.is_empty() {
+            break;
+        }
+        let seed = (0..SEEDS_PER_BUCKET).find(|&seed| {
+            placed.clear();
+            keys.iter().all(|&index| {
+                let slot = slot_of(hashes[index], seed, slot_mask);
+                let free = slots[slot] == EMPTY_SLOT && !placed.contains(&slot);
+                placed.push(slot);
+                free
+            })
+        })?;
+        seeds[bucket] = seed;
+        for (&index, &slot) in keys.iter().zip(&placed) {
+            // At most `i16::MAX` keys, so every index fits.
+            #[allow(clippy::cast_possible_truncation)]
+            let offset = index as Size + 1;
+            slots[slot] = offset;
+        }
+    }
+    Some((seeds.into_boxed_slice(), slots.into_boxed_slice()))
+}
+
+impl<K, V> PhfMap<K, V>
+where
+    K: Hash + Eq,
+    V: Copy,
+{
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).map(|index| self.values[index])
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._find(key).is_some()
+    }
+
+    /// Iterate over the key-value pairs, in the order they were given to the builder.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_ {
+        self.keys.iter().zip(self.values.iter().copied())
+    }
+
+    /// Get the count of key-value pairs in the map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Find the index of `key` in `keys`.
+    fn _find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = hash(key, self.hash_builder);
+        let seed = self.seeds[bucket_of(hash, self.seeds.len())];
+        let offset = self.slots[slot_of(hash, seed, self.slots.len() - 1)];
+        if offset == EMPTY_SLOT {
+            return None;
+        }
+        let index = usize::from(offset - 1);
+        (self.keys[index].borrow() == key).then_some(index)
+    }
+}
+
+/// Hash `key` to the full 32 bits. Unlike the map's hashes, none are given up to flags, which
+/// makes keys with identical hashes (which no seed can separate) less likely.
+fn hash<Q: Hash + ?Sized>(key: &Q, hash_builder: SeededHashBuilder) -> u32 {
+    let hash = hash_builder.hash_one(key);
+    #[allow(clippy::cast_possible_truncation)]
+    let folded = (hash ^ (hash >> 32)) as u32;
+    folded
+}
+
+/// Map `hash` into `0..bucket_count` by multiplying, so the bucket count needn't be a power of
+/// two.
+fn bucket_of(hash: u32, bucket_count: usize) -> usize {
+    #[allow(clippy::cast_possible_truncation)]
+    let bucket = ((u64::from(hash) * bucket_count as u64) >> 32) as usize;
+    bucket
+}
+
+/// The slot of a key with `hash` in a bucket with `seed`. The seed is mixed into the hash with
+/// the `MurmurHash3` finalizer, so each seed gives the bucket's keys an unrelated set of slots.
+fn slot_of(hash: u32, seed: u32, slot_mask: usize) -> usize {
+    let mut h = hash ^ seed.wrapping_mul(0x9e37_79b9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h as usize & slot_mask
+}
@@ -0,0 +1,94 @@
+use crate::{key_index, Entry, HashMap};
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::ops::Deref;
+
+/// A pinned view of a map, returned by [`HashMap::pin`]. Values borrowed through it live as long
+/// as the view, rather than being copied out one lookup at a time.
+///
+/// Keys are never moved or freed while the map is shared, so the view only ties borrows to the
+/// map. It is the handle that operations which do free memory will pin against.
+pub struct MapRef<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    map: &'map HashMap<K, V, S>,
+}
+
+/// A key and its value, borrowed through a [`MapRef`]. Dereferences to the value it was found
+/// with.
+///
+/// Entries are updated in place with a single atomic write, so a `&V` into the table would race
+/// with updates. Instead, the value is read once when the key is found, and
+/// [`reload`](Self::reload) reads the entry again without hashing or probing.
+pub struct ValueRef<'map, K, V> {
+    key: &'map K,
+    value: V,
+    entry: &'map Atomic<Entry<V>>,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Pin the map, for lookups which borrow from it. See [`MapRef`].
+    pub fn pin(&self) -> MapRef<'_, K, V, S> {
+        MapRef { map: self }
+    }
+}
+
+impl<'map, K, V, S> MapRef<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// The map which is pinned.
+    #[must_use]
+    pub fn map(&self) -> &'map HashMap<K, V, S> {
+        self.map
+    }
+
+    /// Find a key, borrowing its stored key and value for the life of the view. Returns None if
+    /// the key doesn't exist.
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<ValueRef<'map, K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.map._find_entry(key)?;
+        let loaded = entry.load(Ordering::Acquire);
+        Some(ValueRef {
+            key: self.map.get_key(key_index(loaded.key_offset))?,
+            value: loaded.value,
+            entry,
+        })
+    }
+}
+
+impl<'map, K, V: Copy + NoUninit> ValueRef<'map, K, V> {
+    /// The key as stored in the map.
+    #[must_use]
+    pub fn key(&self) -> &'map K {
+        self.key
+    }
+
+    /// Read the entry's current value, which may have been updated since it was found.
+    pub fn reload(&mut self) -> V {
+        self.value = self.entry.load(Ordering::Acquire).value;
+        self.value
+    }
+}
+
+impl<K, V> Deref for ValueRef<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.value
+    }
+}
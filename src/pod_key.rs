@@ -0,0 +1,58 @@
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+/// A key which is compared by its bytes, rather than through its `Eq` implementation.
+///
+/// For plain-old-data keys like `[u8; 16]` or `(u32, u32)`, a byte comparison of a known size
+/// compiles to a few wide loads, where a derived `Eq` may compare field by field. The key's `Eq`
+/// must agree with byte equality, which holds for integers, and arrays and tuples of them, but
+/// not for types whose equal values can have different bytes.
+///
+/// ```
+/// use folklore::{HashMap, PodKey};
+///
+/// let map: HashMap<PodKey<[u8; 16]>, u16> = HashMap::with_capacity(16);
+/// map.insert(PodKey([1; 16]), 1);
+/// assert_eq!(map.get(&PodKey([1; 16])), Some(1));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(transparent)]
+pub struct PodKey<T>(pub T);
+
+impl<T: NoUninit> PartialEq for PodKey<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        bytemuck::bytes_of(&self.0) == bytemuck::bytes_of(&other.0)
+    }
+}
+
+impl<T: NoUninit> Eq for PodKey<T> {}
+
+// Hashes like `T`, so a map with `PodKey<T>` keys can be queried with a `&T`.
+impl<T: Hash> Hash for PodKey<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T> Borrow<T> for PodKey<T> {
+    fn borrow(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for PodKey<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for PodKey<T> {
+    fn from(key: T) -> Self {
+        Self(key)
+    }
+}
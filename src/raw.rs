@@ -0,0 +1,265 @@
+//! The probing table underneath [`HashMap`](crate::HashMap), for building maps with a different
+//! entry layout.
+//!
+//! A [`RawTable`] is a fixed-size, power-of-two array of atomic entries, grouped into buckets of
+//! [`BUCKET_CAPACITY`] entries. It knows nothing about keys or values: each entry type decides
+//! what its bits mean, and which entries are empty. Probing is linear, from the index derived from
+//! a hash, and stops at the first empty entry. Entries are only ever changed with a single CAS.
+//!
+//! The table never moves an entry, and never makes a non-empty entry empty again. Probes rely on
+//! this: an entry which is found to be non-empty stays non-empty, so a probe which passed it never
+//! needs to look back.
+
+use crate::{generic_asserts, util};
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::mem::{align_of, size_of};
+use core::ptr::slice_from_raw_parts;
+
+/// The number of entries in each bucket of a [`RawTable`].
+pub const BUCKET_CAPACITY: usize = 8;
+
+/// An entry layout which can be stored in a [`RawTable`].
+///
+/// # Safety
+/// The all-zero bit pattern must be a valid value of the type, and must be the only value for
+/// which [`is_empty`](Self::is_empty) returns true. A new table is allocated zeroed, so every
+/// entry starts out empty.
+pub unsafe trait RawEntry: Copy + NoUninit {
+    /// Whether this is an empty entry. By default, compares every byte with zero.
+    fn is_empty(&self) -> bool {
+        bytemuck::bytes_of(self).iter().all(|&byte| byte == 0)
+    }
+
+    /// The empty entry.
+    #[must_use]
+    fn empty() -> Self {
+        // SAFETY: Implementors guarantee that all zeroes is a valid (empty) entry.
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+/// The result of [`RawTable::probe`].
+pub enum RawProbe<'table, E> {
+    /// An entry which matched, and its index.
+    Found(&'table Atomic<E>, u16),
+    /// The first empty entry in the probe sequence, and its index. No entry before it matched.
+    Vacant(&'table Atomic<E>, u16),
+    /// Every entry in the probe sequence was non-empty, and none matched.
+    Exhausted,
+}
+
+/// A fixed-size table of atomic entries, probed linearly. See the [module docs](self).
+pub struct RawTable<E> {
+    buckets: *mut Bucket<E>,
+    size_mask: u16,
+    align: usize,
+}
+
+struct Bucket<E> {
+    entries: [Atomic<E>; BUCKET_CAPACITY],
+}
+
+impl<E: RawEntry> RawTable<E> {
+    /// Allocate a table with `slots` entries, all empty.
+    /// Returns None if `slots` isn't a power of two between [`BUCKET_CAPACITY`] and 65536, or the
+    /// allocation fails.
+    #[must_use]
+    pub fn try_new(slots: usize) -> Option<Self> {
+        Self::try_new_aligned(slots, align_of::<Bucket<E>>())
+    }
+
+    /// Allocate a table with `slots` entries, all empty, aligned to at least `align` bytes. The
+    /// allocation is padded to a multiple of `align`, so with a page-sized `align` the table
+    /// occupies whole pages, which can be mapped or registered with a DMA engine.
+    /// Returns None if `slots` isn't a power of two between [`BUCKET_CAPACITY`] and 65536,
+    /// `align` isn't a power of two, or the allocation fails.
+    #[must_use]
+    pub fn try_new_aligned(slots: usize, align: usize) -> Option<Self> {
+        generic_asserts!((E);
+            LOCK_FREE: Atomic::<E>::is_lock_free();
+        );
+        if !slots.is_power_of_two() || slots < BUCKET_CAPACITY {
+            return None;
+        }
+        let size_mask = u16::try_from(slots - 1).ok()?;
+        let layout = util::aligned_array_layout::<Bucket<E>>(bucket_count(slots), align)?;
+        let buckets = unsafe { alloc::alloc::alloc_zeroed(layout) }.cast::<Bucket<E>>();
+        if buckets.is_null() {
+            return None;
+        }
+        Some(Self {
+            buckets,
+            size_mask,
+            align: layout.align(),
+        })
+    }
+
+    /// The alignment of the table's allocation, which is at least the `align` it was created with.
+    #[must_use]
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    /// The number of entries in the table.
+    #[must_use]
+    pub fn slots(&self) -> usize {
+        usize::from(self.size_mask) + 1
+    }
+
+    /// The index at which probes for `hash` start.
+    #[must_use]
+    pub fn index_for(&self, hash: u32) -> u16 {
+        #[allow(clippy::cast_possible_truncation)]
+        let index = util::wrap(hash as usize, usize::from(self.size_mask)) as u16;
+        index
+    }
+
+    /// The index after `index`, wrapping around at the end of the table.
+    #[must_use]
+    pub fn next_index(&self, index: u16) -> u16 {
+        next_index(index, self.size_mask)
+    }
+
+    /// The number of steps a probe takes to get from `from` to `to`, wrapping around the end of
+    /// the table.
+    #[must_use]
+    pub fn distance(&self, from: u16, to: u16) -> u16 {
+        to.wrapping_sub(from) & self.size_mask
+    }
+
+    /// Get the entry at `index`, which is wrapped into the table.
+    #[must_use]
+    pub fn entry(&self, index: u16) -> &Atomic<E> {
+        let index = usize::from(index & self.size_mask);
+        &self.bucket_slice()[index / BUCKET_CAPACITY].entries[index % BUCKET_CAPACITY]
+    }
+
+    /// Get the entry at `index`, without wrapping or bounds checks.
+    /// # Safety
+    /// `index` must be less than [`slots`](Self::slots).
+    #[must_use]
+    pub unsafe fn entry_unchecked(&self, index: u16) -> &Atomic<E> {
+        let index = usize::from(index);
+        debug_assert!(index < self.slots());
+        let bucket = &*self.buckets.add(index / BUCKET_CAPACITY);
+        bucket.entries.get_unchecked(index % BUCKET_CAPACITY)
+    }
+
+    /// Iterate over the buckets of the table, in order.
+    pub fn buckets(&self) -> impl Iterator<Item = &[Atomic<E>]> {
+        self.bucket_slice().iter().map(|bucket| &bucket.entries[..])
+    }
+
+    /// Iterate over every entry in the table, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Atomic<E>> {
+        self.bucket_slice()
+            .iter()
+            .flat_map(|bucket| &bucket.entries)
+    }
+
+    /// Probe the table from `start`, until `is_match` returns true for an entry, or an empty
+    /// entry is reached. Entries are loaded with `Relaxed`; `is_match` must synchronise with
+    /// whatever an entry refers to before reading it.
+    ///
+    /// Every entry but one is visited before the probe gives up, so a table which always has at
+    /// least one empty entry never returns [`RawProbe::Exhausted`].
+    pub fn probe<F>(&self, start: u16, is_match: F) -> RawProbe<'_, E>
+    where
+        F: FnMut(E) -> bool,
+    {
+        self.probe_within(start, self.size_mask, is_match)
+    }
+
+    /// Probe the table from `start` like [`probe`](Self::probe), but give up with
+    /// [`RawProbe::Exhausted`] after visiting `max_len` entries.
+    pub fn probe_within<F>(&self, start: u16, max_len: u16, mut is_match: F) -> RawProbe<'_, E>
+    where
+        F: FnMut(E) -> bool,
+    {
+        let mut index = start & self.size_mask;
+        for _ in 0..max_len.min(self.size_mask) {
+            let entry = self.entry(index);
+            let current = entry.load(Ordering::Relaxed);
+            if current.is_empty() {
+                return RawProbe::Vacant(entry, index);
+            }
+            if is_match(current) {
+                return RawProbe::Found(entry, index);
+            }
+            index = self.next_index(index);
+        }
+        RawProbe::Exhausted
+    }
+
+    /// Find the first entry from `start` for which `is_match` returns true, stopping at the first
+    /// empty entry. See [`probe`](Self::probe).
+    pub fn find<F>(&self, start: u16, is_match: F) -> Option<(&Atomic<E>, u16)>
+    where
+        F: FnMut(E) -> bool,
+    {
+        match self.probe(start, is_match) {
+            RawProbe::Found(entry, index) => Some((entry, index)),
+            _ => None,
+        }
+    }
+
+    /// Find the first empty entry from `start`.
+    #[must_use]
+    pub fn find_empty(&self, start: u16) -> Option<(&Atomic<E>, u16)> {
+        match self.probe(start, |_| false) {
+            RawProbe::Vacant(entry, index) => Some((entry, index)),
+            _ => None,
+        }
+    }
+
+    /// Replace the entry at `index` with `new`, if it is still `current`. Returns the previous
+    /// entry on success, or the entry which was found instead on failure.
+    ///
+    /// `new` must not be empty unless `current` is: probes assume that non-empty entries never
+    /// become empty, and would stop early at the emptied entry.
+    /// # Errors
+    /// The entry which was found, if it wasn't `current`.
+    pub fn cas_entry(&self, index: u16, current: E, new: E) -> Result<E, E> {
+        debug_assert!(current.is_empty() || !new.is_empty());
+        self.entry(index)
+            .compare_exchange(current, new, Ordering::Release, Ordering::Acquire)
+    }
+
+    fn bucket_slice(&self) -> &[Bucket<E>] {
+        unsafe { &*slice_from_raw_parts(self.buckets, bucket_count(self.slots())) }
+    }
+
+    /// The layout of the allocation for a table with `slots` entries.
+    pub(crate) fn layout(slots: usize) -> core::alloc::Layout {
+        core::alloc::Layout::array::<Bucket<E>>(bucket_count(slots)).unwrap()
+    }
+}
+
+impl<E> Drop for RawTable<E> {
+    fn drop(&mut self) {
+        let buckets = bucket_count(usize::from(self.size_mask) + 1);
+        // The same layout was allocated successfully in `try_new_aligned`.
+        if let Some(layout) = util::aligned_array_layout::<Bucket<E>>(buckets, self.align) {
+            unsafe { alloc::alloc::dealloc(self.buckets.cast(), layout) };
+        }
+    }
+}
+
+unsafe impl<E: Send> Send for RawTable<E> {}
+unsafe impl<E: Send + Sync> Sync for RawTable<E> {}
+
+/// The number of bytes allocated for a table with `slots` entries of type `E`.
+pub(crate) const fn table_bytes<E>(slots: usize) -> usize {
+    bucket_count(slots) * size_of::<Bucket<E>>()
+}
+
+pub(crate) const fn next_index(index: u16, size_mask: u16) -> u16 {
+    #[allow(clippy::cast_possible_truncation)]
+    let next = util::wrap(index as usize + 1, size_mask as usize) as u16;
+    next
+}
+
+const fn bucket_count(slots: usize) -> usize {
+    slots / BUCKET_CAPACITY
+}
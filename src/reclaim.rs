@@ -0,0 +1,66 @@
+//! Deferred destruction of memory which other threads may still be reading.
+//!
+//! The map never frees anything while it is shared, so it doesn't need a reclaimer yet. Operations
+//! which replace shared memory, like moving to a larger table, take one of these so the choice of
+//! scheme is left to the application, and the crate stays `no_std` without one.
+
+#[cfg(feature = "epoch")]
+use alloc::boxed::Box;
+
+/// A memory reclamation scheme. Readers [`pin`](Self::pin) before loading shared pointers, and
+/// writers [`retire`](Self::retire) the pointers they unlink, which are destroyed once no reader
+/// could still hold them.
+pub trait Reclaim {
+    /// Held by a thread for as long as it may dereference shared pointers.
+    type Guard;
+
+    /// Announce that this thread is about to read shared memory.
+    fn pin(&self) -> Self::Guard;
+
+    /// Destroy `ptr` once every guard taken before this call has been dropped.
+    /// # Safety
+    /// `ptr` must come from [`Box::into_raw`](alloc::boxed::Box::into_raw), must already be unreachable for threads which pin
+    /// after this call, and must only be retired once.
+    unsafe fn retire<T: Send + 'static>(&self, guard: &Self::Guard, ptr: *mut T);
+
+    /// Destroy whatever retired memory can be destroyed now, such as before a thread goes idle.
+    /// The default does nothing.
+    fn quiesce(&self) {}
+}
+
+/// A [`Reclaim`] scheme which never frees retired memory. Pinning costs nothing, so this suits maps
+/// which are rarely replaced, or which live for the whole program.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverFree;
+
+impl Reclaim for NeverFree {
+    type Guard = ();
+
+    fn pin(&self) -> Self::Guard {}
+
+    unsafe fn retire<T: Send + 'static>(&self, (): &Self::Guard, _ptr: *mut T) {
+        // Leaked on purpose.
+    }
+}
+
+/// A [`Reclaim`] scheme backed by `crossbeam-epoch`'s global collector.
+#[cfg(feature = "epoch")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Epoch;
+
+#[cfg(feature = "epoch")]
+impl Reclaim for Epoch {
+    type Guard = crossbeam_epoch::Guard;
+
+    fn pin(&self) -> Self::Guard {
+        crossbeam_epoch::pin()
+    }
+
+    unsafe fn retire<T: Send + 'static>(&self, guard: &Self::Guard, ptr: *mut T) {
+        guard.defer_unchecked(move || drop(Box::from_raw(ptr)));
+    }
+
+    fn quiesce(&self) {
+        crossbeam_epoch::pin().flush();
+    }
+}
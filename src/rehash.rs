@@ -0,0 +1,117 @@
+use crate::{constants, hashing, Entry, Error, HashMap, HashT, Size};
+use alloc::vec::Vec;
+use atomic::Ordering;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Rebuild the table in place with a new hasher, such as a freshly seeded one, for a map whose
+    /// keys turn out to collide under the current hasher. Every key is hashed again, but the key
+    /// store isn't moved, so key indexes and values are kept, along with their flags. Tombstones
+    /// are dropped, which returns the room they took to the map. Earlier
+    /// [`Checkpoint`](crate::Checkpoint)s can't be restored.
+    /// # Errors
+    /// [`Error::ProbeLimitExceeded`] if a key would be placed beyond the
+    /// [probe limit](Self::set_probe_limit) under the new hasher. The map is left unchanged.
+    pub fn rehash_with(&mut self, hash_builder: S) -> Result<(), Error> {
+        let old_entries: Vec<Entry<V>> = self
+            .table
+            .iter()
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .collect();
+        // Indexed like the key store. Slots which hold no key are never referenced by an entry.
+        let hashes: Vec<HashT> = (0..self.key_store.len())
+            .map(|index| {
+                self.key_store
+                    .get(index)
+                    .map_or(0, |stored| hashing::hash(&stored.key, &hash_builder))
+            })
+            .collect();
+
+        let mut live: Vec<Entry<V>> = old_entries.iter().copied().filter(Entry::is_live).collect();
+        // Place keys in the order they were inserted, so the new layout doesn't depend on the old.
+        live.sort_unstable_by_key(|entry| entry.key_offset);
+        for entry in self.table.iter() {
+            entry.store(Entry::EMPTY, Ordering::Relaxed);
+        }
+        for mut entry in live {
+            let key_hash = hashes[crate::key_index(entry.key_offset)];
+            entry.key_hash = key_hash | (entry.key_hash & !constants::HASH_MASK);
+            let home = self._index(key_hash);
+            match self.table.find_empty(home) {
+                Some((slot, index)) if self.table.distance(home, index) < self.probe_limit => {
+                    slot.store(entry, Ordering::Relaxed);
+                }
+                _ => {
+                    for (slot, entry) in self.table.iter().zip(old_entries) {
+                        slot.store(entry, Ordering::Relaxed);
+                    }
+                    return Err(Error::ProbeLimitExceeded);
+                }
+            }
+        }
+
+        for (index, key_hash) in hashes.into_iter().enumerate() {
+            if let Some(stored) = self.key_store.get_mut(index) {
+                stored.key_hash = key_hash;
+            }
+        }
+        *self.tombstones.get_mut() = 0;
+        self.hash_builder = hash_builder;
+        // Earlier checkpoints hold entries placed and hashed with the old hasher.
+        self._invalidate_checkpoints();
+        Ok(())
+    }
+
+    /// Rebuild the table in place without its tombstones, then drop the keys they left behind in
+    /// the key store, returning the room taken by removed, rekeyed and rolled-back keys to the
+    /// map. Returns the number of tombstones dropped.
+    ///
+    /// Keys either stay put or move closer to where their probes start, so none ends up beyond
+    /// the [probe limit](Self::set_probe_limit). Key indexes change, as with
+    /// [`compact_key_store`](Self::compact_key_store).
+    pub fn purge_tombstones(&mut self) -> usize {
+        let slots = self.table.slots();
+        // Start after an entry which was empty to begin with. No probe sequence crosses it, so
+        // every key is visited after the keys in front of it in its probe sequence.
+        let Some(start) = (0..slots)
+            .filter_map(|index| Size::try_from(index).ok())
+            .find(|&index| {
+                self.table.entry(index).load(Ordering::Relaxed).key_offset == constants::EMPTY_KEY
+            })
+        else {
+            return 0;
+        };
+        let mut purged = 0;
+        let mut index = start;
+        for _ in 0..slots {
+            index = self.table.next_index(index);
+            let slot = self.table.entry(index);
+            let entry = slot.load(Ordering::Relaxed);
+            if entry.key_offset == constants::TOMBSTONE {
+                slot.store(Entry::EMPTY, Ordering::Relaxed);
+                purged += 1;
+            } else if entry.has_key() {
+                // Lift the key out and place it again. The entry it came from is empty now, so it
+                // lands there or earlier in its probe sequence.
+                slot.store(Entry::EMPTY, Ordering::Relaxed);
+                let home = self._index(entry.key_hash & constants::HASH_MASK);
+                self.table
+                    .find_empty(home)
+                    .map_or(slot, |(empty, _)| empty)
+                    .store(entry, Ordering::Relaxed);
+            }
+        }
+        *self.tombstones.get_mut() = 0;
+        if purged > 0 {
+            self._invalidate_checkpoints();
+        }
+        self.compact_key_store();
+        purged
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{constants, Entry, Error, HashMap, InsertFailure};
+use atomic::Ordering;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Move the value (and flags) of the key `old` to the key `new`, which mustn't be in the map.
+    /// Returns the value which was moved.
+    ///
+    /// `new` is inserted first, then `old` is replaced with a tombstone, so a reader never sees
+    /// neither key. In between, both keys are visible with the same value. An update of `old` in
+    /// that window is carried over to `new`, unless `new` was updated too. Once `old` is gone,
+    /// updates of it fail as they would for any missing key.
+    ///
+    /// The tombstone occupies the table, so every move uses up one entry of the map's capacity,
+    /// until `old` is inserted again and takes its entry back, the map's tombstones are
+    /// [purged](Self::purge_tombstones), or the map is rebuilt with
+    /// [`compact_filter`](Self::compact_filter).
+    /// # Errors
+    /// - [`Error::KeyNotFound`] if `old` isn't in the map, or another thread moved it first.
+    /// - An error from [`try_insert`](Self::try_insert) if `new` can't be inserted.
+    pub fn rekey<Q>(&self, old: &Q, new: K) -> Result<V, Error>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old_entry = self._find_entry(old).ok_or(Error::KeyNotFound)?;
+        let moving = old_entry.load(Ordering::Acquire);
+        let flags = moving.key_hash & !(constants::HASH_MASK | constants::PENDING);
+        let new_hash = self._hash::<K>(&new) | flags;
+        let (value, key_index) = self
+            ._insert_hashed(new, new_hash, |_| moving.value)
+            .map_err(InsertFailure::into_error)?;
+        // SAFETY: The key was just published, and is never removed.
+        let new_key = unsafe { self._stored_key(key_index) };
+        let new_entry = self._find_entry::<K>(new_key);
+
+        let Some(last) = self._kill_entry(old_entry) else {
+            // Another thread moved `old` first, so take `new` back out.
+            if let Some(entry) = new_entry {
+                self._kill_entry(entry);
+            }
+            return Err(Error::KeyNotFound);
+        };
+
+        let unchanged = |a: &V, b: &V| bytemuck::bytes_of(a) == bytemuck::bytes_of(b);
+        if !unchanged(&last.value, &value) {
+            if let Some(entry) = new_entry {
+                self._update_entry(entry, |current| {
+                    unchanged(&current.value, &value).then_some(Entry {
+                        value: last.value,
+                        ..current
+                    })
+                });
+            }
+        }
+        Ok(last.value)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::util::SpinLock;
+use crate::{HashMap, Logged, WriteLogger};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+
+/// A [`WriteLogger`] which maps each value to the indices of the keys holding it, so the keys with
+/// a given value can be found without scanning the map. Created by
+/// [`HashMap::with_reverse_index`].
+///
+/// Mutations are logged after they are made, so two updates of one key may be logged in the
+/// opposite order to the one in which they were made. The index therefore remembers every value a
+/// key was logged with, and [`Logged::keys_with_value`] checks each candidate against the map,
+/// forgetting the ones which no longer hold the value.
+pub struct ReverseIndex<V> {
+    keys: SpinLock<BTreeMap<V, BTreeSet<usize>>>,
+}
+
+impl<V: Ord> ReverseIndex<V> {
+    fn add(&self, key_index: usize, value: V) {
+        self.keys
+            .with(|keys| keys.entry(value).or_default().insert(key_index));
+    }
+}
+
+impl<K, V: Ord> WriteLogger<K, V> for ReverseIndex<V> {
+    fn log_insert(&self, key_index: usize, _key: &K, value: V) {
+        self.add(key_index, value);
+    }
+
+    fn log_update(&self, key_index: usize, value: V) {
+        self.add(key_index, value);
+    }
+
+    // The key's indices are forgotten when `keys_with_value` finds it missing from the map.
+    fn log_remove(&self, _key_index: usize) {}
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit + Ord,
+    S: BuildHasher,
+{
+    /// Wrap the map, maintaining a [`ReverseIndex`] of every insert or update made through the
+    /// returned handle. The index starts with the map's current contents.
+    pub fn with_reverse_index(&self) -> Logged<'_, K, V, S, ReverseIndex<V>> {
+        let index = ReverseIndex {
+            keys: SpinLock::new(BTreeMap::new()),
+        };
+        for key_index in 0..self.key_store.len() {
+            if let Some(value) = self.get_key(key_index).and_then(|key| self.get(key)) {
+                index.add(key_index, value);
+            }
+        }
+        self.with_logger(index)
+    }
+}
+
+impl<'map, K, V, S> Logged<'map, K, V, S, ReverseIndex<V>>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit + Ord,
+    S: BuildHasher,
+{
+    /// Get the keys which hold `value`, in insertion order. Only mutations made through this
+    /// handle are indexed.
+    pub fn keys_with_value(&self, value: V) -> Vec<&'map K> {
+        let map = self.map();
+        self.logger().keys.with(|keys| {
+            let Some(indices) = keys.get_mut(&value) else {
+                return Vec::new();
+            };
+            let mut found = Vec::with_capacity(indices.len());
+            indices.retain(|&key_index| {
+                let key = map.get_key(key_index);
+                match key {
+                    Some(key) if map.get(key) == Some(value) => {
+                        found.push(key);
+                        true
+                    }
+                    _ => false,
+                }
+            });
+            if indices.is_empty() {
+                keys.remove(&value);
+            }
+            found
+        })
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{generic_asserts, hashing, Error, LeBytes};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hasher;
+use core::marker::PhantomData;
+
+/// Identifies a ROM image, and the version of the format.
+const MAGIC: [u8; 4] = *b"flr\x01";
+/// The magic, then the key and value sizes as `u16`s, and the number of keys and slots as `u32`s.
+const HEADER_SIZE: usize = MAGIC.len() + 2 * 2 + 2 * 4;
+/// The largest key an image can hold. Lookups encode the key on the stack, so its size is bounded.
+const MAX_KEY_SIZE: usize = 64;
+/// The first byte of a slot which holds a key. Empty slots are all zeroes.
+const OCCUPIED: u8 = 1;
+
+/// Writes the byte image of a fully populated, read-only table, which a [`RomMap`] reads in
+/// place. The image can be embedded with `include_bytes!` or flashed, and is the same whichever
+/// target builds or reads it.
+///
+/// Each slot of the table is a byte marking it occupied, then the key and value in their
+/// [`LeBytes`] encodings. Keys are placed by hashing their encoding with FNV-1a, and probing
+/// forward from there, so keys up to 64 bytes are supported, and two keys are equal if their
+/// encodings are.
+///
+/// ```
+/// use folklore::{RomBuilder, RomMap};
+///
+/// let mut builder = RomBuilder::new();
+/// builder.insert(0x10_u32, 1_u16);
+/// builder.insert(0x20, 2);
+/// let image = builder.build().unwrap();
+///
+/// let map = RomMap::<u32, u16>::new(&image).unwrap();
+/// assert_eq!(map.get(&0x20), Some(2));
+/// assert_eq!(map.get(&0x30), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RomBuilder<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+/// A read-only map over an image written by [`RomBuilder`]. Lookups read the image where it is,
+/// so the map takes no memory beyond the image and a few words, and doesn't need an allocator.
+#[derive(Debug, Clone, Copy)]
+pub struct RomMap<'image, K, V> {
+    table: &'image [u8],
+    len: usize,
+    slot_mask: usize,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Default for RomBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> RomBuilder<K, V> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a key-value pair. Duplicate keys are reported by [`build`](Self::build).
+    pub fn insert(&mut self, key: K, value: V) {
+        self.entries.push((key, value));
+    }
+
+    /// The number of key-value pairs added so far.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no key-value pairs have been added.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: LeBytes, V: LeBytes> RomBuilder<K, V> {
+    /// Write the image. Its table has as many slots as a [`HashMap`](crate::HashMap) with room
+    /// for exactly these keys.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if there are more than `i16::MAX` keys, or
+    /// [`Error::DuplicateKey`] if a key was added twice.
+    pub fn build(&self) -> Result<Vec<u8>, Error> {
+        generic_asserts!((K: LeBytes);
+            KEY_SIZE: K::SIZE <= MAX_KEY_SIZE;
+        );
+        if self.entries.len() > i16::MAX as usize {
+            return Err(Error::CapacityTooLarge);
+        }
+        let slots = hashing::table_slots(self.entries.len());
+        let slot_size = slot_size::<K, V>();
+        let mut image = vec![0; HEADER_SIZE + slots * slot_size];
+        let (header, table) = image.split_at_mut(HEADER_SIZE);
+        header[..MAGIC.len()].copy_from_slice(&MAGIC);
+        // A value too large for its size to fit is written as `u16::MAX`, which loading rejects.
+        // The key size is bounded by `MAX_KEY_SIZE`, and the counts by `1 << 16`.
+        let to_u16 = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+        to_u16(K::SIZE).write_le(&mut header[4..6]);
+        to_u16(V::SIZE).write_le(&mut header[6..8]);
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            (self.entries.len() as u32).write_le(&mut header[8..12]);
+            (slots as u32).write_le(&mut header[12..16]);
+        }
+
+        let mut buffer = [0; MAX_KEY_SIZE];
+        let key = &mut buffer[..K::SIZE];
+        for (k, value) in &self.entries {
+            k.write_le(key);
+            let mut index = hashing::home_index(hash(key), slots);
+            loop {
+                let slot = &mut table[index * slot_size..][..slot_size];
+                let (state, rest) = slot.split_at_mut(1);
+                let (stored_key, stored_value) = rest.split_at_mut(K::SIZE);
+                if state[0] != OCCUPIED {
+                    state[0] = OCCUPIED;
+                    stored_key.copy_from_slice(key);
+                    value.write_le(stored_value);
+                    break;
+                }
+                if stored_key == key {
+                    return Err(Error::DuplicateKey);
+                }
+                index = (index + 1) & (slots - 1);
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for RomBuilder<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<K, V> Extend<(K, V)> for RomBuilder<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        self.entries.extend(iter);
+    }
+}
+
+impl<'image, K: LeBytes, V: LeBytes> RomMap<'image, K, V> {
+    /// Check that `image` was written by a [`RomBuilder`] with these key and value types, and
+    /// read it in place.
+    /// # Errors
+    /// [`Error::InvalidSnapshot`] if `image` isn't an image of a map with these key and value
+    /// types.
+    pub fn new(image: &'image [u8]) -> Result<Self, Error> {
+        generic_asserts!((K: LeBytes);
+            KEY_SIZE: K::SIZE <= MAX_KEY_SIZE;
+        );
+        let (header, table) = image
+            .split_at_checked(HEADER_SIZE)
+            .ok_or(Error::InvalidSnapshot)?;
+        let field = |range: core::ops::Range<usize>| match range.len() {
+            2 => usize::from(u16::read_le(&header[range])),
+            _ => usize::try_from(u32::read_le(&header[range])).unwrap_or(usize::MAX),
+        };
+        let (key_size, value_size) = (field(4..6), field(6..8));
+        let (len, slots) = (field(8..12), field(12..16));
+        if header[..MAGIC.len()] != MAGIC
+            || key_size != K::SIZE
+            || value_size != V::SIZE
+            || !slots.is_power_of_two()
+            // A full table would leave probes for missing keys nowhere to stop.
+            || len >= slots
+            || Some(table.len()) != slots.checked_mul(slot_size::<K, V>())
+        {
+            return Err(Error::InvalidSnapshot);
+        }
+        Ok(Self {
+            table,
+            len,
+            slot_mask: slots - 1,
+            types: PhantomData,
+        })
+    }
+
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self._find(key).map(|slot| V::read_le(&slot[1 + K::SIZE..]))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self._find(key).is_some()
+    }
+
+    /// Iterate over the key-value pairs, in the order they are laid out in the image.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + 'image {
+        self.table
+            .chunks_exact(slot_size::<K, V>())
+            .filter(|slot| slot[0] == OCCUPIED)
+            .map(|slot| {
+                let (key, value) = slot[1..].split_at(K::SIZE);
+                (K::read_le(key), V::read_le(value))
+            })
+    }
+
+    /// Get the count of key-value pairs in the map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Find the slot which holds `key`.
+    fn _find(&self, key: &K) -> Option<&'image [u8]> {
+        let mut buffer = [0; MAX_KEY_SIZE];
+        let encoded = &mut buffer[..K::SIZE];
+        key.write_le(encoded);
+        let slot_size = slot_size::<K, V>();
+        let mut index = hashing::home_index(hash(encoded), self.slot_mask + 1);
+        // `new` checked that there is an empty slot, but a corrupted image may not have one.
+        for _ in 0..=self.slot_mask {
+            let slot = &self.table[index * slot_size..][..slot_size];
+            if slot[0] != OCCUPIED {
+                return None;
+            }
+            if slot[1..=K::SIZE] == *encoded {
+                return Some(slot);
+            }
+            index = (index + 1) & self.slot_mask;
+        }
+        None
+    }
+}
+
+/// The size of a slot: the byte marking it occupied, then the key and the value.
+const fn slot_size<K: LeBytes, V: LeBytes>() -> usize {
+    1 + K::SIZE + V::SIZE
+}
+
+/// FNV-1a over a key's encoding, which is the same on every target.
+fn hash(key: &[u8]) -> u32 {
+    let mut hasher = hash32::FnvHasher::default();
+    hasher.write(key);
+    #[allow(clippy::cast_possible_truncation)]
+    let hash = hasher.finish() as u32;
+    hash
+}
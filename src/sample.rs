@@ -0,0 +1,56 @@
+use crate::HashMap;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+use rand_core::RngCore;
+
+/// The number of slots of the key store drawn for one sample before giving up. Slots whose keys
+/// aren't in the map are skipped, so this only runs out when few of them are live.
+const DRAWS_PER_SAMPLE: usize = 64;
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Yield `n` keys drawn uniformly at random, with replacement, such as for picking eviction
+    /// candidates. Keys are drawn from the key store, which is dense, so each one costs a few
+    /// random reads rather than a scan of the table.
+    ///
+    /// Stops early if the map is empty, or if the key store holds so many keys which are no
+    /// longer in the map that a live one isn't found after a number of draws. Compacting the key
+    /// store fixes the latter.
+    /// ```
+    /// use rand::SeedableRng;
+    ///
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(64);
+    /// for i in 0..50 {
+    ///     map.insert(i, 0);
+    /// }
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    /// let sample: Vec<_> = map.sample_keys(5, &mut rng).collect();
+    /// assert_eq!(sample.len(), 5);
+    /// assert!(sample.iter().all(|&&key| key < 50));
+    /// ```
+    pub fn sample_keys<'a, R>(&'a self, n: usize, rng: &'a mut R) -> impl Iterator<Item = &'a K>
+    where
+        R: RngCore + ?Sized,
+    {
+        // Keys published after this point aren't sampled, like keys inserted after an iterator
+        // is created.
+        let len = self.key_store.len();
+        (0..n).map_while(move |_| {
+            (0..DRAWS_PER_SAMPLE).find_map(|_| {
+                let index = below(rng.next_u32(), len)?;
+                self._get_stored(index).map(|(key, _)| key)
+            })
+        })
+    }
+}
+
+/// Map a random `u32` into `0..n` by multiplying. Returns None if `n` is zero.
+fn below(random: u32, n: usize) -> Option<usize> {
+    // The key store holds at most `1 << 16` keys, so the bias is at most `n / 2^32`.
+    let n = u64::try_from(n).ok().filter(|&n| n > 0)?;
+    usize::try_from((u64::from(random) * n) >> 32).ok()
+}
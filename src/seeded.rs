@@ -0,0 +1,58 @@
+use crate::{Error, HashMap};
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash, Hasher};
+
+/// A hasher with an explicit seed, so the layout of a map's table (and so the order its probes
+/// visit entries) is the same on every run. A failing test can then be replayed with the same
+/// seed, which isn't possible with the random seeds used by the `std` feature.
+///
+/// Keys are hashed with FNV, like the default hasher without `std`, after the seed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeededHashBuilder {
+    seed: u64,
+}
+
+impl SeededHashBuilder {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    #[must_use]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl BuildHasher for SeededHashBuilder {
+    type Hasher = hash32::FnvHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        let mut hasher = hash32::FnvHasher::default();
+        hasher.write_u64(self.seed);
+        hasher
+    }
+}
+
+impl<K, V> HashMap<K, V, SeededHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// Create a map with the given capacity, which hashes keys with the given seed.
+    /// See [`SeededHashBuilder`].
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    #[must_use]
+    pub fn with_capacity_and_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_capacity_and_hasher(capacity, SeededHashBuilder::new(seed))
+    }
+
+    /// Create a map with the given capacity, which hashes keys with the given seed, returning an
+    /// error instead of panicking if the capacity is too large or the allocation fails.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity_and_seed(capacity: usize, seed: u64) -> Result<Self, Error> {
+        Self::try_with_capacity_and_hasher(capacity, SeededHashBuilder::new(seed))
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{DefaultHashBuilder, Error, HashMap};
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// A lock-free set, which is a [`HashMap`] whose values are never read.
+///
+/// Every entry is one word whatever it holds, so the unused value costs no memory, and each
+/// operation is still a single CAS.
+///
+/// ```
+/// let set = folklore::HashSet::<&str>::with_capacity(8);
+/// assert!(set.insert("a"));
+/// assert!(!set.insert("a"));
+/// assert!(set.contains("a"));
+/// assert!(set.remove("a"));
+/// assert!(set.is_empty());
+/// ```
+pub struct HashSet<K, S = DefaultHashBuilder>
+where
+    K: Hash + Eq,
+{
+    map: HashMap<K, u16, S>,
+}
+
+impl<K> HashSet<K, DefaultHashBuilder>
+where
+    K: Hash + Eq,
+{
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Create a set with the given capacity, returning an error instead of panicking if the
+    /// capacity is too large or the allocation fails.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        HashMap::try_with_capacity(capacity).map(|map| Self { map })
+    }
+}
+
+impl<K, S> HashSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Create a set with the given capacity, which uses `hash_builder` to hash keys.
+    /// # Panics
+    /// If `capacity > i16::MAX`
+    #[must_use]
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    /// Add a key to the set.
+    /// Returns true if the key was added, false if the set is full or the key is already in it.
+    pub fn insert(&self, key: K) -> bool {
+        self.map.insert(key, 0)
+    }
+
+    /// Add a key to the set, returning the reason on failure.
+    /// # Errors
+    /// As for [`HashMap::try_insert`].
+    pub fn try_insert(&self, key: K) -> Result<(), Error> {
+        self.map.try_insert(key, 0)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Remove a key from the set, as for [`HashMap::remove`]. Returns true if it was in the set.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(key).is_some()
+    }
+
+    /// Returns true if every key in this set is also in `other`.
+    pub fn is_subset<S2>(&self, other: &HashSet<K, S2>) -> bool
+    where
+        S2: BuildHasher,
+    {
+        self.map.is_submap_of(&other.map)
+    }
+
+    /// Iterate over the keys, in insertion order, with the same snapshot semantics as
+    /// [`HashMap::iter`].
+    pub fn iter(&self) -> impl Iterator<Item = &K> + '_ {
+        self.map.keys()
+    }
+
+    /// Get the count of keys in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the set contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// The number of keys the set can hold.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+}
+
+impl<K, S> Clone for HashSet<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K, S> Default for HashSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::HashMap;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Returns true if every key in `keys` is in the map.
+    pub fn contains_all<'q, Q, I>(&self, keys: I) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'q,
+        I: IntoIterator<Item = &'q Q>,
+    {
+        keys.into_iter().all(|key| self.contains_key(key))
+    }
+
+    /// Returns true if every entry in this map is also in `other`, with an equal value.
+    pub fn is_submap_of<S2>(&self, other: &HashMap<K, V, S2>) -> bool
+    where
+        V: PartialEq,
+        S2: BuildHasher,
+    {
+        self.is_submap_of_by(other, |a, b| a == b)
+    }
+
+    /// Returns true if every key in this map is also in `other`, and `eq` returns true for the
+    /// values associated with it in this map and `other`. To compare only the keys, pass
+    /// `|_, _| true`.
+    pub fn is_submap_of_by<S2, F>(&self, other: &HashMap<K, V, S2>, mut eq: F) -> bool
+    where
+        S2: BuildHasher,
+        F: FnMut(V, V) -> bool,
+    {
+        self.len() <= other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key).is_some_and(|other| eq(value, other)))
+    }
+
+    /// Returns true if every entry in `other` is also in this map, with an equal value.
+    pub fn is_supermap_of<S2>(&self, other: &HashMap<K, V, S2>) -> bool
+    where
+        V: PartialEq,
+        S2: BuildHasher,
+    {
+        other.is_submap_of(self)
+    }
+
+    /// Iterate over the keys which are in this map, `other`, or both. Keys in this map are
+    /// yielded first, followed by the keys only in `other`.
+    pub fn union_keys<'a, S2>(
+        &'a self,
+        other: &'a HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a
+    where
+        S2: BuildHasher,
+    {
+        self.keys().chain(other.difference_keys(self))
+    }
+
+    /// Iterate over the keys which are in both this map and `other`.
+    pub fn intersection_keys<'a, S2>(
+        &'a self,
+        other: &'a HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a
+    where
+        S2: BuildHasher,
+    {
+        self.keys().filter(|key| other.contains_key(*key))
+    }
+
+    /// Iterate over the keys which are in this map, but not in `other`.
+    pub fn difference_keys<'a, S2>(
+        &'a self,
+        other: &'a HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a
+    where
+        S2: BuildHasher,
+    {
+        self.keys().filter(|key| !other.contains_key(*key))
+    }
+
+    /// Iterate over the keys which are in exactly one of this map and `other`. Keys only in this
+    /// map are yielded first, followed by the keys only in `other`.
+    pub fn symmetric_difference_keys<'a, S2>(
+        &'a self,
+        other: &'a HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a
+    where
+        S2: BuildHasher,
+    {
+        self.difference_keys(other)
+            .chain(other.difference_keys(self))
+    }
+
+    /// Iterate over the key-value pairs whose keys are in exactly one of this map and `other`,
+    /// in the same order as [`symmetric_difference_keys`](Self::symmetric_difference_keys).
+    pub fn symmetric_difference<'a, S2>(
+        &'a self,
+        other: &'a HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = (&'a K, V)> + 'a
+    where
+        S2: BuildHasher,
+    {
+        self.iter()
+            .filter(|(key, _)| !other.contains_key(*key))
+            .chain(other.iter().filter(|(key, _)| !self.contains_key(*key)))
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{hashing, DefaultHashBuilder, Entry, Error, HashMap, HashT, InsertFailure};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// The hash bits below this are left to the shards, whose table indexes use at most 16 bits.
+const SHARD_SHIFT: u32 = 16;
+/// The most shards a map can have, one for each value of the hash bits no table index uses.
+const MAX_SHARDS: usize = 1 << (hashing::HASH_BITS - SHARD_SHIFT);
+/// The largest share of the capacity given to one shard. Each shard has room for an eighth more
+/// than its share, within the `i16::MAX` limit, because keys don't split evenly between shards.
+const MAX_SHARE: usize = i16::MAX as usize / 9 * 8;
+
+/// A map which splits its keys between [`HashMap`]s, to hold more than the `i16::MAX` keys one
+/// map can.
+///
+/// A single map's entries are one word, so every operation is a single 64-bit CAS, which leaves
+/// 16 bits for a key's offset. Rather than widen the entries, this picks a shard for each key from
+/// hash bits which no table index uses, so keys are hashed once, and spread evenly over shards of
+/// unrelated sizes. Up to 2048 shards give a capacity of around 59 million keys.
+///
+/// ```
+/// let map = folklore::ShardedHashMap::<u32, u16>::with_capacity(100_000);
+/// for i in 0..100_000 {
+///     assert!(map.insert(i, 1));
+/// }
+/// assert_eq!(map.len(), 100_000);
+/// assert_eq!(map.get(&99_999), Some(1));
+/// ```
+pub struct ShardedHashMap<K, V, S = DefaultHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    shards: Box<[HashMap<K, V, S>]>,
+    capacity: usize,
+    hash_builder: S,
+}
+
+impl<K, V> ShardedHashMap<K, V, DefaultHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// # Panics
+    /// If the capacity is too large, or the allocation fails. See
+    /// [`try_with_capacity`](Self::try_with_capacity).
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+
+    /// Create a map with the given capacity, returning an error instead of panicking if the
+    /// capacity is too large or the allocation fails.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if the capacity needs more than 2048 shards, or
+    /// [`Error::AllocationFailed`].
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        Self::try_with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher + Clone,
+{
+    /// # Panics
+    /// If the capacity is too large, or the allocation fails. See
+    /// [`try_with_capacity_and_hasher`](Self::try_with_capacity_and_hasher).
+    #[must_use]
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        match Self::try_with_capacity_and_hasher(capacity, hash_builder) {
+            Ok(map) => map,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    /// Create a map with the given capacity, which uses `hash_builder` to hash keys. Each shard
+    /// gets a clone of it.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if the capacity needs more than 2048 shards, or
+    /// [`Error::AllocationFailed`].
+    pub fn try_with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Result<Self, Error> {
+        let shard_count = capacity.div_ceil(MAX_SHARE).max(1).next_power_of_two();
+        if shard_count > MAX_SHARDS {
+            return Err(Error::CapacityTooLarge);
+        }
+        let share = capacity.div_ceil(shard_count);
+        // One shard needs no slack, as it holds every key.
+        let shard_capacity = if shard_count == 1 {
+            share
+        } else {
+            share + share / 8
+        };
+        let shards = (0..shard_count)
+            .map(|_| HashMap::try_with_capacity_and_hasher(shard_capacity, hash_builder.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            shards: shards.into_boxed_slice(),
+            capacity,
+            hash_builder,
+        })
+    }
+}
+
+impl<K, V, S> ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Insert a key-value pair into the map.
+    /// Returns true if the key was inserted, false if its shard is full or the key already exists.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, returning the reason on failure.
+    /// # Errors
+    /// As for [`HashMap::try_insert`]. [`Error::Full`] means the key's shard is full, which
+    /// before the map holds `capacity` keys only happens if they hash very unevenly.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), Error> {
+        let (shard, key_hash) = self._shard(&key);
+        shard
+            ._insert_hashed(key, key_hash, |_| value)
+            .map(|_| ())
+            .map_err(InsertFailure::into_error)
+    }
+
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (shard, key_hash) = self._shard(key);
+        shard
+            ._find_entry_hashed(key, key_hash)
+            .map(|entry| entry.load(atomic::Ordering::Relaxed).value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Update the value associated with a key. Returns the previous value, or None if the key
+    /// doesn't exist.
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.fetch_update(key, |_| Some(value))
+    }
+
+    /// Update the value associated with a key with `f`, as for [`HashMap::fetch_update`].
+    /// Returns the previous value, or None if the key doesn't exist or `f` returned None.
+    pub fn fetch_update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(V) -> Option<V>,
+    {
+        let (shard, key_hash) = self._shard(key);
+        let entry = shard._find_entry_hashed(key, key_hash)?;
+        shard
+            ._update_entry(entry, |current| {
+                Some(Entry {
+                    value: f(current.value)?,
+                    ..current
+                })
+            })
+            .map(|previous| previous.value)
+    }
+
+    /// Remove a key from its shard, as for [`HashMap::remove`]. Returns its value, or None if it
+    /// doesn't exist.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (shard, key_hash) = self._shard(key);
+        shard._remove_entry(shard._find_entry_hashed(key, key_hash)?)
+    }
+
+    /// Iterate over the key-value pairs, one shard at a time, each in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_ {
+        self.shards.iter().flatten()
+    }
+
+    /// Iterate over the keys, in the same order as [`iter`](Self::iter).
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.shards.iter().flat_map(HashMap::keys)
+    }
+
+    /// Iterate over the values, in the same order as [`iter`](Self::iter).
+    pub fn values(&self) -> impl Iterator<Item = V> + '_ {
+        self.shards.iter().flat_map(HashMap::values)
+    }
+
+    /// Get the count of key-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(HashMap::len).sum()
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(HashMap::is_empty)
+    }
+
+    /// The number of key-value pairs the map was created to hold. Each shard has some room
+    /// beyond its share, so the map may hold a few more.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of shards the keys are split between.
+    #[must_use]
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Hash `key`, and pick its shard from the hash.
+    fn _shard<Q>(&self, key: &Q) -> (&HashMap<K, V, S>, HashT)
+    where
+        Q: Hash + ?Sized,
+    {
+        let key_hash = hashing::hash(key, &self.hash_builder);
+        let index = (key_hash >> SHARD_SHIFT) as usize & (self.shards.len() - 1);
+        (&self.shards[index], key_hash)
+    }
+}
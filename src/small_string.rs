@@ -0,0 +1,146 @@
+use crate::generic_asserts;
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+/// A string key which stores up to `N` bytes inline, and only allocates for longer strings.
+///
+/// The key store holds keys by value, so a map with `SmallString` keys stores short keys directly
+/// in the key store, without an allocation per key. It hashes and compares like a `str`, so maps
+/// can be queried with `&str`:
+/// ```
+/// use folklore::{HashMap, SmallString};
+///
+/// let map: HashMap<SmallString, u16> = HashMap::with_capacity(16);
+/// map.insert("short".into(), 1);
+/// map.insert("a key which is too long to be stored inline".into(), 2);
+/// assert_eq!(map.get("short"), Some(1));
+/// ```
+#[derive(Clone)]
+pub struct SmallString<const N: usize = 22>(Repr<N>);
+
+#[derive(Clone)]
+enum Repr<const N: usize> {
+    Inline { len: u8, bytes: [u8; N] },
+    Heap(Box<str>),
+}
+
+impl<const N: usize> SmallString<N> {
+    /// The longest string which is stored inline.
+    pub const INLINE_CAPACITY: usize = N;
+
+    /// Create a string, storing it inline if it is no longer than `N` bytes.
+    /// # Panics
+    /// At compile time, if `N` is greater than 255.
+    #[must_use]
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        generic_asserts!((; const N: usize);
+            INLINE_LEN_FITS_U8: N <= u8::MAX as usize;
+        );
+        match u8::try_from(s.len()) {
+            Ok(len) if s.len() <= N => {
+                let mut bytes = [0; N];
+                bytes[..s.len()].copy_from_slice(s.as_bytes());
+                Self(Repr::Inline { len, bytes })
+            }
+            _ => Self(Repr::Heap(s.into())),
+        }
+    }
+
+    /// Returns true if the string is stored inline, rather than on the heap.
+    #[must_use]
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // SAFETY: The bytes were copied from a `str`, up to `len`.
+            Repr::Inline { len, bytes } => unsafe {
+                core::str::from_utf8_unchecked(&bytes[..usize::from(*len)])
+            },
+            Repr::Heap(s) => s,
+        }
+    }
+}
+
+impl<const N: usize> Deref for SmallString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Borrow<str> for SmallString<N> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> AsRef<str> for SmallString<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// Hashes like a `str`, as `Borrow<str>` requires.
+impl<const N: usize> Hash for SmallString<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl<const N: usize> PartialEq for SmallString<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for SmallString<N> {}
+
+impl<const N: usize> PartialOrd for SmallString<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for SmallString<N> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl<const N: usize> From<&str> for SmallString<N> {
+    fn from(s: &str) -> Self {
+        Self::from_str(s)
+    }
+}
+
+impl<const N: usize> From<String> for SmallString<N> {
+    /// Reuses the string's allocation if it is too long to be stored inline.
+    fn from(s: String) -> Self {
+        if s.len() <= N {
+            Self::from_str(&s)
+        } else {
+            Self(Repr::Heap(s.into_boxed_str()))
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for SmallString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for SmallString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
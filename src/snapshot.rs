@@ -0,0 +1,162 @@
+use crate::{constants, Entry, Error, HashMap};
+use alloc::vec::Vec;
+use atomic::Ordering;
+use bytemuck::NoUninit;
+use core::hash::{BuildHasher, Hash};
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicU64;
+
+/// The source of every map's checkpoint epoch. Shared by all maps, so an epoch is never reused,
+/// and a checkpoint from one map never matches another, even one given the same allocation.
+static NEXT_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// A checkpoint epoch which no map has had before.
+pub(crate) fn next_epoch() -> u64 {
+    NEXT_EPOCH.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A difference between a map and an earlier snapshot of it, returned by [`HashMap::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Change<'map, K, V> {
+    /// The key isn't in the snapshot.
+    Added { key: &'map K, value: V },
+    /// The key's value is different from its value in the snapshot.
+    Changed { key: &'map K, old: V, new: V },
+}
+
+/// A copy of a map's table and the length of its key store, taken by [`HashMap::checkpoint`].
+/// The map can be rolled back to the checkpoint with [`HashMap::restore`].
+pub struct Checkpoint<V> {
+    epoch: u64,
+    entries: Vec<Entry<V>>,
+    key_store_len: usize,
+    count: u16,
+    tombstones: u16,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Iterate over the changes made to this map since `old` was taken as a snapshot of it
+    /// (for example with [`Clone::clone`]). Yields the keys which were added, and the keys whose
+    /// values changed, in insertion order.
+    pub fn diff<'map, S2>(
+        &'map self,
+        old: &'map HashMap<K, V, S2>,
+    ) -> impl Iterator<Item = Change<'map, K, V>> + 'map
+    where
+        V: PartialEq,
+        S2: BuildHasher,
+    {
+        self.iter().filter_map(|(key, new)| match old.get(key) {
+            None => Some(Change::Added { key, value: new }),
+            Some(old) if old != new => Some(Change::Changed { key, old, new }),
+            Some(_) => None,
+        })
+    }
+
+    /// Take a checkpoint of the map, which [`restore`](Self::restore) can roll the map back to.
+    /// The checkpoint copies the table, but not the keys, because keys are never moved or
+    /// modified once they are inserted.
+    ///
+    /// The checkpoint is only consistent if the map isn't modified while it is taken.
+    pub fn checkpoint(&self) -> Checkpoint<V> {
+        Checkpoint {
+            epoch: self.checkpoint_epoch,
+            entries: self
+                .table
+                .iter()
+                .map(|entry| entry.load(Ordering::Acquire))
+                .collect(),
+            key_store_len: self.key_store.len(),
+            count: self.count.load(Ordering::Relaxed),
+            tombstones: self.tombstones.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Roll the map back to the state it was in when `checkpoint` was taken. Keys inserted since
+    /// then are dropped.
+    ///
+    /// Restoring drops keys which later checkpoints refer to, so it invalidates every checkpoint
+    /// of the map, including the one restored. Take a new checkpoint to roll back again.
+    /// # Errors
+    /// [`Error::InvalidSnapshot`] if the checkpoint wasn't taken from this map, or since it was
+    /// taken the map has been [restored](Self::restore), [cleared](Self::clear),
+    /// [compacted](Self::compact_key_store), [purged](Self::purge_tombstones) or
+    /// [rehashed](Self::rehash_with).
+    pub fn restore(&mut self, checkpoint: &Checkpoint<V>) -> Result<(), Error> {
+        if checkpoint.epoch != self.checkpoint_epoch
+            || checkpoint.key_store_len > self.key_store.len()
+        {
+            return Err(Error::InvalidSnapshot);
+        }
+        self._invalidate_checkpoints();
+        self.key_store.truncate(checkpoint.key_store_len);
+        for (entry, &saved) in self.table.iter().zip(&checkpoint.entries) {
+            entry.store(saved, Ordering::Relaxed);
+        }
+        *self.count.get_mut() = checkpoint.count;
+        *self.tombstones.get_mut() = checkpoint.tombstones;
+        Ok(())
+    }
+
+    /// Copy the map's keys and values into caller-provided buffers, without allocating. Pairs are
+    /// written in insertion order, with the same snapshot semantics as [`iter`](Self::iter),
+    /// until either buffer is full. Returns the number of pairs written: the first that many
+    /// elements of `keys` and `values` are initialised, and the caller is responsible for
+    /// dropping the keys.
+    pub fn export_into(&self, keys: &mut [MaybeUninit<K>], values: &mut [MaybeUninit<V>]) -> usize
+    where
+        K: Clone,
+    {
+        let mut written = 0;
+        for ((key, value), (key_slot, value_slot)) in
+            self.iter().zip(keys.iter_mut().zip(values.iter_mut()))
+        {
+            key_slot.write(key.clone());
+            value_slot.write(value);
+            written += 1;
+        }
+        written
+    }
+
+    /// A digest of the map's contents which doesn't depend on the order keys were inserted in,
+    /// so two replicas can cheaply check that they hold the same keys and values before
+    /// comparing them in full. Reads the table once, without allocating.
+    ///
+    /// Each key is represented by its hash, so the digests of two maps can only be compared if
+    /// they use the same hasher with the same seed, such as a
+    /// [`SeededHashBuilder`](crate::SeededHashBuilder). Flags aren't included. The digest is only
+    /// consistent if the map isn't modified while it is computed.
+    pub fn content_hash(&self) -> u64 {
+        self.table
+            .iter()
+            .map(|entry| entry.load(Ordering::Acquire))
+            .filter(Entry::is_live)
+            .map(|entry| {
+                let value = bytemuck::bytes_of(&entry.value)
+                    .iter()
+                    .rev()
+                    .fold(0, |bits, &byte| (bits << 8) | u64::from(byte));
+                mix(u64::from(entry.key_hash & constants::HASH_MASK) << 32 ^ value)
+            })
+            // Addition is commutative, so the table's layout doesn't change the sum.
+            .fold(0, u64::wrapping_add)
+    }
+
+    /// Give the map a new checkpoint epoch, so no earlier checkpoint can be restored.
+    pub(crate) fn _invalidate_checkpoints(&mut self) {
+        self.checkpoint_epoch = next_epoch();
+    }
+}
+
+/// The `SplitMix64` finalizer, so that each pair contributes an unrelated value to the sum.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
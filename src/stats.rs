@@ -0,0 +1,264 @@
+use crate::raw::BUCKET_CAPACITY;
+use crate::{constants, Error, HashMap, Size};
+#[cfg(feature = "raw-iter")]
+use crate::{key_index, Entry};
+#[cfg(feature = "raw-iter")]
+use alloc::string::String;
+use atomic::Ordering;
+use bytemuck::NoUninit;
+#[cfg(feature = "raw-iter")]
+use core::fmt::{self, Write};
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Count the buckets in the table by how many occupied entries they hold. Element `n` of the
+    /// result is the number of buckets with exactly `n` occupied entries.
+    ///
+    /// A well-distributed map has most of its buckets partly full. Many full buckets next to many
+    /// empty ones means the keys are clustering, and probes will be long.
+    pub fn bucket_occupancy(&self) -> [usize; BUCKET_CAPACITY + 1] {
+        let mut histogram = [0; BUCKET_CAPACITY + 1];
+        for bucket in self.table.buckets() {
+            let occupied = bucket
+                .iter()
+                .filter(|entry| entry.load(Ordering::Relaxed).key_offset != constants::EMPTY_KEY)
+                .count();
+            histogram[occupied] += 1;
+        }
+        histogram
+    }
+
+    /// Count how the key store's slots are used, to tell whether inserts are failing because the
+    /// table is full or because the key store is. See [`KeyStoreStats`].
+    pub fn key_store_stats(&self) -> KeyStoreStats {
+        let live = self
+            .table
+            .iter()
+            .filter(|entry| entry.load(Ordering::Relaxed).has_key())
+            .count();
+        let high_water = self.key_store.len();
+        KeyStoreStats {
+            capacity: self.key_store.capacity(),
+            high_water,
+            live,
+            burned: high_water.saturating_sub(live),
+        }
+    }
+
+    /// Set the occupancy at which the map is considered nearly full, as a number of entries.
+    /// Tombstones occupy the table, so they count towards it. Applications which spread keys over
+    /// several maps can use this to allocate the next one before inserts start failing with
+    /// [`Error::Full`].
+    ///
+    /// Setting the watermark re-arms [`try_insert_with_occupancy`](Self::try_insert_with_occupancy),
+    /// so the next insert to find the map at or over it reports crossing it again. The watermark
+    /// isn't carried over to clones, or to maps rebuilt from this one.
+    pub fn set_watermark(&mut self, entries: usize) {
+        self.watermark = Size::try_from(entries).unwrap_or(Size::MAX);
+        *self.watermark_crossed.get_mut() = false;
+    }
+
+    /// The watermark set by [`set_watermark`](Self::set_watermark), if any.
+    pub fn watermark(&self) -> Option<usize> {
+        (self.watermark != Size::MAX).then_some(usize::from(self.watermark))
+    }
+
+    /// Returns true if the map's entries and tombstones have reached its
+    /// [watermark](Self::set_watermark). Always false if no watermark is set.
+    pub fn is_near_full(&self) -> bool {
+        self.watermark != Size::MAX
+            && self.len() + self._tombstones() >= usize::from(self.watermark)
+    }
+
+    /// Insert a key-value pair into the map like [`try_insert`](Self::try_insert), and report the
+    /// map's occupancy against its [watermark](Self::set_watermark) afterwards.
+    ///
+    /// Exactly one insert reports [`Occupancy::CrossedWatermark`], even if several threads take
+    /// the map over the watermark at once, so only one of them needs to react.
+    /// # Errors
+    /// The same as [`try_insert`](Self::try_insert).
+    pub fn try_insert_with_occupancy(&self, key: K, value: V) -> Result<Occupancy, Error> {
+        self.try_insert(key, value)?;
+        if !self.is_near_full() {
+            Ok(Occupancy::BelowWatermark)
+        } else if self.watermark_crossed.load(Ordering::Relaxed)
+            || self.watermark_crossed.swap(true, Ordering::Relaxed)
+        {
+            Ok(Occupancy::NearFull)
+        } else {
+            Ok(Occupancy::CrossedWatermark)
+        }
+    }
+
+    /// Iterate over every entry in the table, including empty ones, in physical order. Yields
+    /// the index of the bucket, the index of the entry within the bucket, and the entry.
+    ///
+    /// This is meant for debuggers and visualisers which render the layout of the table. Entries
+    /// are loaded one at a time, so the result isn't a consistent snapshot if the map is being
+    /// modified.
+    #[cfg(feature = "raw-iter")]
+    pub fn raw_iter(&self) -> impl Iterator<Item = (usize, usize, RawSlot<V>)> + '_ {
+        self.table
+            .buckets()
+            .enumerate()
+            .flat_map(|(bucket, entries)| {
+                entries.iter().enumerate().map(move |(index, entry)| {
+                    (bucket, index, RawSlot(entry.load(Ordering::Acquire)))
+                })
+            })
+    }
+
+    /// Render the table as text, one line per entry, grouped by bucket. Each occupied entry shows
+    /// its hash, how far it is from the entry its probe starts at, its flags, a preview of its key,
+    /// and its value.
+    ///
+    /// This is the layout to look at when inserts fail while [`len`](Self::len) is below the
+    /// capacity: long displacements mean clustering, and tombstones take up room. Entries are
+    /// loaded one at a time, so the dump isn't a consistent snapshot if the map is being modified.
+    #[cfg(feature = "raw-iter")]
+    pub fn debug_dump(&self) -> String
+    where
+        K: fmt::Debug,
+        V: fmt::Debug,
+    {
+        /// The longest key preview, in characters.
+        const PREVIEW: usize = 24;
+
+        let mut out = String::new();
+        // Writing to a `String` can't fail.
+        let _ = writeln!(
+            out,
+            "len {}, capacity {}, tombstones {}, slots {}, probe limit {:?}",
+            self.len(),
+            self.capacity,
+            self._tombstones(),
+            self.table.slots(),
+            self.probe_limit(),
+        );
+        let mut preview = String::new();
+        for (slot, (bucket, index, raw)) in self.raw_iter().enumerate() {
+            if index == 0 {
+                let _ = writeln!(out, "bucket {bucket}:");
+            }
+            let _ = write!(out, "  {slot:>5} ");
+            if raw.is_empty() {
+                let _ = writeln!(out, "empty");
+                continue;
+            }
+            let hash = raw.hash();
+            let home = self._index(hash);
+            #[allow(clippy::cast_possible_truncation)]
+            let displacement = self.table.distance(home, slot as Size);
+            let _ = write!(
+                out,
+                "hash {hash:07x} +{displacement:<3} flags {:x} ",
+                raw.flags()
+            );
+            match raw.key_index().and_then(|index| self.get_key(index)) {
+                Some(key) => {
+                    preview.clear();
+                    let _ = write!(preview, "{key:?}");
+                    if let Some((cut, _)) = preview.char_indices().nth(PREVIEW) {
+                        preview.truncate(cut);
+                        preview.push('…');
+                    }
+                    let pending = if raw.is_pending() { " pending" } else { "" };
+                    let _ = writeln!(out, "{preview} => {:?}{pending}", raw.value());
+                }
+                None if raw.is_tombstone() => {
+                    let _ = writeln!(out, "tombstone");
+                }
+                None => {
+                    let _ = writeln!(out, "unpublished key");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// How full a map is after an insert, returned by [`HashMap::try_insert_with_occupancy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occupancy {
+    /// The map is below its watermark, or has none.
+    BelowWatermark,
+    /// This insert was the first to find the map at or over its watermark.
+    CrossedWatermark,
+    /// The map was already at or over its watermark.
+    NearFull,
+}
+
+/// How a map's key store slots are used, returned by [`HashMap::key_store_stats`].
+///
+/// The key store only grows, so a slot is used up by every key pushed to it, even one which
+/// doesn't end up in the map. [`HashMap::compact_key_store`] reclaims the burned slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStoreStats {
+    /// The number of slots in the key store.
+    pub capacity: usize,
+    /// The number of slots used so far. Inserts fail with [`Error::KeyStoreFull`] once this
+    /// reaches `capacity`.
+    pub high_water: usize,
+    /// The number of slots holding a key which an entry refers to.
+    pub live: usize,
+    /// The number of used slots which hold no key in the map: keys which lost an insert race,
+    /// keys which were removed or rolled back and haven't been inserted again, and keys moved
+    /// away by [`HashMap::rekey`].
+    pub burned: usize,
+}
+
+/// A copy of one entry in a map's table, yielded by [`HashMap::raw_iter`].
+#[cfg(feature = "raw-iter")]
+#[derive(Clone, Copy)]
+pub struct RawSlot<V>(Entry<V>);
+
+#[cfg(feature = "raw-iter")]
+impl<V: Copy> RawSlot<V> {
+    /// Whether the entry has never been written.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.key_offset == constants::EMPTY_KEY
+    }
+
+    /// Whether the entry's key was removed, rolled back or moved away by [`HashMap::rekey`].
+    /// Probes for other keys skip over it, and an insert of the same key takes it back.
+    #[must_use]
+    pub fn is_tombstone(&self) -> bool {
+        self.0.key_offset == constants::TOMBSTONE
+    }
+
+    /// Whether the entry is part of a batch which hasn't committed yet.
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.0.is_pending()
+    }
+
+    /// The index of the entry's key in the key store, if the entry holds a key.
+    #[must_use]
+    pub fn key_index(&self) -> Option<usize> {
+        (!self.is_empty() && !self.is_tombstone()).then(|| key_index(self.0.key_offset))
+    }
+
+    /// The hash of the entry's key, without its flags. A tombstone no longer holds a hash.
+    #[must_use]
+    pub fn hash(&self) -> u32 {
+        self.0.key_hash & constants::HASH_MASK
+    }
+
+    /// The entry's user flags.
+    #[must_use]
+    pub fn flags(&self) -> u8 {
+        self.0.flags()
+    }
+
+    /// The entry's value. Meaningless if the entry is empty.
+    #[must_use]
+    pub fn value(&self) -> V {
+        self.0.value
+    }
+}
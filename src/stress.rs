@@ -0,0 +1,263 @@
+//! A reusable stress test, for checking the map on targets the crate isn't tested on.
+
+use crate::{Error, HashMap};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The settings for [`stress_test`].
+#[derive(Debug, Clone)]
+pub struct StressConfig {
+    /// The number of threads which operate on the map at once.
+    pub threads: usize,
+    /// How long the threads run for.
+    pub duration: Duration,
+    /// The capacity of the map, which is also the number of distinct keys used.
+    pub capacity: u16,
+    /// The relative weights of gets, inserts and updates.
+    pub mix: OpMix,
+    /// The seed for every thread's random operations.
+    pub seed: u64,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            threads: 8,
+            duration: Duration::from_secs(1),
+            capacity: 4096,
+            mix: OpMix::default(),
+            seed: 0,
+        }
+    }
+}
+
+/// The relative weights of each operation in a [`stress_test`]. Defaults to a read-heavy mix.
+#[derive(Debug, Clone, Copy)]
+pub struct OpMix {
+    pub get: u32,
+    pub insert: u32,
+    pub update: u32,
+}
+
+impl Default for OpMix {
+    fn default() -> Self {
+        Self {
+            get: 8,
+            insert: 1,
+            update: 1,
+        }
+    }
+}
+
+/// The operations a [`stress_test`] ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StressReport {
+    pub gets: u64,
+    pub inserts: u64,
+    pub updates: u64,
+    /// The number of keys in the map at the end.
+    pub len: usize,
+}
+
+/// How the map disagreed with the oracle in a [`stress_test`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StressFailure {
+    /// An operation on a key owned by one thread returned something other than what that thread
+    /// had written.
+    Mismatch {
+        key: u32,
+        expected: Option<u16>,
+        found: Option<u16>,
+    },
+    /// An insert failed with an error other than a duplicate key, or reported a duplicate key
+    /// which wasn't in the map.
+    InsertFailed { key: u32, error: Error },
+    /// A key which a thread had seen in the map was later missing.
+    Vanished { key: u32 },
+    /// The map's length didn't match the number of keys inserted.
+    Len { expected: usize, found: usize },
+    /// A worker thread panicked.
+    Panicked,
+}
+
+impl fmt::Display for StressFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StressFailure::Mismatch {
+                key,
+                expected,
+                found,
+            } => write!(f, "key {key}: expected {expected:?}, found {found:?}"),
+            StressFailure::InsertFailed { key, error } => {
+                write!(f, "inserting new key {key} failed: {error}")
+            }
+            StressFailure::Vanished { key } => write!(f, "key {key} vanished from the map"),
+            StressFailure::Len { expected, found } => {
+                write!(f, "expected {expected} keys in the map, found {found}")
+            }
+            StressFailure::Panicked => f.write_str("a worker thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for StressFailure {}
+
+/// Run random gets, inserts and updates on a map from several threads at once, checking every
+/// result against an oracle.
+///
+/// Each thread only writes the keys it owns (those equal to its index, modulo the number of
+/// threads), so it knows exactly what each of those keys should hold. It also reads other threads'
+/// keys, and checks that a key it has seen never disappears. When the threads finish, the whole
+/// map is checked against the combined oracles.
+///
+/// This is the test the crate runs on its own targets, exported behind the `test-util` feature
+/// so it can be run on other hardware, or under emulation.
+/// # Errors
+/// The first [`StressFailure`] found.
+/// # Panics
+/// If `config.threads` or `config.capacity` is zero, or the map can't be allocated.
+pub fn stress_test(config: &StressConfig) -> Result<StressReport, StressFailure> {
+    assert!(config.threads > 0 && config.capacity > 0);
+    let map = HashMap::<u32, u16>::with_capacity(usize::from(config.capacity));
+    let deadline = Instant::now() + config.duration;
+
+    let results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.threads)
+            .map(|thread| {
+                let map = &map;
+                scope.spawn(move || run_worker(map, config, thread, deadline))
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or(Err(StressFailure::Panicked)))
+            .collect()
+    });
+
+    let mut report = StressReport::default();
+    let mut expected_len = 0;
+    for result in results {
+        let (oracle, counts) = result?;
+        report.gets += counts.gets;
+        report.inserts += counts.inserts;
+        report.updates += counts.updates;
+        expected_len += oracle.len();
+        for (key, value) in oracle {
+            check(key, Some(value), map.get(&key))?;
+        }
+    }
+    for len in [map.len(), map.iter().count()] {
+        if len != expected_len {
+            return Err(StressFailure::Len {
+                expected: expected_len,
+                found: len,
+            });
+        }
+    }
+    report.len = expected_len;
+    Ok(report)
+}
+
+type WorkerResult = Result<(BTreeMap<u32, u16>, StressReport), StressFailure>;
+
+fn run_worker(
+    map: &HashMap<u32, u16>,
+    config: &StressConfig,
+    thread: usize,
+    deadline: Instant,
+) -> WorkerResult {
+    let threads = u32::try_from(config.threads).unwrap_or(u32::MAX);
+    let thread_id = u32::try_from(thread).unwrap_or(u32::MAX);
+    let keys = u32::from(config.capacity);
+    let total = config.mix.get + config.mix.insert + config.mix.update;
+    let mut rng = SplitMix64(config.seed ^ u64::from(thread_id).wrapping_mul(0x9e37_79b9));
+    let mut oracle = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+    let mut counts = StressReport::default();
+
+    for op in 0_u64.. {
+        if op % 64 == 0 && Instant::now() >= deadline {
+            break;
+        }
+        let choice = rng.below(total.max(1));
+        // Writes go to keys this thread owns. Reads can be of any key.
+        let owned_keys = keys.saturating_sub(thread_id).div_ceil(threads);
+        if choice < config.mix.get || owned_keys == 0 {
+            counts.gets += 1;
+            let key = rng.below(keys);
+            let found = map.get(&key);
+            if key % threads == thread_id {
+                check(key, oracle.get(&key).copied(), found)?;
+            } else if found.is_some() {
+                seen.insert(key);
+            } else if seen.contains(&key) {
+                return Err(StressFailure::Vanished { key });
+            }
+            continue;
+        }
+        let key = rng.below(owned_keys) * threads + thread_id;
+        #[allow(clippy::cast_possible_truncation)]
+        let value = op as u16;
+        if choice < config.mix.get + config.mix.insert {
+            counts.inserts += 1;
+            match (map.try_insert(key, value), oracle.get(&key)) {
+                (Ok(()), None) => {
+                    oracle.insert(key, value);
+                }
+                (Err(Error::DuplicateKey), Some(_)) => {}
+                (Ok(()), Some(&expected)) => {
+                    return Err(StressFailure::Mismatch {
+                        key,
+                        expected: Some(expected),
+                        found: None,
+                    })
+                }
+                (Err(error), _) => return Err(StressFailure::InsertFailed { key, error }),
+            }
+        } else {
+            counts.updates += 1;
+            let previous = map.update(&key, value);
+            check(key, oracle.get(&key).copied(), previous)?;
+            if previous.is_some() {
+                oracle.insert(key, value);
+            }
+        }
+    }
+    Ok((oracle, counts))
+}
+
+fn check(key: u32, expected: Option<u16>, found: Option<u16>) -> Result<(), StressFailure> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(StressFailure::Mismatch {
+            key,
+            expected,
+            found,
+        })
+    }
+}
+
+/// A small, fast PRNG, so the harness doesn't need a dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    fn below(&mut self, n: u32) -> u32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let below = (((self.next() >> 32) * u64::from(n)) >> 32) as u32;
+        below
+    }
+}
@@ -1,10 +1,21 @@
-use crate::HashMap;
+// The original tests predate the pedantic lints, and are kept as they were written.
+#![allow(
+    clippy::cast_lossless,
+    clippy::semicolon_if_nothing_returned,
+    clippy::uninlined_format_args
+)]
+
+extern crate std;
+
+use crate::{Change, DefaultHashBuilder, Error, HashMap, Occupancy, PodKey, SmallString};
 use alloc::{
     format,
     string::{String, ToString},
+    vec::Vec,
 };
+use core::hash::BuildHasher;
 use fixedstr::zstr;
-use rayon::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 fn traits_check<T: Sized + Send + Sync + Unpin + Default>() {}
 
@@ -82,3 +93,2722 @@ fn iter() {
         assert_eq!(*k, v as u64);
     }
 }
+
+/// The outcome of a single operation in a randomised schedule, as observed by the calling thread.
+enum Op {
+    Insert {
+        key: u64,
+        value: u16,
+        ok: bool,
+    },
+    Update {
+        key: u64,
+        value: u16,
+        previous: Option<u16>,
+    },
+    Get {
+        key: u64,
+        value: Option<u16>,
+    },
+}
+
+const SCHEDULE_THREADS: u16 = 4;
+const SCHEDULE_KEYS: u64 = 16;
+const SCHEDULE_CAPACITY: usize = 16;
+const SCHEDULE_OPS: u16 = 64;
+
+/// Run one randomised schedule of mixed operations, returning every thread's operation log.
+/// Threads yield at random points so that each seed explores a different interleaving.
+fn run_schedule<S: BuildHasher + Sync>(map: &HashMap<u64, u16, S>, seed: u64) -> Vec<Op> {
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..SCHEDULE_THREADS)
+            .map(|thread| {
+                s.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed ^ (u64::from(thread) << 32));
+                    let mut log = Vec::new();
+                    for seq in 0..SCHEDULE_OPS {
+                        // Every written value is unique, so it identifies the write that produced it.
+                        let value = (thread << 12) | seq;
+                        let key = rng.gen_range(0..SCHEDULE_KEYS);
+                        if rng.gen_bool(0.3) {
+                            std::thread::yield_now();
+                        }
+                        log.push(match rng.gen_range(0..3) {
+                            0 => Op::Insert {
+                                key,
+                                value,
+                                ok: map.insert(key, value),
+                            },
+                            1 => Op::Update {
+                                key,
+                                value,
+                                previous: map.update(&key, value),
+                            },
+                            _ => Op::Get {
+                                key,
+                                value: map.get(&key),
+                            },
+                        });
+                    }
+                    log
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    })
+}
+
+/// Check the logs against a per-key register model. Each key must have been inserted at most
+/// once, every successful update must have replaced exactly one earlier write, the writes must
+/// form a single chain, and the final value must be the end of that chain.
+fn check_schedule<S: BuildHasher>(map: &HashMap<u64, u16, S>, log: &[Op]) {
+    for key in 0..SCHEDULE_KEYS {
+        let mut initial = None;
+        let mut replaced_by = std::collections::HashMap::new();
+        let mut written = std::collections::HashSet::new();
+        for op in log {
+            match *op {
+                Op::Insert { key: k, value, ok } if k == key && ok => {
+                    assert!(initial.replace(value).is_none(), "key {key} inserted twice");
+                    written.insert(value);
+                }
+                Op::Update {
+                    key: k,
+                    value,
+                    previous: Some(previous),
+                } if k == key => {
+                    assert!(
+                        replaced_by.insert(previous, value).is_none(),
+                        "value {previous} of key {key} replaced twice"
+                    );
+                    written.insert(value);
+                }
+                _ => {}
+            }
+        }
+        for op in log {
+            if let Op::Get {
+                key: k,
+                value: Some(value),
+            } = *op
+            {
+                if k == key {
+                    assert!(written.contains(&value), "read unwritten value {value}");
+                }
+            }
+        }
+
+        let Some(mut last) = initial else {
+            assert!(replaced_by.is_empty());
+            assert_eq!(map.get(&key), None);
+            continue;
+        };
+        let mut chain_len = 1;
+        while let Some(&next) = replaced_by.get(&last) {
+            last = next;
+            chain_len += 1;
+        }
+        assert_eq!(
+            chain_len,
+            written.len(),
+            "writes to key {key} do not form a chain"
+        );
+        assert_eq!(map.get(&key), Some(last));
+    }
+    let inserted = log
+        .iter()
+        .filter(|op| matches!(op, Op::Insert { ok: true, .. }))
+        .count();
+    assert_eq!(map.len(), inserted);
+}
+
+/// The seeds to run schedules with. Set `FOLKLORE_SEED` to replay a single failing seed: it
+/// fixes each thread's operations and the table's layout, though not how the threads interleave.
+fn schedule_seeds() -> core::ops::Range<u64> {
+    match std::env::var("FOLKLORE_SEED") {
+        Ok(seed) => {
+            let seed = seed.parse().expect("FOLKLORE_SEED must be a u64");
+            seed..seed + 1
+        }
+        Err(_) => 0..500,
+    }
+}
+
+/// Prints the seed of a schedule if it panics, so it can be replayed.
+struct ReportSeed(u64);
+
+impl Drop for ReportSeed {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            std::eprintln!("schedule failed, replay with FOLKLORE_SEED={}", self.0);
+        }
+    }
+}
+
+#[test]
+fn randomized_schedules() {
+    for seed in schedule_seeds() {
+        let _report = ReportSeed(seed);
+        let map = HashMap::<u64, u16, _>::with_capacity_and_seed(SCHEDULE_CAPACITY, seed);
+        let log = run_schedule(&map, seed);
+        check_schedule(&map, &log);
+    }
+}
+
+#[test]
+fn randomized_schedules_near_capacity() {
+    // With fewer slots than keys, inserts compete for the last free slots.
+    for seed in schedule_seeds() {
+        let _report = ReportSeed(seed);
+        let map = HashMap::<u64, u16, _>::with_capacity_and_seed(SCHEDULE_CAPACITY / 2, seed);
+        let log = run_schedule(&map, seed);
+        check_schedule(&map, &log);
+    }
+}
+
+#[test]
+fn seeded_layout() {
+    let layout = |seed| {
+        let map = HashMap::<u64, u16, _>::with_capacity_and_seed(32, seed);
+        for key in 0..32 {
+            assert!(map.insert(key, 0));
+        }
+        (0..32)
+            .map(|key| map.find_index(&key).unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(layout(1), layout(1));
+    assert_ne!(layout(1), layout(2));
+}
+
+#[test]
+fn tiny_capacity() {
+    for capacity in 0..8_u16 {
+        let map = HashMap::<u64, u16>::with_capacity(capacity.into());
+        assert_eq!(map.get(&0), None);
+        for i in 0..capacity {
+            assert!(map.insert(u64::from(i), i));
+        }
+        assert!(!map.insert(u64::MAX, 0));
+        assert_eq!(map.len(), usize::from(capacity));
+    }
+}
+
+#[test]
+fn try_insert_errors() {
+    let map = HashMap::<u64, u16>::with_capacity(2);
+    assert_eq!(map.try_insert(1, 1), Ok(()));
+    assert_eq!(map.try_insert(1, 2), Err(Error::DuplicateKey));
+    assert_eq!(map.try_insert(2, 2), Ok(()));
+    assert_eq!(map.try_insert(3, 3), Err(Error::Full));
+    // A duplicate is reported even when the map is full.
+    assert_eq!(map.try_insert(2, 3), Err(Error::DuplicateKey));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn try_with_capacity_too_large() {
+    assert!(matches!(
+        HashMap::<u64, u16>::try_with_capacity(i16::MAX as usize + 1),
+        Err(Error::CapacityTooLarge)
+    ));
+}
+
+#[test]
+#[cfg(not(feature = "std"))]
+fn default_hasher_is_fnv() {
+    use core::hash::Hash;
+    use hash32::{FnvHasher, Hasher};
+
+    let a = HashMap::<u64, u16>::default();
+    let b = HashMap::<u64, u16>::default();
+    let mut fnv = FnvHasher::default();
+    42_u64.hash(&mut fnv);
+    assert_eq!(a._hash(&42), fnv.finish32() & crate::constants::HASH_MASK);
+    assert_eq!(a._hash(&42), b._hash(&42));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn default_hasher_is_random() {
+    let a = HashMap::<u64, u16>::default();
+    let b = HashMap::<u64, u16>::default();
+    assert!((0..8_u64).any(|key| a._hash(&key) != b._hash(&key)));
+}
+
+#[test]
+fn iter_skips_unpublished_keys() {
+    let map = HashMap::<u64, u16>::with_capacity(8);
+    assert!(map.insert(0, 0));
+    // Simulate an insert which lost its race, leaving an unpublished key behind.
+    let stored = crate::HashedKey {
+        key_hash: map._hash(&1),
+        key: 1,
+    };
+    assert!(map.key_store.push(stored).is_some());
+    assert!(map.insert(2, 2));
+    assert_eq!(map.get_key(1), None);
+    let entries: Vec<_> = map.iter().map(|(k, v)| (*k, v)).collect();
+    assert_eq!(entries, [(0, 0), (2, 2)]);
+}
+
+#[test]
+fn iter_snapshot_with_concurrent_inserts() {
+    let map = HashMap::<u64, u16>::with_capacity(200);
+    for i in 0..100 {
+        assert!(map.insert(u64::from(i), i));
+    }
+    let iter = map.iter();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 100..200 {
+                assert!(map.insert(u64::from(i), i));
+            }
+        });
+        let keys: Vec<_> = iter.map(|(k, _)| *k).collect();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+    });
+    assert_eq!(map.iter().count(), 200);
+}
+
+#[test]
+fn keys_and_values() {
+    let map = HashMap::<u64, u16>::with_capacity(200);
+    for i in 0..100 {
+        assert!(map.insert(u64::from(i), i * 2));
+    }
+    assert_eq!(map.remove(&50), Some(100));
+    let keys = map.keys();
+    let values = map.values();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 100..200 {
+                assert!(map.insert(u64::from(i), i * 2));
+            }
+        });
+        assert!(keys.copied().eq((0..100).filter(|&i| i != 50)));
+        assert!(values.eq((0..100).filter(|&i| i != 50).map(|i| i * 2)));
+    });
+    assert_eq!(map.keys().count(), 199);
+
+    let sharded = crate::ShardedHashMap::<u64, u16>::with_capacity(100_000);
+    for i in 0..1000 {
+        assert!(sharded.insert(i, 1));
+    }
+    let mut keys: Vec<_> = sharded.keys().copied().collect();
+    keys.sort_unstable();
+    assert!(keys.into_iter().eq(0..1000));
+    assert_eq!(sharded.values().map(usize::from).sum::<usize>(), 1000);
+}
+
+#[test]
+fn drop_drops_keys() {
+    let key = alloc::rc::Rc::new(7_u64);
+    let map = HashMap::<_, u16>::with_capacity(8);
+    assert!(map.insert(key.clone(), 1));
+    assert!(!map.insert(key.clone(), 2));
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 2);
+    drop(map);
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 1);
+}
+
+#[test]
+fn get_or_insert_with_key() {
+    let map = HashMap::<String, u16>::with_capacity(2);
+    let len = |key: &String| u16::try_from(key.len()).unwrap();
+    assert_eq!(map.get_or_insert_with_key("four".to_string(), len), Ok(4));
+    assert_eq!(
+        map.get_or_insert_with_key("four".to_string(), |_| unreachable!()),
+        Ok(4)
+    );
+    assert_eq!(map.get_or_insert_with("two".to_string(), || 2), Ok(2));
+    assert_eq!(
+        map.get_or_insert_with_key("full".to_string(), len),
+        Err(Error::Full)
+    );
+    assert_eq!(map.get("four"), Some(4));
+}
+
+#[test]
+fn insert_with_skips_duplicates() {
+    let map = HashMap::<u64, u16>::with_capacity(8);
+    assert!(map.insert_with(1, || 10));
+    assert!(!map.insert_with(1, || unreachable!()));
+    assert_eq!(map.get(&1), Some(10));
+}
+
+#[test]
+fn update_with_key() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert("abc".to_string(), 1));
+    let previous = map.update_with_key("abc", |key, value| {
+        assert_eq!(key, "abc");
+        Some(value + u16::try_from(key.len()).unwrap())
+    });
+    assert_eq!(previous, Some(1));
+    assert_eq!(map.get("abc"), Some(4));
+    assert_eq!(map.update_with_key("abc", |_, _| None), None);
+    assert_eq!(map.update_with_key("xyz", |_, _| Some(0)), None);
+}
+
+#[test]
+fn update_with_key_removed() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert("abc".to_string(), 10));
+    // The key is removed while the update function runs, so the update can't land.
+    let previous = map.update_with_key("abc", |key, value| {
+        assert_eq!(map.remove(key), Some(10));
+        Some(value + 1)
+    });
+    assert_eq!(previous, None);
+    assert_eq!(map.get("abc"), None);
+    assert_eq!(map.update_with_key("abc", |_, value| Some(value)), None);
+}
+
+#[test]
+fn with_orderings() {
+    use core::sync::atomic::Ordering;
+    let map = HashMap::<u64, u16>::with_capacity(8);
+    assert!(map.insert(1, 10));
+    assert_eq!(map.get_with_ordering(&1, Ordering::Acquire), Some(10));
+    assert_eq!(map.get_with_ordering(&2, Ordering::SeqCst), None);
+    let previous = map.update_with_orderings(&1, 11, Ordering::SeqCst, Ordering::Relaxed);
+    assert_eq!(previous, Some(10));
+    assert_eq!(map.get(&1), Some(11));
+    assert_eq!(
+        map.update_with_orderings(&2, 0, Ordering::AcqRel, Ordering::Acquire),
+        None
+    );
+}
+
+#[test]
+fn min_max_by_value() {
+    let map = HashMap::<u64, u16>::with_capacity(64);
+    assert_eq!(map.min_by_value(Ord::cmp), None);
+    for i in 0..50_u16 {
+        assert!(map.insert(u64::from(i), (i * 7) % 50));
+    }
+    assert_eq!(map.min_by_value(Ord::cmp), Some((&0, 0)));
+    assert_eq!(map.max_by_value(Ord::cmp), Some((&7, 49)));
+    assert_eq!(map.max_by_value(|a, b| b.cmp(a)), Some((&0, 0)));
+}
+
+#[test]
+fn submaps() {
+    let small = HashMap::<u64, u16>::with_capacity(8);
+    let large = HashMap::<u64, u16>::with_capacity(8);
+    for i in 0..4 {
+        assert!(small.insert(u64::from(i), i));
+        assert!(large.insert(u64::from(i), i));
+    }
+    assert!(large.insert(4, 4));
+
+    assert!(large.contains_all(&[0, 1, 4]));
+    assert!(!small.contains_all(&[0, 1, 4]));
+    assert!(small.is_submap_of(&large));
+    assert!(large.is_supermap_of(&small));
+    assert!(!large.is_submap_of(&small));
+
+    large.update(&0, 10);
+    assert!(!small.is_submap_of(&large));
+    assert!(small.is_submap_of_by(&large, |_, _| true));
+}
+
+#[test]
+fn key_set_operations() {
+    let a = HashMap::<u64, u16>::with_capacity(8);
+    let b = HashMap::<u64, u16>::with_capacity(8);
+    for i in 0..4 {
+        assert!(a.insert(i, 0));
+        assert!(b.insert(i + 2, 0));
+    }
+    let collect = |keys: &mut dyn Iterator<Item = &u64>| {
+        let mut keys: Vec<_> = keys.copied().collect();
+        keys.sort_unstable();
+        keys
+    };
+    assert_eq!(collect(&mut a.union_keys(&b)), [0, 1, 2, 3, 4, 5]);
+    assert_eq!(collect(&mut a.intersection_keys(&b)), [2, 3]);
+    assert_eq!(collect(&mut a.difference_keys(&b)), [0, 1]);
+    assert_eq!(collect(&mut b.difference_keys(&a)), [4, 5]);
+    assert_eq!(collect(&mut a.symmetric_difference_keys(&b)), [0, 1, 4, 5]);
+    assert!(b.insert(0, 1));
+    let mut drift: Vec<_> = a.symmetric_difference(&b).collect();
+    drift.sort_unstable();
+    assert_eq!(drift, [(&1, 0), (&4, 0), (&5, 0)]);
+}
+
+#[test]
+fn diff_against_snapshot() {
+    let map = HashMap::<u64, u16>::with_capacity(8);
+    for i in 0..4 {
+        assert!(map.insert(u64::from(i), i));
+    }
+    let snapshot = map.clone();
+    assert_eq!(map.diff(&snapshot).count(), 0);
+
+    map.update(&1, 10);
+    assert!(map.insert(4, 4));
+    map.update(&2, 2);
+    let changes: Vec<_> = map.diff(&snapshot).collect();
+    assert_eq!(
+        changes,
+        [
+            Change::Changed {
+                key: &1,
+                old: 1,
+                new: 10
+            },
+            Change::Added { key: &4, value: 4 },
+        ]
+    );
+}
+
+#[test]
+fn checkpoint_restore() {
+    let key = alloc::rc::Rc::new(100_u64);
+    let mut map = HashMap::<_, u16>::with_capacity(16);
+    for i in 0..4 {
+        assert!(map.insert(alloc::rc::Rc::new(u64::from(i)), i));
+    }
+    let checkpoint = map.checkpoint();
+
+    map.update(&0, 10);
+    assert!(map.insert(key.clone(), 100));
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 2);
+
+    assert_eq!(map.restore(&checkpoint), Ok(()));
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 1);
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.get(&0), Some(0));
+    assert_eq!(map.get(&100), None);
+    assert_eq!(map.iter().count(), 4);
+
+    // The map still works after being restored.
+    assert!(map.insert(key.clone(), 101));
+    assert_eq!(map.get(&100), Some(101));
+
+    let other = HashMap::<alloc::rc::Rc<u64>, u16>::with_capacity(16);
+    assert_eq!(
+        map.restore(&other.checkpoint()),
+        Err(Error::InvalidSnapshot)
+    );
+}
+
+#[test]
+fn stale_checkpoints() {
+    let mut map = HashMap::<&str, u16>::with_capacity(16);
+    let before_a = map.checkpoint();
+    assert!(map.insert("a", 1));
+    let after_a = map.checkpoint();
+    assert_eq!(map.restore(&before_a), Ok(()));
+    assert!(map.insert("b", 2));
+    // Restoring dropped "a", which `after_a` still refers to.
+    assert_eq!(map.restore(&after_a), Err(Error::InvalidSnapshot));
+    assert_eq!(map.restore(&before_a), Err(Error::InvalidSnapshot));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get("b"), Some(2));
+    assert!(map.iter().eq([(&"b", 2)]));
+
+    let checkpoint = map.checkpoint();
+    map.clear();
+    assert_eq!(map.restore(&checkpoint), Err(Error::InvalidSnapshot));
+
+    // A map given the allocation of a dropped one doesn't accept the dropped map's checkpoints.
+    let checkpoint = map.checkpoint();
+    drop(map);
+    let mut map = HashMap::<&str, u16>::with_capacity(16);
+    assert_eq!(map.restore(&checkpoint), Err(Error::InvalidSnapshot));
+}
+
+#[test]
+fn write_logger() {
+    use crate::WriteLogger;
+    use core::cell::RefCell;
+
+    #[derive(Default)]
+    struct Log(
+        RefCell<Vec<(usize, Option<&'static str>, u16)>>,
+        RefCell<Vec<usize>>,
+    );
+
+    impl WriteLogger<&'static str, u16> for Log {
+        fn log_insert(&self, key_index: usize, key: &&'static str, value: u16) {
+            self.0.borrow_mut().push((key_index, Some(*key), value));
+        }
+
+        fn log_update(&self, key_index: usize, value: u16) {
+            self.0.borrow_mut().push((key_index, None, value));
+        }
+
+        fn log_remove(&self, key_index: usize) {
+            self.1.borrow_mut().push(key_index);
+        }
+    }
+
+    let map = HashMap::<&'static str, u16>::with_capacity(2);
+    let logged = map.with_logger(Log::default());
+    assert!(logged.insert("one", 1));
+    assert!(logged.insert("two", 2));
+    assert!(!logged.insert("one", 10));
+    assert!(!logged.insert("three", 3));
+    assert_eq!(logged.update("two", 20), Some(2));
+    assert_eq!(logged.fetch_update("one", |_| None), None);
+    assert_eq!(logged.update("three", 30), None);
+    // Mutations made directly on the map aren't logged.
+    assert_eq!(map.update("one", 100), Some(1));
+
+    assert_eq!(
+        logged.logger().0.take(),
+        [(0, Some("one"), 1), (1, Some("two"), 2), (1, None, 20)]
+    );
+
+    // A removed key is logged with the same index when it is inserted again.
+    assert_eq!(logged.remove("two"), Some(20));
+    assert_eq!(logged.remove("two"), None);
+    assert!(logged.insert("two", 3));
+    assert!(!logged.insert("three", 3));
+    assert_eq!(logged.logger().1.take(), [1]);
+    assert_eq!(logged.logger().0.take(), [(1, Some("two"), 3)]);
+}
+
+#[test]
+fn leak() {
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    assert!(map.insert(1, 1));
+    let map: &'static HashMap<u32, u16> = map.leak();
+    std::thread::spawn(move || assert!(map.insert(2, 2)))
+        .join()
+        .unwrap();
+    assert_eq!(map.get(&2), Some(2));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn global_init_once() {
+    static MAP: crate::Global<u32, u16> = crate::Global::new();
+
+    assert!(MAP.get().is_none());
+    std::thread::scope(|s| {
+        for i in 0..4 {
+            s.spawn(move || {
+                let map = MAP.get_or_init(|| HashMap::with_capacity(16));
+                assert!(map.insert(i, 0));
+            });
+        }
+    });
+    let map = MAP.get().unwrap();
+    assert_eq!(map.len(), 4);
+    assert!(MAP.init(HashMap::with_capacity(16)).is_err());
+    assert!(core::ptr::eq(MAP.get_or_init(|| unreachable!()), map));
+}
+
+#[test]
+#[cfg(not(feature = "timestamps"))]
+fn table_bytes_for() {
+    // 100 / 0.6 rounds up to 256 entries of 8 bytes, and 128 keys of 4 bytes with a 4 byte hash
+    // and a state byte each.
+    assert_eq!(HashMap::<u32, u16>::table_bytes_for(100), 256 * 8 + 128 * 9);
+    // The table always holds at least one bucket. A `u64` key's hash is padded to 8 bytes.
+    assert_eq!(HashMap::<u64, u16>::table_bytes_for(0), 8 * 8 + 17);
+}
+
+#[test]
+#[cfg(feature = "timestamps")]
+fn table_bytes_for() {
+    // As without timestamps, plus a 4 byte tick for each of the 128 keys.
+    assert_eq!(
+        HashMap::<u32, u16>::table_bytes_for(100),
+        256 * 8 + 128 * 13
+    );
+    assert_eq!(HashMap::<u64, u16>::table_bytes_for(0), 8 * 8 + 21);
+}
+
+#[test]
+fn bucket_occupancy() {
+    let map = HashMap::<u32, u16>::with_capacity(64);
+    let empty = map.bucket_occupancy();
+    assert_eq!(empty[0], 16);
+    assert_eq!(empty[1..].iter().sum::<usize>(), 0);
+
+    for i in 0..64 {
+        assert!(map.insert(i, 0));
+    }
+    let histogram = map.bucket_occupancy();
+    assert_eq!(histogram.iter().sum::<usize>(), 16);
+    let occupied: usize = histogram.iter().enumerate().map(|(n, b)| n * b).sum();
+    assert_eq!(occupied, 64);
+}
+
+#[test]
+fn will_fit() {
+    let map = HashMap::<u32, u16>::with_capacity(10);
+    assert!(map.will_fit(10));
+    assert!(!map.will_fit(11));
+    for i in 0..7 {
+        assert!(map.insert(i, 0));
+    }
+    assert!(map.will_fit(3));
+    assert!(!map.will_fit(4));
+    for i in 7..10 {
+        assert!(map.insert(i, 0));
+    }
+    assert!(map.will_fit(0));
+    assert!(!map.will_fit(1));
+}
+
+#[test]
+fn entry_flags() {
+    const PINNED: u8 = 0b01;
+    const STALE: u8 = 0b10;
+
+    let map = HashMap::<u32, u16>::with_capacity(64);
+    for i in 0..32 {
+        assert!(map.insert(i, 0));
+    }
+    assert_eq!(map.get_flags(&1), Some(0));
+    assert_eq!(map.get_flags(&100), None);
+
+    assert_eq!(map.set_flags(&1, PINNED), Some(0));
+    assert_eq!(map.update(&1, 10), Some(0));
+    assert_eq!(map.get_with_flags(&1), Some((10, PINNED)));
+
+    assert_eq!(
+        map.fetch_update_with_flags(&1, |value, flags| Some((value + 1, flags | STALE))),
+        Some((10, PINNED))
+    );
+    assert_eq!(map.get_with_flags(&1), Some((11, PINNED | STALE)));
+
+    // Flags don't affect lookups.
+    for i in 0..32 {
+        map.set_flags(&i, 0b1111);
+        assert!(map.contains_key(&i));
+    }
+    assert!(!map.contains_key(&100));
+    assert!(!map.insert(5, 0));
+}
+
+#[test]
+#[should_panic(expected = "flags out of range")]
+fn entry_flags_out_of_range() {
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    assert!(map.insert(1, 0));
+    map.set_flags(&1, 1 << crate::FLAG_BITS);
+}
+
+#[test]
+#[cfg(feature = "timestamps")]
+fn last_modified() {
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    assert!(map.insert(1, 1));
+    map.set_tick(5);
+    assert!(map.insert(2, 2));
+    assert_eq!(map.last_modified(&1), Some(0));
+    assert_eq!(map.last_modified(&2), Some(5));
+
+    map.set_tick(9);
+    assert_eq!(map.update(&1, 10), Some(1));
+    assert_eq!(map.fetch_update(&2, |_| None), None);
+    assert_eq!(map.last_modified(&1), Some(9));
+    assert_eq!(map.last_modified(&2), Some(5));
+    assert_eq!(map.last_modified(&3), None);
+    assert_eq!(map.tick(), 9);
+    assert_eq!(map.remove(&1), Some(10));
+    assert_eq!(map.last_modified(&1), None);
+}
+
+#[test]
+#[cfg(feature = "timestamps")]
+fn last_modified_after_compaction() {
+    for purge in [false, true] {
+        let mut map = HashMap::<u32, u16>::with_capacity(16);
+        for key in 0..4 {
+            map.set_tick(u32::from(key) * 100 + 100);
+            assert!(map.insert(u32::from(key), key));
+        }
+        assert_eq!(map.remove(&0), Some(0));
+        if purge {
+            assert_eq!(map.purge_tombstones(), 1);
+        } else {
+            assert_eq!(map.compact_key_store(), 1);
+        }
+        let ticks: Vec<_> = (1..4).map(|key| map.last_modified(&key)).collect();
+        assert_eq!(ticks, [Some(200), Some(300), Some(400)]);
+    }
+}
+
+#[test]
+fn reverse_index() {
+    let map = HashMap::<u32, u16>::with_capacity(64);
+    assert!(map.insert(0, 200));
+    let indexed = map.with_reverse_index();
+    for i in 1..32 {
+        assert!(indexed.insert(i, if i % 2 == 0 { 200 } else { 404 }));
+    }
+    assert_eq!(indexed.keys_with_value(200).len(), 16);
+    assert_eq!(indexed.keys_with_value(404).len(), 16);
+    assert!(indexed.keys_with_value(500).is_empty());
+
+    assert_eq!(indexed.update(&1, 200), Some(404));
+    assert_eq!(indexed.keys_with_value(404).len(), 15);
+    assert_eq!(indexed.keys_with_value(200)[..2], [&0, &1]);
+
+    // Concurrent updates end with every key indexed under its final value.
+    std::thread::scope(|s| {
+        for t in 0..4_u16 {
+            let indexed = &indexed;
+            s.spawn(move || {
+                for i in 0..32 {
+                    indexed.update(&i, t);
+                }
+            });
+        }
+    });
+    let total: usize = (0..4).map(|t| indexed.keys_with_value(t).len()).sum();
+    assert_eq!(total, 32);
+    assert!(indexed.keys_with_value(200).is_empty());
+}
+
+#[test]
+fn compact_filter() {
+    let map = HashMap::<String, u16>::with_capacity(64);
+    for i in 0..40 {
+        assert!(map.insert(i.to_string(), i));
+    }
+    map.set_flags("2", 0b11);
+
+    let even = map.compact_filter(|_, value| value % 2 == 0);
+    assert_eq!(even.len(), 20);
+    assert_eq!(even.get_with_flags("2"), Some((2, 0b11)));
+    assert_eq!(even.get("3"), None);
+    let keys: Vec<_> = even
+        .iter()
+        .map(|(key, _)| key.parse::<u16>().unwrap())
+        .collect();
+    assert_eq!(keys, (0..40).step_by(2).collect::<Vec<_>>());
+    // The new map can be inserted into, and rejects duplicates.
+    assert!(!even.insert("4".to_string(), 0));
+    assert!(even.insert("41".to_string(), 41));
+
+    let small = map
+        .try_compact_filter_with_capacity(10, |key, _| key.len() == 1)
+        .unwrap();
+    assert_eq!(small.len(), 10);
+    assert_eq!(small.get("9"), Some(9));
+    assert!(matches!(
+        map.try_compact_filter_with_capacity(10, |_, _| true),
+        Err(Error::Full)
+    ));
+}
+
+#[test]
+fn update_many() {
+    let map = HashMap::<u32, u16>::with_capacity(64);
+    for i in 0..32 {
+        assert!(map.insert(i, 0));
+    }
+    let updates = [(&3, 30), (&100, 1), (&7, 70), (&3, 31)];
+    assert_eq!(map.update_many(updates), [Some(0), None, Some(0), Some(30)]);
+    assert_eq!(map.get(&3), Some(31));
+    assert_eq!(map.get(&7), Some(70));
+    assert_eq!(map.get(&100), None);
+
+    let keys: Vec<u32> = (0..32).collect();
+    let results = map.update_many(keys.iter().map(|key| (key, 5)));
+    assert!(results.iter().all(Option::is_some));
+    assert!(map.iter().all(|(_, value)| value == 5));
+}
+
+#[test]
+fn insert_all_or_nothing() {
+    use crate::BatchError;
+
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    assert_eq!(map.insert_all_or_nothing(&[(1, 1), (2, 2)]), Ok(()));
+    assert_eq!(map.get(&2), Some(2));
+
+    assert_eq!(
+        map.insert_all_or_nothing(&[(3, 3), (4, 4), (3, 5)]),
+        Err(BatchError {
+            index: Some(2),
+            error: Error::DuplicateKey
+        })
+    );
+    // Entries placed before the existing key is found are rolled back.
+    assert_eq!(
+        map.insert_all_or_nothing(&[(3, 3), (4, 4), (1, 1)]),
+        Err(BatchError {
+            index: Some(2),
+            error: Error::DuplicateKey
+        })
+    );
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&3), None);
+    assert_eq!(map.iter().count(), 2);
+    // The rolled back keys can be inserted again, taking back their tombstones, but the
+    // tombstones of the others still use up capacity.
+    assert!(map.insert(3, 30));
+    assert_eq!(map._tombstones(), 1);
+    assert!(map.will_fit(12));
+    assert!(!map.will_fit(13));
+    assert_eq!(
+        map.insert_all_or_nothing(&(10..23).map(|i| (i, 0)).collect::<Vec<_>>()),
+        Err(BatchError {
+            index: None,
+            error: Error::Full
+        })
+    );
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn insert_all_or_nothing_concurrent() {
+    const BATCHES: u16 = 8;
+    let map = HashMap::<u32, u16>::with_capacity(1024);
+    std::thread::scope(|s| {
+        for batch in 0..BATCHES {
+            let map = &map;
+            s.spawn(move || {
+                // Every batch overlaps with the next one.
+                let keys = u32::from(batch) * 10..u32::from(batch) * 10 + 20;
+                let pairs: Vec<_> = keys.clone().map(|key| (key, batch)).collect();
+                let _ = map.insert_all_or_nothing(&pairs);
+                for key in keys {
+                    map.insert(key + 1000, 0);
+                }
+            });
+        }
+    });
+    for batch in 0..BATCHES {
+        let owned = (u32::from(batch) * 10..u32::from(batch) * 10 + 20)
+            .filter(|key| map.get(key) == Some(batch))
+            .count();
+        assert!(owned == 0 || owned == 20, "batch {batch} partly applied");
+    }
+    assert_eq!(map.len(), map.iter().count());
+}
+
+#[test]
+fn transaction() {
+    let map = HashMap::<String, u16>::with_capacity(16);
+    assert!(map.insert("a".to_string(), 1));
+
+    let mut transaction = map.transaction();
+    assert!(transaction.insert("b".to_string(), 2));
+    assert!(!transaction.insert("b".to_string(), 3));
+    assert!(!transaction.insert("a".to_string(), 3));
+    assert_eq!(transaction.update("a", 10), Some(1));
+    assert_eq!(transaction.update("a", 11), Some(10));
+    assert_eq!(transaction.update("b", 20), Some(2));
+    assert_eq!(transaction.update("c", 30), None);
+    assert_eq!(transaction.get("a"), Some(11));
+    assert_eq!(transaction.get("b"), Some(20));
+    assert!(transaction.contains_key("b"));
+    assert!(!transaction.contains_key("c"));
+    // Nothing is applied until the transaction commits.
+    assert_eq!(map.get("a"), Some(1));
+    assert_eq!(map.get("b"), None);
+    transaction.discard();
+    assert_eq!(map.len(), 1);
+
+    let mut transaction = map.transaction();
+    assert!(transaction.insert("b".to_string(), 2));
+    assert_eq!(transaction.update("a", 10), Some(1));
+    assert_eq!(transaction.commit(), Ok(()));
+    assert_eq!(map.get("a"), Some(10));
+    assert_eq!(map.get("b"), Some(2));
+
+    // A failed commit applies nothing.
+    let mut transaction = map.transaction();
+    assert!(transaction.insert("c".to_string(), 3));
+    assert_eq!(transaction.update("a", 100), Some(10));
+    assert!(map.insert("c".to_string(), 4));
+    assert!(transaction.commit().is_err());
+    assert_eq!(map.get("a"), Some(10));
+    assert_eq!(map.get("c"), Some(4));
+}
+
+#[test]
+fn raw_table_custom_entry() {
+    use crate::raw::{RawEntry, RawProbe, RawTable};
+
+    /// A 48-bit fingerprint and a 16-bit value, where a zero fingerprint means empty.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    // `u64` is only 4-byte aligned on some 32-bit targets, which would make it lock-based.
+    #[repr(align(8))]
+    struct Packed(u64);
+    unsafe impl bytemuck::NoUninit for Packed {}
+    unsafe impl RawEntry for Packed {}
+
+    let table = RawTable::<Packed>::try_new(16).unwrap();
+    assert!(RawTable::<Packed>::try_new(12).is_none());
+    assert_eq!(table.slots(), 16);
+
+    // Two fingerprints which start probing at the same index.
+    let (a, b) = (0x11_0003_u32, 0x22_0003_u32);
+    for fingerprint in [a, b] {
+        let start = table.index_for(fingerprint);
+        let (_, index) = table.find_empty(start).unwrap();
+        let new = Packed(u64::from(fingerprint) << 16 | 7);
+        assert_eq!(table.cas_entry(index, Packed::empty(), new), Ok(Packed(0)));
+    }
+    let start = table.index_for(b);
+    let Some((entry, index)) = table.find(start, |entry| entry.0 >> 16 == u64::from(b)) else {
+        panic!("not found");
+    };
+    assert_eq!(index, 4);
+    assert_eq!(
+        entry.load(atomic::Ordering::Relaxed),
+        Packed(u64::from(b) << 16 | 7)
+    );
+    assert!(matches!(
+        table.probe(start, |_| false),
+        RawProbe::Vacant(_, 5)
+    ));
+    assert_eq!(
+        table
+            .iter()
+            .filter(|e| !e.load(atomic::Ordering::Relaxed).is_empty())
+            .count(),
+        2
+    );
+}
+
+#[test]
+#[cfg(feature = "raw-iter")]
+fn raw_iter() {
+    let map = HashMap::<u16, u16>::with_capacity(16);
+    for i in 0..10 {
+        assert!(map.insert(i, i * 2));
+    }
+    map.set_flags(&3, 0b101);
+
+    let slots: Vec<_> = map.raw_iter().collect();
+    assert_eq!(slots.len(), 32);
+    assert_eq!(slots[9].0, 1);
+    assert_eq!(slots[9].1, 1);
+    let occupied: Vec<_> = slots.iter().filter(|(.., slot)| !slot.is_empty()).collect();
+    assert_eq!(occupied.len(), 10);
+    for (.., slot) in occupied {
+        let key = map.get_key(slot.key_index().unwrap()).unwrap();
+        assert_eq!(slot.value(), *key * 2);
+        assert_eq!(slot.flags(), if *key == 3 { 0b101 } else { 0 });
+        assert_eq!(slot.hash(), map._hash(key));
+    }
+}
+
+#[test]
+fn get_by_index() {
+    let map = HashMap::<String, u16>::with_capacity(64);
+    for i in 0..40 {
+        assert!(map.insert(i.to_string(), i));
+    }
+    let index = map.find_index("17").unwrap();
+    assert_eq!(map.find_index("100"), None);
+    assert_eq!(map.update("17", 170), Some(17));
+    assert_eq!(map.get_by_index(index), Some((&"17".to_string(), 170)));
+    let (key, value) = unsafe { map.get_unchecked_by_index(index) };
+    assert_eq!((key.as_str(), value), ("17", 170));
+
+    // An index from another map is rejected by the checked accessor.
+    let other = HashMap::<String, u16>::with_capacity(4);
+    assert_eq!(other.get_by_index(index), None);
+}
+
+#[test]
+fn export_into() {
+    use core::mem::MaybeUninit;
+
+    let map = HashMap::<u16, u16>::with_capacity(16);
+    for i in 0..10 {
+        assert!(map.insert(i, 100 + i));
+    }
+    let mut keys = [MaybeUninit::<u16>::uninit(); 16];
+    let mut values = [MaybeUninit::<u16>::uninit(); 16];
+    let written = map.export_into(&mut keys, &mut values);
+    assert_eq!(written, 10);
+    for (i, (key, value)) in (0..).zip(keys.iter().zip(&values).take(written)) {
+        let (key, value) = unsafe { (key.assume_init(), value.assume_init()) };
+        assert_eq!((key, value), (i, 100 + i));
+    }
+
+    // Stops when either buffer is full.
+    assert_eq!(map.export_into(&mut keys[..4], &mut values), 4);
+}
+
+#[test]
+fn from_slices() {
+    let keys: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+    let values: Vec<u16> = (0..1000).collect();
+    let map = HashMap::try_from_slices(&keys, &values).unwrap();
+    assert_eq!(map.len(), 1000);
+    assert!(!map.will_fit(1));
+    assert_eq!(map.get("999"), Some(999));
+    assert!(map.iter().map(|(key, _)| key).eq(keys.iter()));
+
+    let pairs = [("a", 1_u16), ("b", 2), ("a", 3)];
+    assert!(matches!(
+        HashMap::try_from_slice(&pairs),
+        Err(Error::DuplicateKey)
+    ));
+    let map = HashMap::try_from_slice(&pairs[..2]).unwrap();
+    assert_eq!(map.get("b"), Some(2));
+}
+
+#[test]
+fn clone_resized() {
+    let map = HashMap::<u16, u16>::with_capacity(10);
+    for i in 0..10 {
+        assert!(map.insert(i, i));
+    }
+    assert!(!map.insert(10, 10));
+
+    let larger = map.clone_resized(100).unwrap();
+    assert!(larger.iter().eq(map.iter()));
+    for i in 10..100 {
+        assert!(larger.insert(i, i));
+    }
+    assert_eq!(larger.len(), 100);
+    assert_eq!(map.len(), 10);
+
+    assert!(matches!(map.clone_resized(5), Err(Error::Full)));
+}
+
+#[test]
+fn split_into_shards() {
+    let map = HashMap::<u32, u16>::with_capacity(1000);
+    for i in 0..1000 {
+        assert!(map.insert(i, 0));
+    }
+    let shards = map.split_into_shards(4).unwrap();
+    assert_eq!(shards.len(), 4);
+    assert_eq!(shards.iter().map(HashMap::len).sum::<usize>(), 1000);
+    for i in 0..1000 {
+        let shard = map.shard_for(&i, 4);
+        assert!(shards[shard].contains_key(&i));
+        assert_eq!(shards[0].shard_for(&i, 4), shard);
+    }
+    // Every shard gets a share of the keys.
+    assert!(shards.iter().all(|shard| shard.len() > 150));
+
+    let one = map.split_into_shards(1).unwrap();
+    assert!(one[0].iter().eq(map.iter()));
+}
+
+#[test]
+fn compact_key_store() {
+    let mut map = HashMap::<u32, u16>::with_capacity(16);
+    assert!(map.insert(1, 1));
+    assert!(map
+        .insert_all_or_nothing(&[(2, 2), (3, 3), (1, 1)])
+        .is_err());
+    assert!(map.insert(4, 4));
+    let checkpoint = map.checkpoint();
+    assert_eq!(map.compact_key_store(), 2);
+    assert_eq!(map.restore(&checkpoint), Err(Error::InvalidSnapshot));
+    assert_eq!(map.compact_key_store(), 0);
+
+    assert_eq!(map.get(&1), Some(1));
+    assert_eq!(map.get(&4), Some(4));
+    assert!(map.insert(2, 20));
+    assert_eq!(map.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [1, 4, 2]);
+    assert_eq!(map.len(), 3);
+
+    // A removed key is dropped, so inserting it again pushes it afresh.
+    assert_eq!(map.remove(&4), Some(4));
+    assert_eq!(map.compact_key_store(), 1);
+    assert!(map.insert(4, 40));
+    assert_eq!(map.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [1, 2, 4]);
+    assert_eq!((map.len(), map._tombstones()), (3, 3));
+}
+
+#[test]
+fn encode_decode() {
+    let map = HashMap::<u32, u16>::with_capacity(4);
+    assert!(map.insert(0x0102_0304, 0x0506));
+    assert!(map.insert(7, 8));
+    map.set_flags(&7, 0b1010);
+
+    // The encoding is little-endian on every target.
+    let fixture: &[u8] = &[
+        b'f', b'l', b'k', 1, 4, 0, 2, 0, 4, 0, 2, 0, //
+        0, 4, 3, 2, 1, 6, 5, //
+        0b1010, 7, 0, 0, 0, 8, 0,
+    ];
+    assert_eq!(map.encode(), fixture);
+
+    let decoded = HashMap::<u32, u16>::decode(fixture).unwrap();
+    assert!(decoded.iter().eq(map.iter()));
+    assert_eq!(decoded.get_with_flags(&7), Some((8, 0b1010)));
+    assert_eq!(decoded.capacity, 4);
+
+    // The same map written with native big-endian integers is rejected, not misread.
+    let byte_swapped: &[u8] = &[
+        b'f', b'l', b'k', 1, 0, 4, 0, 2, 0, 4, 0, 2, //
+        0, 1, 2, 3, 4, 5, 6, //
+        0b1010, 0, 0, 0, 7, 0, 8,
+    ];
+    assert_eq!(
+        HashMap::<u32, u16>::decode(byte_swapped).err(),
+        Some(Error::InvalidSnapshot)
+    );
+    assert_eq!(
+        HashMap::<u32, u16>::decode(&fixture[..fixture.len() - 1]).err(),
+        Some(Error::InvalidSnapshot)
+    );
+}
+
+#[test]
+fn page_aligned_table() {
+    let map = HashMap::<u32, u16>::try_with_capacity_and_hasher_aligned(
+        10,
+        DefaultHashBuilder::default(),
+        4096,
+    )
+    .unwrap();
+    assert_eq!(core::ptr::from_ref(map.table.entry(0)) as usize % 4096, 0);
+    assert_eq!(map.table.align(), 4096);
+    assert!(map.insert(1, 1));
+    let clone = map.clone();
+    assert_eq!(core::ptr::from_ref(clone.table.entry(0)) as usize % 4096, 0);
+    assert_eq!(clone.get(&1), Some(1));
+
+    assert_eq!(
+        HashMap::<u32, u16>::try_with_capacity_and_hasher_aligned(
+            10,
+            DefaultHashBuilder::default(),
+            3
+        )
+        .err(),
+        Some(Error::AllocationFailed)
+    );
+}
+
+#[test]
+fn count_saturates() {
+    let map = HashMap::<u32, u16>::with_capacity(4);
+    assert!(map.insert(1, 1));
+    assert_eq!(map._reserve_count(u16::MAX), Err(Error::Full));
+    assert_eq!(map.len(), 1);
+    map._release_count(1);
+    assert_eq!(map.len(), 0);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic = "released 1 entries, but only 0 were counted"]
+fn count_underflow() {
+    let map = HashMap::<u32, u16>::with_capacity(4);
+    map._release_count(1);
+}
+
+#[test]
+#[cfg(feature = "test-util")]
+fn stress_test() {
+    use crate::stress::{stress_test, OpMix, StressConfig};
+    use std::time::Duration;
+
+    let report = stress_test(&StressConfig {
+        threads: 4,
+        duration: Duration::from_millis(200),
+        capacity: 1024,
+        mix: OpMix {
+            get: 2,
+            insert: 2,
+            update: 1,
+        },
+        seed: 7,
+    })
+    .unwrap();
+    assert!(report.inserts > 0 && report.updates > 0 && report.gets > 0);
+    assert!(report.len > 0 && report.len <= 1024);
+}
+
+#[test]
+fn probe_limit() {
+    let keys: Vec<u64> = StdRng::seed_from_u64(5)
+        .sample_iter(rand::distributions::Standard)
+        .take(64)
+        .collect();
+    let mut map = HashMap::<u64, u16, _>::with_capacity_and_seed(64, 3);
+    assert_eq!(map.probe_limit(), None);
+    assert_eq!(map.set_probe_limit(1), Ok(()));
+    assert_eq!(map.probe_limit(), Some(1));
+
+    // With a limit of one, every key must be in the entry its probe starts at.
+    let mut rejected = 0;
+    for &key in &keys {
+        match map.try_insert(key, 1) {
+            Ok(()) => {}
+            Err(Error::ProbeLimitExceeded) => rejected += 1,
+            Err(error) => panic!("unexpected error {error:?}"),
+        }
+    }
+    assert!(rejected > 0);
+    assert_eq!(map.len(), 64 - rejected);
+    assert_eq!(
+        keys.iter().filter(|key| map.contains_key(key)).count(),
+        map.len()
+    );
+    assert!(map.iter().all(|(key, _)| map.find_index(key).is_some()));
+
+    // Keys further along their sequence than a new limit prevent it from being set.
+    let mut map = HashMap::<u64, u16, _>::with_capacity_and_seed(64, 3);
+    for &key in &keys {
+        assert!(map.insert(key, 1));
+    }
+    assert_eq!(map.set_probe_limit(1), Err(Error::ProbeLimitExceeded));
+    assert_eq!(map.probe_limit(), None);
+    assert_eq!(map.set_probe_limit(128), Ok(()));
+}
+
+#[test]
+fn watermark() {
+    let mut map = HashMap::<u32, u16>::with_capacity(10);
+    assert_eq!(map.watermark(), None);
+    assert!(!map.is_near_full());
+    map.set_watermark(8);
+    assert_eq!(map.watermark(), Some(8));
+
+    let occupancy: Vec<_> = (0..10)
+        .map(|key| map.try_insert_with_occupancy(key, 0))
+        .collect();
+    assert_eq!(occupancy[6], Ok(Occupancy::BelowWatermark));
+    assert_eq!(occupancy[7], Ok(Occupancy::CrossedWatermark));
+    assert_eq!(occupancy[9], Ok(Occupancy::NearFull));
+    assert!(map.is_near_full());
+    assert_eq!(map.try_insert_with_occupancy(10, 0), Err(Error::Full));
+
+    // Setting the watermark again re-arms the crossing.
+    let mut map = HashMap::<u32, u16>::with_capacity(10);
+    map.set_watermark(1);
+    assert_eq!(
+        map.try_insert_with_occupancy(0, 0),
+        Ok(Occupancy::CrossedWatermark)
+    );
+    map.set_watermark(2);
+    assert_eq!(
+        map.try_insert_with_occupancy(1, 0),
+        Ok(Occupancy::CrossedWatermark)
+    );
+}
+
+#[test]
+fn small_string_keys() {
+    let map = HashMap::<SmallString<8>, u16>::with_capacity(16);
+    let short = SmallString::<8>::from("eight_b_");
+    let long = SmallString::<8>::from(String::from("nine_byte"));
+    assert!(short.is_inline());
+    assert!(!long.is_inline());
+
+    assert!(map.insert(short, 1));
+    assert!(map.insert(long, 2));
+    assert!(!map.insert("eight_b_".into(), 3));
+    assert_eq!(map.get("eight_b_"), Some(1));
+    assert_eq!(map.get("nine_byte"), Some(2));
+    assert_eq!(map.get("nine_bytes"), None);
+}
+
+#[test]
+fn iter_reuses_cached_hashes() {
+    use core::cell::Cell;
+    use core::hash::{Hash, Hasher};
+
+    #[derive(Clone)]
+    struct Counted<'a>(u32, &'a Cell<usize>);
+    impl PartialEq for Counted<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl Eq for Counted<'_> {}
+    impl Hash for Counted<'_> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.1.set(self.1.get() + 1);
+            self.0.hash(state);
+        }
+    }
+
+    let hashes = Cell::new(0);
+    let map = HashMap::<Counted, u16>::with_capacity(32);
+    for key in 0..20 {
+        assert!(map.insert(Counted(key, &hashes), 0));
+    }
+    hashes.set(0);
+    assert_eq!(map.iter().count(), 20);
+    assert_eq!(hashes.get(), 0);
+    assert_eq!(map.clone_resized(64).map(|map| map.len()), Ok(20));
+    assert_eq!(hashes.get(), 0);
+}
+
+#[test]
+fn hash_key() {
+    let a = HashMap::<u64, u16, _>::with_capacity_and_seed(16, 7);
+    let b = HashMap::<u64, u16, _>::with_capacity_and_seed(64, 7);
+    for key in 0..100_u64 {
+        assert_eq!(a.hash_key(&key), b.hash_key(&key));
+        assert_eq!(a.hash_key(&key) & !crate::constants::HASH_MASK, 0);
+    }
+    let other = HashMap::<u64, u16, _>::with_capacity_and_seed(16, 8);
+    assert!((0..100_u64).any(|key| a.hash_key(&key) != other.hash_key(&key)));
+}
+
+#[test]
+fn inserter_batches_count() {
+    let map = HashMap::<u32, u16>::with_capacity(10);
+    let mut inserter = map.inserter(4);
+    assert!(inserter.insert(0, 0));
+    // The rest of the batch is counted until it is used or flushed.
+    assert_eq!(map.len(), 4);
+    assert_eq!(inserter.try_insert(0, 1), Err(Error::DuplicateKey));
+    assert!(inserter.insert(1, 1));
+    inserter.flush();
+    assert_eq!(map.len(), 2);
+
+    // Once a whole batch doesn't fit, the handle reserves one key at a time.
+    for key in 2..10 {
+        assert_eq!(inserter.try_insert(key, 0), Ok(()));
+    }
+    assert_eq!(inserter.try_insert(10, 0), Err(Error::Full));
+    drop(inserter);
+    assert_eq!(map.len(), 10);
+    assert_eq!(map.iter().count(), 10);
+}
+
+#[cfg(feature = "epoch")]
+#[test]
+fn epoch_reclaim() {
+    use crate::reclaim::{Epoch, Reclaim};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct SetOnDrop(Arc<AtomicBool>);
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let ptr = alloc::boxed::Box::into_raw(alloc::boxed::Box::new(SetOnDrop(dropped.clone())));
+    let guard = Epoch.pin();
+    unsafe { Epoch.retire(&guard, ptr) };
+    drop(guard);
+    for _ in 0..1000 {
+        if dropped.load(Ordering::Relaxed) {
+            break;
+        }
+        Epoch.quiesce();
+    }
+    assert!(dropped.load(Ordering::Relaxed));
+}
+
+#[test]
+fn pinned_get_ref() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert("a".to_string(), 1));
+    let pinned = map.pin();
+    let mut a = pinned.get_ref("a").unwrap();
+    assert!(pinned.get_ref("b").is_none());
+    assert_eq!(a.key(), "a");
+    assert_eq!(*a, 1);
+
+    assert_eq!(map.update("a", 2), Some(1));
+    assert_eq!(*a, 1);
+    assert_eq!(a.reload(), 2);
+    assert_eq!(*a, 2);
+}
+
+#[test]
+fn batch_rolls_back_on_panic() {
+    use core::hash::{Hash, Hasher};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    /// A key which hashes by `bucket` only, and whose `Eq` panics if `panics` is set.
+    #[derive(Clone, Debug)]
+    struct Key {
+        id: u32,
+        bucket: u32,
+        panics: bool,
+    }
+    impl PartialEq for Key {
+        fn eq(&self, other: &Self) -> bool {
+            assert!(!self.panics && !other.panics, "Eq panicked");
+            self.id == other.id
+        }
+    }
+    impl Eq for Key {}
+    impl Hash for Key {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.bucket.hash(state);
+        }
+    }
+    let key = |id, bucket, panics| Key { id, bucket, panics };
+
+    let map = HashMap::<Key, u16>::with_capacity(8);
+    assert!(map.insert(key(1, 0, false), 1));
+    let batch = [(key(2, 1, false), 2), (key(3, 0, true), 3)];
+    let result = catch_unwind(AssertUnwindSafe(|| map.insert_all_or_nothing(&batch)));
+    assert!(result.is_err());
+
+    // The count was released, and the first key's pending entry was rolled back rather than left
+    // for other inserts to wait on forever.
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get(&key(2, 1, false)), None);
+    assert!(map.insert(key(2, 1, false), 2));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn pod_keys() {
+    let map = HashMap::<PodKey<[u8; 16]>, u16>::with_capacity(64);
+    for i in 0..50_u8 {
+        assert!(map.insert(PodKey([i; 16]), u16::from(i)));
+    }
+    assert!(!map.insert(PodKey([7; 16]), 0));
+    for i in 0..50_u8 {
+        assert_eq!(map.get(&PodKey([i; 16])), Some(u16::from(i)));
+        // Lookups through `Borrow` use the key's own `Eq`.
+        assert_eq!(map.get(&[i; 16]), Some(u16::from(i)));
+    }
+    assert_eq!(map.get(&PodKey([50; 16])), None);
+}
+
+#[test]
+fn boxed_keys() {
+    use alloc::boxed::Box;
+    // A boxed key takes a pointer in the key store, rather than the whole key.
+    assert_eq!(
+        core::mem::size_of::<crate::HashedKey<Box<[u8; 512]>>>(),
+        2 * core::mem::size_of::<usize>()
+    );
+    let map = HashMap::<Box<[u8; 512]>, u16>::with_capacity(64);
+    for i in 0..50_u8 {
+        assert!(map.insert(Box::new([i; 512]), u16::from(i)));
+    }
+    assert!(!map.insert(Box::new([7; 512]), 0));
+    for i in 0..50_u8 {
+        assert_eq!(map.get(&[i; 512]), Some(u16::from(i)));
+    }
+    assert_eq!(map.iter().count(), 50);
+}
+
+#[cfg(feature = "raw-iter")]
+#[test]
+fn debug_dump() {
+    let map = HashMap::<&str, u16>::with_capacity(8);
+    assert!(map.insert("short", 1));
+    assert!(map.insert("a key which is far too long to show in full", 2));
+    let dump = map.debug_dump();
+    let mut lines = dump.lines();
+    assert_eq!(
+        lines.next(),
+        Some("len 2, capacity 8, tombstones 0, slots 16, probe limit None")
+    );
+    assert_eq!(
+        dump.lines()
+            .filter(|line| line.starts_with("bucket"))
+            .count(),
+        2
+    );
+    assert_eq!(
+        dump.lines().filter(|line| line.ends_with("empty")).count(),
+        14
+    );
+    assert!(dump.contains("\"short\" => 1"));
+    assert!(dump.contains("\"a key which is far too … => 2"));
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn injected_faults() {
+    use crate::inject::{self, Fault, Point};
+
+    let map = HashMap::<u32, u16>::with_capacity(8);
+    // A lost CAS is retried from the same entry, which is still empty.
+    inject::set(Point::InsertCas, Fault::Fail { times: 3 });
+    assert_eq!(map.try_insert(1, 1), Ok(()));
+    assert_eq!(map.get(&1), Some(1));
+
+    inject::set(Point::KeyStorePush, Fault::Fail { times: 2 });
+    assert_eq!(map.try_insert(2, 2), Err(Error::KeyStoreFull));
+    assert_eq!(
+        map.insert_all_or_nothing(&[(2, 2), (3, 3)])
+            .map_err(|error| error.error),
+        Err(Error::KeyStoreFull)
+    );
+    // The failed inserts released what they reserved.
+    assert_eq!(map.len(), 1);
+    assert!(map.will_fit(7));
+    assert_eq!(map.try_insert(2, 2), Ok(()));
+
+    inject::set(Point::InsertCas, Fault::Fail { times: 1 });
+    inject::clear();
+    assert_eq!(map.try_insert(3, 3), Ok(()));
+}
+
+#[test]
+fn concurrent_map_trait() {
+    use crate::{ConcurrentMap, WriteLogger};
+
+    fn exercise<M>(map: &M)
+    where
+        M: ConcurrentMap<Key = u32>,
+        M::Value: From<u16> + PartialEq + core::fmt::Debug,
+    {
+        assert!(map.is_empty());
+        assert!(map.insert(1, 1.into()));
+        assert!(!map.insert(1, 2.into()));
+        assert_eq!(map.update(&1, 3.into()), Some(1.into()));
+        assert_eq!(map.get(&1), Some(3.into()));
+        assert_eq!(map.update(&2, 3.into()), None);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.capacity(), 16);
+    }
+
+    struct NoLog;
+    impl WriteLogger<u32, u16> for NoLog {
+        fn log_insert(&self, _: usize, _: &u32, _: u16) {}
+        fn log_update(&self, _: usize, _: u16) {}
+        fn log_remove(&self, _: usize) {}
+    }
+
+    exercise(&HashMap::<u32, u16>::with_capacity(16));
+    exercise(&HashMap::<u32, u16>::with_capacity(16).with_logger(NoLog));
+    exercise(&crate::ShardedHashMap::<u32, u16>::with_capacity(16));
+    exercise(&crate::GrowableHashMap::<u32, u16>::with_capacity(16));
+    #[cfg(feature = "wide")]
+    if let Ok(map) = crate::WideHashMap::<u32, u32>::try_with_capacity(16) {
+        exercise(&map);
+    }
+}
+
+#[test]
+fn bits_values() {
+    use crate::{Bits, ToBits};
+
+    /// Has a padding byte after `tag`, so it isn't `NoUninit`.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    struct Padded {
+        tag: u8,
+        value: u16,
+    }
+    impl ToBits for Padded {
+        fn to_bits(self) -> u16 {
+            u16::from(self.tag) << 12 | (self.value & 0xfff)
+        }
+        fn from_bits(bits: u16) -> Self {
+            #[allow(clippy::cast_possible_truncation)]
+            let tag = (bits >> 12) as u8;
+            Padded {
+                tag,
+                value: bits & 0xfff,
+            }
+        }
+    }
+
+    let map = HashMap::<u32, Bits<Padded>>::with_capacity(8);
+    let a = Padded { tag: 3, value: 100 };
+    assert!(map.insert(1, a.into()));
+    assert_eq!(map.get(&1).map(Bits::get), Some(a));
+    let b = Padded { tag: 4, value: 5 };
+    assert_eq!(map.update(&1, Bits::new(b)), Some(Bits::new(a)));
+    assert_eq!(map.get(&1).map(Bits::get), Some(b));
+}
+
+#[test]
+fn rekey() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert("old".to_string(), 1));
+    assert!(map.insert("taken".to_string(), 2));
+    assert_eq!(map.set_flags("old", 0b101), Some(0));
+
+    assert_eq!(map.rekey("old", "new".to_string()), Ok(1));
+    assert_eq!(map.get("old"), None);
+    assert_eq!(map.get_with_flags("new"), Some((1, 0b101)));
+    assert_eq!(map.update("old", 3), None);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.iter().count(), 2);
+
+    assert_eq!(
+        map.rekey("old", "newer".to_string()),
+        Err(Error::KeyNotFound)
+    );
+    assert_eq!(
+        map.rekey("new", "taken".to_string()),
+        Err(Error::DuplicateKey)
+    );
+    // The old key can be inserted again, and takes back the tombstone it left.
+    assert!(map.insert("old".to_string(), 4));
+    assert_eq!(map.iter().count(), 3);
+    assert_eq!(map.union_keys(&map).count(), 3);
+    assert!(map.will_fit(5));
+    assert!(!map.will_fit(6));
+}
+
+#[test]
+fn freeze() {
+    let map = HashMap::<String, u16>::with_capacity(16);
+    for i in 0..10u16 {
+        assert!(map.insert(i.to_string(), i));
+    }
+    assert_eq!(map.set_flags("3", 0b11), Some(0));
+    assert_eq!(map.rekey("9", "nine".to_string()), Ok(9));
+
+    let frozen = map.freeze();
+    assert_eq!(frozen.len(), 10);
+    assert_eq!(frozen.get("3"), Some(3));
+    assert_eq!(frozen.get_with_flags("3"), Some((3, 0b11)));
+    assert_eq!(frozen.get("nine"), Some(9));
+    assert_eq!(frozen.get("9"), None);
+    assert!(!frozen.contains_key("10"));
+    let keys: Vec<_> = frozen.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["0", "1", "2", "3", "4", "5", "6", "7", "8", "nine"]);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn insert_when_available() {
+    use core::future::Future;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::{Context, Poll};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let map = HashMap::<u32, u16>::with_capacity(4);
+    let flag = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Arc::clone(&flag).into();
+    let mut cx = Context::from_waker(&waker);
+
+    // An inserter holding every slot leaves no room until it flushes.
+    let mut inserter = map.inserter(4);
+    assert!(inserter.insert(1, 1));
+    let mut insert = map.insert_when_available(2, 2);
+    assert_eq!(
+        core::pin::Pin::new(&mut insert).poll(&mut cx),
+        Poll::Pending
+    );
+    assert!(!flag.0.load(Ordering::Relaxed));
+
+    inserter.flush();
+    assert!(flag.0.load(Ordering::Relaxed));
+    assert_eq!(
+        core::pin::Pin::new(&mut insert).poll(&mut cx),
+        Poll::Ready(Ok(()))
+    );
+    assert_eq!(map.get(&2), Some(2));
+
+    let mut duplicate = map.insert_when_available(2, 3);
+    assert_eq!(
+        core::pin::Pin::new(&mut duplicate).poll(&mut cx),
+        Poll::Ready(Err(Error::DuplicateKey))
+    );
+    assert_eq!(map.len(), 2);
+
+    // Only the removed key can take back the tombstone, so only its tasks are woken.
+    let map = HashMap::<u32, u16>::with_capacity(4);
+    let (same, other) = (
+        Arc::new(Flag(AtomicBool::new(false))),
+        Arc::new(Flag(AtomicBool::new(false))),
+    );
+    let (same_waker, other_waker) = (Arc::clone(&same).into(), Arc::clone(&other).into());
+    let mut same_cx = Context::from_waker(&same_waker);
+    let mut other_cx = Context::from_waker(&other_waker);
+    let mut inserter = map.inserter(4);
+    assert!(inserter.insert(3, 3));
+    let mut insert_same = map.insert_when_available(1, 10);
+    let mut insert_other = map.insert_when_available(2, 20);
+    assert_eq!(
+        core::pin::Pin::new(&mut insert_same).poll(&mut same_cx),
+        Poll::Pending
+    );
+    assert_eq!(
+        core::pin::Pin::new(&mut insert_other).poll(&mut other_cx),
+        Poll::Pending
+    );
+
+    assert!(inserter.insert(1, 1));
+    assert_eq!(map.remove(&1), Some(1));
+    assert!(same.0.load(Ordering::Relaxed));
+    assert!(!other.0.load(Ordering::Relaxed));
+    assert_eq!(
+        core::pin::Pin::new(&mut insert_same).poll(&mut same_cx),
+        Poll::Ready(Ok(()))
+    );
+    assert_eq!(map.get(&1), Some(10));
+    assert_eq!(
+        core::pin::Pin::new(&mut insert_other).poll(&mut other_cx),
+        Poll::Pending
+    );
+
+    inserter.flush();
+    assert!(other.0.load(Ordering::Relaxed));
+    assert_eq!(
+        core::pin::Pin::new(&mut insert_other).poll(&mut other_cx),
+        Poll::Ready(Ok(()))
+    );
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn watch() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert("key".to_string(), 1));
+    assert!(map.watch("missing").is_none());
+    let mut watcher = map.watch("key").unwrap();
+    assert!(watcher.poll_changed().is_pending());
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            map.update("key", 2);
+        });
+        assert_eq!(watcher.changed(), Some(2));
+    });
+    assert_eq!(watcher.value(), 2);
+    assert_eq!(map.set_flags("key", 1), Some(0));
+    assert_eq!(watcher.poll_changed(), core::task::Poll::Ready(Some(2)));
+
+    assert_eq!(map.rekey("key", "other".to_string()), Ok(2));
+    assert_eq!(watcher.poll_changed(), core::task::Poll::Ready(None));
+    assert_eq!(watcher.poll_changed(), core::task::Poll::Ready(None));
+
+    // A removed key ends the watch, rather than leaving `changed` waiting forever.
+    let mut watcher = map.watch("other").unwrap();
+    assert_eq!(map.remove("other"), Some(2));
+    assert_eq!(watcher.changed(), None);
+    assert_eq!(watcher.changed(), None);
+}
+
+#[test]
+fn rehash_with() {
+    let mut map = HashMap::<String, u16, _>::with_capacity_and_seed(8, 1);
+    for i in 0..6u16 {
+        assert!(map.insert(i.to_string(), i));
+    }
+    assert_eq!(map.set_flags("2", 0b1), Some(0));
+    assert_eq!(map.rekey("5", "five".to_string()), Ok(5));
+    // Reclaim the old key's slot in the key store, so only the tombstone is in the way.
+    assert_eq!(map.compact_key_store(), 1);
+    assert!(!map.will_fit(2));
+    let hash = map.hash_key("0");
+
+    assert_eq!(map.rehash_with(crate::SeededHashBuilder::new(2)), Ok(()));
+    assert_ne!(map.hash_key("0"), hash);
+    for i in 0..5u16 {
+        assert_eq!(map.get(&i.to_string()), Some(i));
+    }
+    assert_eq!(map.get_with_flags("2"), Some((2, 0b1)));
+    assert_eq!(map.get("five"), Some(5));
+    assert_eq!(map.get("5"), None);
+    assert_eq!(map.iter().count(), 6);
+    // The tombstone left by the rekey is gone.
+    assert!(map.will_fit(2));
+
+    // With the tightest probe limit the layout allows, some other seed won't fit it.
+    let limit = (1..16).find(|&limit| map.set_probe_limit(limit).is_ok());
+    assert!(limit.is_some());
+    let failed = (3..1000).any(|seed| {
+        let hash = map.hash_key("0");
+        let result = map.rehash_with(crate::SeededHashBuilder::new(seed));
+        result == Err(Error::ProbeLimitExceeded) && map.hash_key("0") == hash
+    });
+    assert!(failed);
+    for i in 0..5u16 {
+        assert_eq!(map.get(&i.to_string()), Some(i));
+    }
+    assert_eq!(map.get("five"), Some(5));
+
+    // A checkpoint's entries were placed with the old hasher, so it can't be restored.
+    let mut map = HashMap::<u32, u16, _>::with_capacity_and_seed(8, 1);
+    assert!(map.insert(1, 1));
+    let checkpoint = map.checkpoint();
+    assert_eq!(map.rehash_with(crate::SeededHashBuilder::new(2)), Ok(()));
+    assert_eq!(map.restore(&checkpoint), Err(Error::InvalidSnapshot));
+    assert_eq!(map.get(&1), Some(1));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn to_json_string() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert_eq!(map.to_json_string().unwrap(), "{}");
+    assert!(map.insert("b".to_string(), 2));
+    assert!(map.insert("a".to_string(), 1));
+    assert_eq!(map.to_json_string().unwrap(), r#"{"b":2,"a":1}"#);
+    assert_eq!(
+        map.to_json_string_pretty().unwrap(),
+        "{\n  \"b\": 2,\n  \"a\": 1\n}"
+    );
+
+    let map = HashMap::<(u8, u8), u16>::with_capacity(8);
+    assert!(map.insert((1, 2), 3));
+    assert!(map.to_json_string().is_err());
+}
+
+#[test]
+fn map_values() {
+    let map = HashMap::<String, u16>::with_capacity(8);
+    for i in 0..6u16 {
+        assert!(map.insert(i.to_string(), i));
+    }
+    assert_eq!(map.set_flags("1", 0b10), Some(0));
+    assert_eq!(map.rekey("2", "two".to_string()), Ok(2));
+
+    let negated = map.map_values(|value| -i16::try_from(value).unwrap());
+    assert_eq!(negated.len(), 6);
+    assert_eq!(negated.get("3"), Some(-3));
+    assert_eq!(negated.get_with_flags("1"), Some((-1, 0b10)));
+    assert_eq!(negated.get("two"), Some(-2));
+    assert_eq!(negated.get("2"), None);
+    let keys: Vec<_> = negated.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["0", "1", "3", "4", "5", "two"]);
+    // The tombstone is copied, so the new map has the same room left.
+    assert!(negated.will_fit(1));
+    assert!(!negated.will_fit(2));
+    assert!(negated.insert("6".to_string(), 6));
+}
+
+#[test]
+fn cursor() {
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    for i in 0..4 {
+        assert!(map.insert(i, 0));
+    }
+    let mut cursor = map.cursor();
+    assert_eq!(cursor.next(&map), Some((&0, 0)));
+    assert_eq!(cursor.next(&map), Some((&1, 0)));
+    assert!(map.insert(4, 0));
+    assert_eq!(cursor.next(&map), Some((&2, 0)));
+    assert_eq!(cursor.next(&map), Some((&3, 0)));
+    // Keys inserted after the cursor was created aren't yielded until it is extended.
+    assert_eq!(cursor.next(&map), None);
+    assert!(cursor.is_finished());
+    cursor.extend(&map);
+    assert_eq!(cursor.next(&map), Some((&4, 0)));
+    assert_eq!(cursor.next(&map), None);
+
+    let mut resumed = map.resume_cursor(3);
+    assert_eq!(resumed.position(), 3);
+    assert_eq!(resumed.next(&map), Some((&3, 0)));
+    assert_eq!(resumed.next(&map), Some((&4, 0)));
+    assert_eq!(resumed.next(&map), None);
+}
+
+#[test]
+fn insert_ref() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(PartialEq, Eq, Hash)]
+    struct Key(u32);
+
+    impl Clone for Key {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::Relaxed);
+            Key(self.0)
+        }
+    }
+
+    let map = HashMap::<Key, u16>::with_capacity(8);
+    assert!(map.insert_ref(&Key(1), 1));
+    assert_eq!(CLONES.load(Ordering::Relaxed), 1);
+    // A duplicate isn't cloned.
+    assert!(!map.insert_ref(&Key(1), 2));
+    assert_eq!(map.try_insert_ref(&Key(1), 2), Err(Error::DuplicateKey));
+    assert_eq!(CLONES.load(Ordering::Relaxed), 1);
+    assert_eq!(map.get(&Key(1)), Some(1));
+}
+
+#[test]
+fn insert_cow() {
+    use alloc::borrow::Cow;
+
+    let map = HashMap::<String, u16>::with_capacity(8);
+    assert!(map.insert_cow(Cow::Borrowed("a"), 1));
+    assert!(!map.insert_cow(Cow::Borrowed("a"), 2));
+    assert_eq!(
+        map.try_insert_cow(Cow::<str>::Owned("a".to_string()), 2),
+        Err(Error::DuplicateKey)
+    );
+    assert!(map.insert_cow(Cow::<str>::Owned("b".to_string()), 2));
+    assert_eq!(map.get("a"), Some(1));
+    assert_eq!(map.get("b"), Some(2));
+    assert_eq!(map.len(), 2);
+
+    // A panicking value function leaves nothing behind.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        map.get_or_insert_with("c".to_string(), || panic!("no value"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(map.get("c"), None);
+    assert_eq!(map.len(), 2);
+    assert!(map.insert("c".to_string(), 3));
+}
+
+#[test]
+fn external_keys() {
+    use crate::ExternalKeyMap;
+
+    let names: Vec<String> = ["a", "b", "c", "a"]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let map = ExternalKeyMap::<_, u16>::with_capacity(3, names.as_slice());
+    assert!(map.insert(0, 1));
+    assert!(map.insert(1, 2));
+    // Index 3 resolves to a key which is already in the map.
+    assert_eq!(map.try_insert(3, 3), Err(Error::DuplicateKey));
+    assert_eq!(map.try_insert(4, 3), Err(Error::KeyNotFound));
+    assert_eq!(map.get("a"), Some(1));
+    assert_eq!(map.find_index("b"), Some(1));
+    assert_eq!(map.update("b", 5), Some(2));
+    assert_eq!(map.get("b"), Some(5));
+    assert!(!map.contains_key("c"));
+    assert!(map.insert(2, 3));
+    assert_eq!(map.len(), 3);
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_unstable();
+    assert_eq!(entries, [(0, 1), (1, 5), (2, 3)]);
+
+    let names: Vec<String> = (0..4).map(|i| i.to_string()).collect();
+    let full = ExternalKeyMap::<_, u16>::with_capacity(3, &names);
+    for i in 0..3 {
+        assert!(full.insert(i, 0));
+    }
+    assert_eq!(full.try_insert(3, 0), Err(Error::Full));
+    assert_eq!(full.len(), 3);
+}
+
+#[test]
+fn update_atomic() {
+    let map = HashMap::<u32, u16>::with_capacity(8);
+    assert!(map.insert(0, 1000));
+    assert!(map.insert(1, 0));
+    assert_eq!(
+        map.update_atomic(&[&0, &2], |_| unreachable!()),
+        Err(Error::KeyNotFound)
+    );
+
+    // Transfers between the keys never show a torn total.
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..1000 {
+                map.update_atomic(&[&0, &1], |values| {
+                    values[0] -= 1;
+                    values[1] += 1;
+                })
+                .unwrap();
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..1000 {
+                let values = map.get_atomic(&[&0, &1]);
+                assert_eq!(values[0].unwrap() + values[1].unwrap(), 1000);
+            }
+        });
+    });
+    assert_eq!(map.get_atomic(&[&0, &1, &2]), [Some(0), Some(1000), None]);
+
+    // A key updated while the group is written fails the whole group, rather than being
+    // overwritten, even once earlier keys are written.
+    assert_eq!(
+        map.update_atomic(&[&0, &1], |values| {
+            assert_eq!(map.update(&1, 7), Some(1000));
+            values[0] = 1;
+            values[1] = 1;
+        }),
+        Err(Error::Conflict)
+    );
+    assert_eq!(map.get_atomic(&[&0, &1]), [Some(0), Some(7)]);
+    assert_eq!(
+        map.update_atomic(&[&0, &1], |values| {
+            assert_eq!(map.fetch_update(&0, |value| Some(value + 1)), Some(0));
+            values[0] = 5;
+        }),
+        Err(Error::Conflict)
+    );
+    assert_eq!(map.get_atomic(&[&0, &1]), [Some(1), Some(7)]);
+
+    // A repeated key takes the last of its values.
+    map.update_atomic(&[&0, &1, &0], |values| {
+        values[0] = 2;
+        values[2] = 3;
+    })
+    .unwrap();
+    assert_eq!(map.get_atomic(&[&0, &1]), [Some(3), Some(7)]);
+
+    // So does a key removed while the group is written, and nothing is updated.
+    assert_eq!(
+        map.update_atomic(&[&1, &0], |values| {
+            assert_eq!(map.remove(&0), Some(3));
+            values[0] = 1;
+            values[1] = 1;
+        }),
+        Err(Error::KeyNotFound)
+    );
+    assert_eq!(map.get_atomic(&[&0, &1]), [None, Some(7)]);
+}
+
+#[test]
+fn key_store_stats() {
+    let mut map = HashMap::<u32, u16>::with_capacity(16);
+    assert!(map.insert(1, 1));
+    // The batch pushes two keys before it finds the duplicate, and rolls them back.
+    assert!(map
+        .insert_all_or_nothing(&[(2, 2), (3, 3), (1, 1)])
+        .is_err());
+    assert_eq!(map.rekey(&1, 5), Ok(1));
+    let stats = map.key_store_stats();
+    assert_eq!(stats.capacity, 16);
+    assert_eq!(stats.high_water, 4);
+    assert_eq!(stats.live, 1);
+    assert_eq!(stats.burned, 3);
+
+    assert_eq!(map.compact_key_store(), 3);
+    let stats = map.key_store_stats();
+    assert_eq!((stats.high_water, stats.live, stats.burned), (1, 1, 0));
+}
+
+#[test]
+fn stable_hashing() {
+    use crate::hashing;
+    // These values are part of the scheme. If any of them change, so must `SCHEME_VERSION`.
+    assert_eq!(hashing::SCHEME_VERSION, 1);
+    assert_eq!(hashing::HASH_BITS, 27);
+    let hash = hashing::hash(&42_u64, &crate::SeededHashBuilder::new(7));
+    #[cfg(target_endian = "little")]
+    assert_eq!(hash, 0x00ca_b128);
+    assert_eq!(hashing::table_slots(1), 8);
+    assert_eq!(hashing::table_slots(100), 256);
+    assert_eq!(hashing::home_index(hash, 256), hash as usize & 0xff);
+}
+
+#[test]
+fn phf_map() {
+    use crate::PhfBuilder;
+    let count = u32::try_from(i16::MAX).unwrap();
+    let map = (0..count)
+        .map(|i| (i * 3, (i % 1000) as u16))
+        .collect::<PhfBuilder<u32, u16>>()
+        .build()
+        .unwrap();
+    assert_eq!(map.len(), usize::try_from(count).unwrap());
+    for i in 0..count {
+        assert_eq!(map.get(&(i * 3)), Some((i % 1000) as u16));
+        assert!(!map.contains_key(&(i * 3 + 1)));
+    }
+    assert!(map
+        .iter()
+        .map(|(&key, _)| key)
+        .eq((0..count).map(|i| i * 3)));
+
+    let empty = PhfBuilder::<String, u16>::new().build().unwrap();
+    assert_eq!(empty.get("a"), None);
+    let duplicates: PhfBuilder<&str, u16> = [("a", 1), ("b", 2), ("a", 3)].into_iter().collect();
+    assert_eq!(duplicates.build().err(), Some(Error::DuplicateKey));
+}
+
+#[test]
+fn rom_image() {
+    use crate::{RomBuilder, RomMap};
+    let builder: RomBuilder<u64, u16> = (0..1000_u16).map(|i| (u64::from(i) << 20, i)).collect();
+    let image = builder.build().unwrap();
+    let map = RomMap::<u64, u16>::new(&image).unwrap();
+    assert_eq!(map.len(), 1000);
+    for i in 0..1000_u16 {
+        assert_eq!(map.get(&(u64::from(i) << 20)), Some(i));
+        assert!(!map.contains_key(&(u64::from(i) + 1)));
+    }
+    assert_eq!(
+        map.iter()
+            .map(|(_, value)| usize::from(value))
+            .sum::<usize>(),
+        999 * 1000 / 2
+    );
+
+    assert_eq!(
+        RomMap::<u32, u16>::new(&image).err(),
+        Some(Error::InvalidSnapshot)
+    );
+    assert_eq!(
+        RomMap::<u64, u16>::new(&image[..image.len() - 1]).err(),
+        Some(Error::InvalidSnapshot)
+    );
+    let empty = RomBuilder::<u64, u16>::new().build().unwrap();
+    assert!(RomMap::<u64, u16>::new(&empty).unwrap().is_empty());
+    let duplicates: RomBuilder<u8, u8> = [(1, 1), (2, 2), (1, 3)].into_iter().collect();
+    assert_eq!(duplicates.build().err(), Some(Error::DuplicateKey));
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn sample_keys() {
+    let map = HashMap::<u32, u16>::with_capacity(1024);
+    let mut rng = StdRng::seed_from_u64(3);
+    assert_eq!(map.sample_keys(10, &mut rng).count(), 0);
+    for i in 0..1000 {
+        assert!(map.insert(i, 0));
+    }
+    let mut counts = [0_u32; 10];
+    for &key in map.sample_keys(10_000, &mut rng) {
+        counts[usize::try_from(key / 100).unwrap()] += 1;
+    }
+    // Every tenth of the keys is drawn about a thousand times.
+    assert!(
+        counts.iter().all(|&count| (800..1200).contains(&count)),
+        "{counts:?}"
+    );
+}
+
+#[test]
+fn content_hash() {
+    use crate::SeededHashBuilder;
+    let a = HashMap::<u32, u16, _>::with_capacity_and_hasher(128, SeededHashBuilder::new(5));
+    let b = HashMap::<u32, u16, _>::with_capacity_and_hasher(100, SeededHashBuilder::new(5));
+    assert_eq!(a.content_hash(), b.content_hash());
+    for i in 0..100 {
+        assert!(a.insert(i, 7));
+        assert!(b.insert(99 - i, 7));
+    }
+    // Different capacities and insertion orders give the same digest.
+    assert_eq!(a.content_hash(), b.content_hash());
+    a.update(&3, 8);
+    assert_ne!(a.content_hash(), b.content_hash());
+    b.update(&3, 8);
+    assert_eq!(a.content_hash(), b.content_hash());
+}
+
+#[test]
+fn remove() {
+    let mut map = HashMap::<u32, u16>::with_capacity(64);
+    for i in 0..64 {
+        assert!(map.insert(i, u16::try_from(i).unwrap()));
+    }
+    for i in (0..64).step_by(2) {
+        assert_eq!(map.remove(&i), Some(u16::try_from(i).unwrap()));
+        assert_eq!(map.remove(&i), None);
+    }
+    assert_eq!(map.len(), 32);
+    assert_eq!(map.iter().count(), 32);
+    assert!(map.iter().all(|(key, _)| key % 2 == 1));
+    assert_eq!(map.get(&2), None);
+    assert_eq!(map.update(&2, 0), None);
+    // The tombstones still take up room, which only the removed keys can take back.
+    assert_eq!(map.try_insert(64, 64), Err(Error::Full));
+    assert_eq!(map.try_insert(2, 2), Ok(()));
+    assert_eq!(map.remove(&2), Some(2));
+
+    assert_eq!(map.purge_tombstones(), 32);
+    assert_eq!(map.purge_tombstones(), 0);
+    assert_eq!(map.key_store_stats().burned, 0);
+    for i in 0..64 {
+        assert_eq!(map.get(&i), (i % 2 == 1).then(|| u16::try_from(i).unwrap()));
+    }
+    for i in (0..64).step_by(2) {
+        assert!(map.insert(i, 0));
+    }
+    assert_eq!(map.len(), 64);
+
+    // Purging never moves a key beyond the probe limit, or out of reach of its probe.
+    let mut rng = StdRng::seed_from_u64(9);
+    let mut map = HashMap::<u32, u16>::with_capacity(512);
+    map.set_probe_limit(24).unwrap();
+    let mut oracle = std::collections::HashMap::new();
+    for round in 0..20 {
+        for _ in 0..400 {
+            let key = rng.gen_range(0..1024);
+            if rng.gen_bool(0.5) {
+                if map.insert(key, round) {
+                    oracle.insert(key, round);
+                }
+            } else {
+                assert_eq!(map.remove(&key), oracle.remove(&key));
+            }
+        }
+        map.purge_tombstones();
+        assert_eq!(map.set_probe_limit(24), Ok(()));
+        assert_eq!(map.len(), oracle.len());
+        for (key, value) in &oracle {
+            assert_eq!(map.get(key), Some(*value));
+        }
+    }
+}
+
+#[test]
+fn remove_and_insert_again() {
+    use crate::MapEntry;
+
+    // A shared map never purges its tombstones, but a key which is removed and inserted again
+    // takes back its own, so the map never runs out of room for the same keys.
+    let map = HashMap::<u32, u16>::with_capacity(16);
+    for key in 0..16 {
+        assert!(map.insert(key, 0));
+    }
+    std::thread::scope(|s| {
+        for thread in 0..4 {
+            let map = &map;
+            s.spawn(move || {
+                for round in 0..1000 {
+                    for key in thread * 4..thread * 4 + 4 {
+                        assert_eq!(map.remove(&key), Some(round));
+                        assert_eq!(map.try_insert(key, round + 1), Ok(()));
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(map.len(), 16);
+    assert_eq!(map._tombstones(), 0);
+    assert_eq!(map.key_store_stats().high_water, 16);
+    assert!(map.iter().all(|(_, value)| value == 1000));
+
+    // Other keys can't use the tombstone.
+    assert_eq!(map.remove(&0), Some(1000));
+    assert_eq!(map.try_insert(16, 0), Err(Error::Full));
+    match map.entry(0) {
+        MapEntry::Occupied(_) => panic!("0 was removed"),
+        MapEntry::Vacant(entry) => entry.insert(1).unwrap(),
+    }
+    assert_eq!((map.len(), map._tombstones()), (16, 0));
+
+    // Batches take tombstones back, and leave them for the keys again if they roll back.
+    assert_eq!(map.remove(&1), Some(1000));
+    assert_eq!(map.remove(&2), Some(1000));
+    assert!(map
+        .insert_all_or_nothing(&[(1, 1), (2, 2), (3, 3)])
+        .is_err());
+    assert_eq!((map.len(), map._tombstones()), (14, 2));
+    assert_eq!(map.insert_all_or_nothing(&[(1, 1), (2, 2)]), Ok(()));
+    assert_eq!((map.len(), map._tombstones()), (16, 0));
+
+    // A key rekeyed away takes back its tombstone too.
+    assert_eq!(map.remove(&2), Some(2));
+    assert_eq!(map.rekey(&3, 2), Ok(1000));
+    assert_eq!((map.len(), map._tombstones()), (15, 1));
+    assert!(map.insert(3, 3));
+    assert_eq!((map.len(), map._tombstones()), (16, 0));
+    assert_eq!(map.key_store_stats().burned, 0);
+    assert_eq!(map.get(&1), Some(1));
+    assert_eq!(map.get(&2), Some(1000));
+}
+
+#[test]
+fn entry_api() {
+    use crate::MapEntry;
+    let map = HashMap::<String, u16>::with_capacity(8);
+    let MapEntry::Vacant(vacant) = map.entry("a".to_string()) else {
+        panic!("key is not in the map");
+    };
+    assert_eq!(vacant.key(), "a");
+    // Another insert lands first, so the vacant entry reports a duplicate.
+    assert!(map.insert("a".to_string(), 1));
+    assert_eq!(vacant.insert(2), Err(Error::DuplicateKey));
+
+    let MapEntry::Occupied(occupied) = map.entry("a".to_string()) else {
+        panic!("key is in the map");
+    };
+    assert_eq!(occupied.key(), "a");
+    assert_eq!(occupied.get(), Some(1));
+    assert_eq!(occupied.update(5), Some(1));
+    assert_eq!(occupied.fetch_update(|value| Some(value * 2)), Some(5));
+    assert_eq!(occupied.fetch_update(|_| None), None);
+    assert_eq!(map.get("a"), Some(10));
+    assert_eq!(map.remove("a"), Some(10));
+    assert_eq!(occupied.get(), None);
+    assert_eq!(occupied.update(1), None);
+    assert_eq!(occupied.remove(), None);
+
+    for key in ["b", "c", "b"] {
+        match map.entry(key.to_string()) {
+            MapEntry::Occupied(entry) => assert_eq!(entry.remove(), Some(0)),
+            MapEntry::Vacant(entry) => entry.insert(0).unwrap(),
+        }
+    }
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get("c"), Some(0));
+}
+
+#[test]
+fn entry_with_concurrent_remove() {
+    use crate::MapEntry;
+    const ROUNDS: u16 = 2000;
+    // Every remove leaves a tombstone, so there must be room for one per round.
+    let map = HashMap::<String, u16>::with_capacity(usize::from(ROUNDS) + 1);
+    let done = core::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for round in 0..ROUNDS {
+                assert!(map.insert("key".to_string(), round));
+                assert_eq!(map.remove("key"), Some(round));
+            }
+            done.store(true, core::sync::atomic::Ordering::Relaxed);
+        });
+        while !done.load(core::sync::atomic::Ordering::Relaxed) {
+            // An occupied entry always refers to the key, however soon it is removed.
+            if let MapEntry::Occupied(entry) = map.entry("key".to_string()) {
+                assert_eq!(entry.key(), "key");
+            }
+        }
+    });
+
+    // Remove the key between finding its entry and reading it, every time.
+    #[cfg(feature = "test-util")]
+    {
+        use crate::inject::{self, Fault, Point};
+        assert!(map.insert("key".to_string(), 1));
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(core::time::Duration::from_millis(20));
+                assert_eq!(map.remove("key"), Some(1));
+            });
+            inject::set(
+                Point::EntryFound,
+                Fault::Delay(core::time::Duration::from_millis(100)),
+            );
+            assert!(matches!(map.entry("key".to_string()), MapEntry::Vacant(_)));
+            inject::clear();
+        });
+    }
+}
+
+#[test]
+fn growable_map() {
+    use crate::GrowableHashMap;
+    const THREADS: u32 = 4;
+    const PER_THREAD: u32 = 2000;
+
+    let map = GrowableHashMap::<u32, u16>::with_capacity(4);
+    std::thread::scope(|s| {
+        for thread in 0..THREADS {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..PER_THREAD {
+                    let key = thread * PER_THREAD + i;
+                    assert!(map.insert(key, 0));
+                    // Every earlier key must stay visible while tables are being replaced.
+                    let earlier = thread * PER_THREAD + i / 2;
+                    assert!(map.contains_key(&earlier) || earlier.is_multiple_of(3));
+                    assert_eq!(map.update(&key, 1), Some(0));
+                    if key.is_multiple_of(3) {
+                        assert_eq!(map.remove(&key), Some(1));
+                    }
+                }
+            });
+        }
+    });
+    let expected = (0..THREADS * PER_THREAD)
+        .filter(|key| !key.is_multiple_of(3))
+        .count();
+    assert_eq!(map.len(), expected);
+    assert!(map.capacity() >= expected);
+    for key in 0..THREADS * PER_THREAD {
+        let expected = (!key.is_multiple_of(3)).then_some(1);
+        assert_eq!(map.get(&key), expected, "key {key}");
+    }
+    assert!(!map.insert(1, 0));
+
+    // Past `i16::MAX` keys, the map can't grow any further.
+    let map = GrowableHashMap::<u16, u16>::with_capacity(0);
+    for key in 0..i16::MAX as u16 {
+        assert!(map.insert(key, key));
+    }
+    assert_eq!(map.try_insert(u16::MAX, 0), Err(Error::Full));
+    assert_eq!(map.remove(&0), Some(0));
+    assert!(map.insert(u16::MAX, 0));
+}
+
+#[test]
+fn sharded_map() {
+    use crate::ShardedHashMap;
+    const KEYS: u32 = 200_000;
+
+    let map = ShardedHashMap::<u32, u16>::with_capacity(KEYS as usize);
+    assert!(map.shard_count() > 1);
+    for key in 0..KEYS {
+        assert!(map.insert(key, 0), "key {key}");
+    }
+    assert!(!map.insert(7, 1));
+    assert_eq!(map.len(), KEYS as usize);
+    assert_eq!(map.iter().count(), KEYS as usize);
+    assert_eq!(map.update(&7, 2), Some(0));
+    assert_eq!(map.fetch_update(&7, |value| Some(value + 1)), Some(2));
+    assert_eq!(map.get(&7), Some(3));
+    assert_eq!(map.remove(&7), Some(3));
+    assert!(!map.contains_key(&7));
+    assert_eq!(map.get(&KEYS), None);
+
+    assert_eq!(
+        ShardedHashMap::<u32, u16>::with_capacity(10).shard_count(),
+        1
+    );
+    assert_eq!(
+        ShardedHashMap::<u32, u16>::try_with_capacity(100_000_000).err(),
+        Some(Error::CapacityTooLarge)
+    );
+}
+
+#[test]
+fn custom_hasher() {
+    use core::hash::Hasher;
+
+    /// A multiplicative hasher in the style of `FxHash`.
+    #[derive(Default)]
+    struct MulHasher(u64);
+    impl Hasher for MulHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 =
+                    (self.0.rotate_left(5) ^ u64::from(byte)).wrapping_mul(0x517c_c1b7_2722_0a95);
+            }
+        }
+    }
+    type BuildMulHasher = core::hash::BuildHasherDefault<MulHasher>;
+
+    let map = HashMap::<u32, u16, BuildMulHasher>::with_hasher(BuildMulHasher::default());
+    assert_eq!(map.capacity(), 64);
+    for key in 0..64 {
+        assert!(map.insert(key, 1));
+    }
+    assert_eq!(map.get(&63), Some(1));
+    assert_eq!(
+        map.hash_key(&63),
+        crate::hashing::hash(&63_u32, map.hasher())
+    );
+}
+
+#[cfg(feature = "wide")]
+#[test]
+fn wide_values() {
+    use crate::WideHashMap;
+    if !portable_atomic::AtomicU128::is_lock_free() {
+        assert_eq!(
+            WideHashMap::<u32, u64>::try_with_capacity(8).err(),
+            Some(Error::NotLockFree)
+        );
+        return;
+    }
+
+    // Room in the key store for every thread to push every key, whether or not it wins.
+    let raced = WideHashMap::<u32, u64>::with_capacity(128);
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for key in 0..32 {
+                    // Every thread races to insert each key, and exactly one wins.
+                    let _ = raced.insert(key, 0);
+                    raced.fetch_update(&key, |value| Some(value + (1 << 40)));
+                }
+            });
+        }
+    });
+    assert_eq!(raced.len(), 32);
+    assert!(raced.iter().all(|(_, value)| value == 4 << 40));
+    assert_eq!(raced.try_insert(0, 1), Err(Error::DuplicateKey));
+
+    // Keys which lost a race can be left in the key store, so fill a map no thread raced on.
+    let map = WideHashMap::<u32, u64>::with_capacity(64);
+    for key in 0..32 {
+        assert!(map.insert(key, 4 << 40));
+    }
+    assert_eq!(map.remove(&0), Some(4 << 40));
+    assert_eq!(map.get(&0), None);
+    assert_eq!(map.update(&0, 1), None);
+    for key in 32..64 {
+        assert!(map.insert(key, key.into()));
+    }
+    // The tombstone left by the removal takes the last slot.
+    assert_eq!(map.try_insert(64, 0), Err(Error::Full));
+    assert_eq!(map.iter().count(), 63);
+
+    let small = WideHashMap::<&str, u32>::with_capacity(4);
+    assert!(small.insert("a", u32::MAX));
+    assert_eq!(small.get("a"), Some(u32::MAX));
+}
+
+#[test]
+fn hash_set() {
+    use crate::HashSet;
+    let set = HashSet::<String>::with_capacity(8);
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for key in ["a", "b", "c"] {
+                    let _ = set.insert(key.to_string());
+                }
+            });
+        }
+    });
+    assert_eq!(set.len(), 3);
+    assert!(set.contains("b"));
+    assert_eq!(set.try_insert("a".to_string()), Err(Error::DuplicateKey));
+    assert_eq!(set.iter().count(), 3);
+
+    let subset = HashSet::<String>::default();
+    assert!(subset.insert("a".to_string()));
+    assert!(subset.is_subset(&set));
+    assert!(!set.is_subset(&subset));
+    assert!(set.remove("b"));
+    assert!(!set.remove("b"));
+    assert!(!set.contains("b"));
+    assert_eq!(set.clone().len(), 2);
+}
+
+#[test]
+fn insert_error() {
+    use crate::InsertError;
+    let map = HashMap::<String, u16>::with_capacity(2);
+    assert_eq!(map.try_insert_returning("a".to_string(), 1), Ok(()));
+    assert_eq!(
+        map.try_insert_returning("a".to_string(), 2),
+        Err(InsertError {
+            error: Error::DuplicateKey,
+            key: "a".to_string(),
+            value: 2
+        })
+    );
+    assert_eq!(map.try_insert_returning("b".to_string(), 3), Ok(()));
+    let full = map.try_insert_returning("c".to_string(), 4).unwrap_err();
+    assert_eq!(
+        (full.error, full.key.as_str(), full.value),
+        (Error::Full, "c", 4)
+    );
+    assert_eq!(full.to_string(), Error::Full.to_string());
+
+    let insert = || -> Result<(), Error> {
+        map.try_insert_returning("d".to_string(), 5)?;
+        Ok(())
+    };
+    assert_eq!(insert(), Err(Error::Full));
+    assert_eq!(map.get("a"), Some(1));
+}
+
+#[test]
+fn insert_or_update() {
+    let map = HashMap::<String, u16>::with_capacity(2);
+    assert_eq!(map.insert_or_update("a".to_string(), 1), Ok(None));
+    assert_eq!(map.insert_or_update("a".to_string(), 2), Ok(Some(1)));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.remove("a"), Some(2));
+    assert_eq!(map.insert_or_update("a".to_string(), 3), Ok(None));
+    // The second "a" takes back the tombstone of the first, leaving the last slot for "b".
+    assert_eq!(map.insert_or_update("b".to_string(), 4), Ok(None));
+    assert_eq!(map.insert_or_update("c".to_string(), 5), Err(Error::Full));
+
+    // Racing upserts of one key insert it once, and every other one replaces a value.
+    let map = HashMap::<u32, u16>::with_capacity(8);
+    let inserted = std::sync::atomic::AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        for thread in 0..4 {
+            let (map, inserted) = (&map, &inserted);
+            s.spawn(move || {
+                for i in 0..100 {
+                    if map.insert_or_update(7, thread * 100 + i).unwrap().is_none() {
+                        inserted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(inserted.into_inner(), 1);
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn get_key_value() {
+    let map = HashMap::<SmallString, u16>::with_capacity(8);
+    assert!(map.insert(SmallString::from("interned"), 1));
+    let (key, value) = map.get_key_value("interned").unwrap();
+    // The key returned is the one stored in the map, not the one looked up with.
+    assert!(core::ptr::eq(key, map.get_key(0).unwrap()));
+    assert_eq!(value, 1);
+    assert!(map.get_key_value("missing").is_none());
+    assert_eq!(map.remove("interned"), Some(1));
+    assert!(map.get_key_value("interned").is_none());
+}
+
+#[test]
+fn clear() {
+    let key = alloc::rc::Rc::new(100_u64);
+    let mut map = HashMap::<_, u16>::with_capacity(8);
+    for i in 0..7 {
+        assert!(map.insert(alloc::rc::Rc::new(u64::from(i)), i));
+    }
+    assert!(map.remove(&0).is_some());
+    let checkpoint = map.checkpoint();
+    assert!(map.insert(key.clone(), 100));
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 2);
+
+    map.clear();
+    assert_eq!(alloc::rc::Rc::strong_count(&key), 1);
+    assert!(map.is_empty());
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.iter().count(), 0);
+    assert_eq!(map.restore(&checkpoint), Err(Error::InvalidSnapshot));
+
+    // The whole capacity is available again, tombstones included.
+    for i in 0..8 {
+        assert!(map.insert(alloc::rc::Rc::new(u64::from(i)), i + 1));
+    }
+    assert_eq!(map.get(&7), Some(8));
+    assert_eq!(map.len(), 8);
+}
+
+#[test]
+fn clone_is_deep() {
+    let map = HashMap::<String, u16>::with_capacity(64);
+    for i in 0..32 {
+        assert!(map.insert(format!("key{i}"), i));
+    }
+    assert_eq!(map.set_flags("key3", 0b1010), Some(0));
+    assert_eq!(map.remove("key4"), Some(4));
+
+    let clone = map.clone();
+    assert_eq!(clone.len(), 31);
+    assert_eq!(clone.get_with_flags("key3"), Some((3, 0b1010)));
+    assert!(clone
+        .iter()
+        .map(|(_, value)| value)
+        .eq((0..32).filter(|&i| i != 4)));
+    // The removed key left no tombstone behind.
+    assert_eq!(clone._tombstones(), 0);
+
+    map.update("key0", 100);
+    assert!(map.insert("new".to_string(), 1));
+    assert_eq!(clone.get("key0"), Some(0));
+    assert!(!clone.contains_key("new"));
+
+    // Clone while another thread writes. Its removals leave tombstones, so it stops well short of
+    // the capacity.
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for round in 0..30 {
+                map.update("key1", round);
+                assert!(map.insert(format!("round{round}"), round));
+                assert_eq!(map.remove(&format!("round{round}")), Some(round));
+            }
+        });
+        for _ in 0..10 {
+            let clone = map.clone();
+            assert!(clone.get("key1").is_some_and(|value| value < 30));
+            assert!(clone.len() >= 31);
+        }
+    });
+}
@@ -0,0 +1,92 @@
+use crate::{util, HashMap};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The tick at which each key was last inserted or updated, indexed like the key store.
+/// Kept beside the table rather than in it, so entries stay one word wide.
+pub struct Timestamps {
+    now: AtomicU32,
+    ticks: *mut AtomicU32,
+    len: usize,
+}
+
+impl Timestamps {
+    /// Returns None if the allocation fails.
+    pub fn try_new(len: usize) -> Option<Self> {
+        let ticks = util::allocate_zeroed::<AtomicU32>(len);
+        if ticks.is_null() {
+            return None;
+        }
+        Some(Self {
+            now: AtomicU32::new(0),
+            ticks,
+            len,
+        })
+    }
+
+    /// Record that the key at `key_index` was modified at the current tick.
+    pub fn touch(&self, key_index: usize) {
+        let now = self.now.load(Ordering::Relaxed);
+        self.tick(key_index).store(now, Ordering::Relaxed);
+    }
+
+    /// Move the tick recorded for the key at `from` to `to`, when the key store is compacted.
+    pub fn relocate(&mut self, from: usize, to: usize) {
+        let tick = self.tick(from).load(Ordering::Relaxed);
+        self.tick(to).store(tick, Ordering::Relaxed);
+    }
+
+    fn tick(&self, key_index: usize) -> &AtomicU32 {
+        assert!(key_index < self.len);
+        unsafe { &*self.ticks.add(key_index) }
+    }
+}
+
+impl Drop for Timestamps {
+    fn drop(&mut self) {
+        util::deallocate(self.ticks, self.len);
+    }
+}
+
+unsafe impl Send for Timestamps {}
+unsafe impl Sync for Timestamps {}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Set the current tick, which is recorded against each key when it is inserted or updated.
+    /// Ticks are supplied by the caller, and can count anything (seconds, frames, epochs).
+    /// The tick is zero when the map is created.
+    pub fn set_tick(&self, tick: u32) {
+        self.timestamps.now.store(tick, Ordering::Relaxed);
+    }
+
+    /// Get the current tick, set by [`set_tick`](Self::set_tick).
+    pub fn tick(&self) -> u32 {
+        self.timestamps.now.load(Ordering::Relaxed)
+    }
+
+    /// Get the tick at which a key was last inserted or updated. Returns None if the key doesn't
+    /// exist.
+    ///
+    /// The tick is recorded just after the modification, so a concurrent reader may briefly see
+    /// the new value with the previous tick.
+    pub fn last_modified<Q>(&self, key: &Q) -> Option<u32>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self._find_entry(key)?.load(atomic::Ordering::Acquire);
+        // The key may have been removed since it was found, leaving no key index.
+        if !entry.is_live() {
+            return None;
+        }
+        let tick = self.timestamps.tick(crate::key_index(entry.key_offset));
+        Some(tick.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{BatchError, HashMap, HashT};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// A set of inserts and updates which are buffered, and only applied to the map when the
+/// transaction is committed. Created by [`HashMap::transaction`].
+///
+/// Dropping a transaction without committing it discards its changes, without touching the map.
+pub struct Transaction<'map, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    map: &'map HashMap<K, V, S>,
+    inserts: Vec<(HashT, K, V)>,
+    /// Updates of keys which were already in the map, by their index in the key store.
+    updates: BTreeMap<usize, V>,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Start a transaction, which buffers inserts and updates until it is committed.
+    pub fn transaction(&self) -> Transaction<'_, K, V, S> {
+        Transaction {
+            map: self,
+            inserts: Vec::new(),
+            updates: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K, V, S> Transaction<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Buffer an insert. Returns false if the key is already in the map or the transaction.
+    /// Whether the map has room for the key is only checked on commit.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        let hash = self.map._hash(&key);
+        if self.map.contains_key(&key) || self._buffered(&key, hash).is_some() {
+            return false;
+        }
+        self.inserts.push((hash, key, value));
+        true
+    }
+
+    /// Buffer an update. Returns the previous value of the key, as seen by the transaction, or
+    /// None if the key is in neither the map nor the transaction.
+    pub fn update<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(position) = self._buffered(key, self.map._hash(key)) {
+            let (.., buffered) = &mut self.inserts[position];
+            return Some(core::mem::replace(buffered, value));
+        }
+        let entry = self.map._find_entry(key)?.load(atomic::Ordering::Relaxed);
+        let previous = self
+            .updates
+            .insert(crate::key_index(entry.key_offset), value);
+        Some(previous.unwrap_or(entry.value))
+    }
+
+    /// Get the value associated with a key, as seen by the transaction.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(position) = self._buffered(key, self.map._hash(key)) {
+            return Some(self.inserts[position].2);
+        }
+        let entry = self.map._find_entry(key)?.load(atomic::Ordering::Relaxed);
+        let updated = self.updates.get(&crate::key_index(entry.key_offset));
+        Some(updated.copied().unwrap_or(entry.value))
+    }
+
+    /// Returns true if the key is in the map or the transaction.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self._buffered(key, self.map._hash(key)).is_some() || self.map.contains_key(key)
+    }
+
+    /// Apply the transaction to the map. The buffered inserts are applied together with
+    /// [`HashMap::insert_all_or_nothing`], and if they succeed, the buffered updates are applied.
+    /// Each change is applied with the map's normal atomic operations, so other threads may see
+    /// them one at a time, and an update overwrites any concurrent change to the same key.
+    /// # Errors
+    /// If the inserts fail, no change is applied, and the [`BatchError`] gives the position of
+    /// the insert which failed, in the order they were made.
+    pub fn commit(self) -> Result<(), BatchError>
+    where
+        K: Clone,
+    {
+        let inserts: Vec<_> = self
+            .inserts
+            .into_iter()
+            .map(|(_, key, value)| (key, value))
+            .collect();
+        self.map.insert_all_or_nothing(&inserts)?;
+        for (key_index, value) in self.updates {
+            if let Some(key) = self.map.get_key(key_index) {
+                self.map.update(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Discard the transaction, leaving the map unchanged. This is the same as dropping it.
+    pub fn discard(self) {}
+
+    /// Find the position of a buffered insert of `key`.
+    fn _buffered<Q>(&self, key: &Q, hash: HashT) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inserts
+            .iter()
+            .position(|(buffered_hash, buffered, _)| {
+                *buffered_hash == hash && buffered.borrow() == key
+            })
+    }
+}
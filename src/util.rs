@@ -1,6 +1,3 @@
-use core::hash::Hash;
-use hash32::Hasher;
-
 /// A macro to assert checks on generic type parameters at compile time.
 /// Credits: <https://morestina.net/blog/1940/compile-time-checks-in-generic-functions-work-and-you-can-use-them-in-your-code>
 #[macro_export]
@@ -32,26 +29,12 @@ macro_rules! generic_asserts {
     }
 }
 
-#[macro_export]
-macro_rules! wrap {
-    (<$to_ty:ty>: $in:expr,$cap:expr) => {
-        ($in as usize % $cap as usize) as $to_ty
-    };
-}
-
-pub fn hash<T: ?Sized, H>(key: &T) -> u32
-where
-    T: Hash,
-    H: Hasher + Default,
-{
-    let mut hasher = H::default();
-    key.hash(&mut hasher);
-    hasher.finish32()
-}
-
-pub fn allocate<T>(count: usize) -> *mut T {
-    let layout = core::alloc::Layout::array::<T>(count).unwrap();
-    unsafe { alloc::alloc::alloc(layout).cast::<T>() }
+/// Wrap `index` into a table of `mask + 1` entries.
+/// Table sizes are always powers of two, so this is a single AND rather than a modulo.
+#[inline]
+pub const fn wrap(index: usize, mask: usize) -> usize {
+    debug_assert!(mask.wrapping_add(1).is_power_of_two());
+    index & mask
 }
 
 pub fn allocate_zeroed<T>(count: usize) -> *mut T {
@@ -59,7 +42,56 @@ pub fn allocate_zeroed<T>(count: usize) -> *mut T {
     unsafe { alloc::alloc::alloc_zeroed(layout).cast::<T>() }
 }
 
+/// The layout of an array of `count` `T`s, aligned to at least `align` and padded to a multiple of
+/// it. Returns None if `align` isn't a power of two, or the array is too large.
+pub fn aligned_array_layout<T>(count: usize, align: usize) -> Option<core::alloc::Layout> {
+    let layout = core::alloc::Layout::array::<T>(count).ok()?;
+    Some(layout.align_to(align).ok()?.pad_to_align())
+}
+
 pub fn deallocate<T>(ptr: *mut T, count: usize) {
     let layout = core::alloc::Layout::array::<T>(count).unwrap();
     unsafe { alloc::alloc::dealloc(ptr.cast::<u8>(), layout) }
 }
+
+/// A minimal spin lock, for the few secondary structures which can't be updated lock-free.
+/// The map itself never takes it.
+pub struct SpinLock<T> {
+    locked: core::sync::atomic::AtomicBool,
+    value: core::cell::UnsafeCell<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: core::sync::atomic::AtomicBool::new(false),
+            value: core::cell::UnsafeCell::new(value),
+        }
+    }
+
+    /// Run `f` with exclusive access to the value.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        use core::sync::atomic::Ordering;
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let _unlock = Unlock(&self.locked);
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+/// Releases a [`SpinLock`] when dropped, including when the closure passed to `with` panics.
+struct Unlock<'a>(&'a core::sync::atomic::AtomicBool);
+
+impl Drop for Unlock<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, core::sync::atomic::Ordering::Release);
+    }
+}
+
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
@@ -0,0 +1,75 @@
+//! Kani proof harnesses for the invariants the probing code relies on.
+//! Run with `cargo kani`.
+
+use crate::raw::{next_index, BUCKET_CAPACITY};
+use crate::{allocated_size, constants, key_index, key_offset, key_store_capacity, Entry, Size};
+
+fn any_capacity() -> usize {
+    let capacity: usize = kani::any();
+    kani::assume(i16::try_from(capacity).is_ok());
+    capacity
+}
+
+/// `RawTable::probe_within` visits at most `size_mask` consecutive entries, one fewer than the
+/// table holds, and only returns `RawProbe::Exhausted` without a probe limit if none of them is
+/// empty. Entries and tombstones together never exceed the capacity, so that can't happen as long
+/// as the table always has at least two more entries than the capacity.
+#[kani::proof]
+fn probes_terminate() {
+    let capacity = any_capacity();
+    let allocated = allocated_size(capacity);
+
+    assert!(allocated.is_power_of_two());
+    assert!(allocated >= BUCKET_CAPACITY);
+    assert!(allocated - 1 <= usize::from(Size::MAX));
+    assert!(allocated - capacity >= 2);
+}
+
+/// Probing from any index stays inside the table, and visits every entry before wrapping back
+/// to where it started.
+#[kani::proof]
+#[kani::unwind(9)]
+fn probes_visit_every_entry() {
+    #[allow(clippy::cast_possible_truncation)]
+    let size_mask = (BUCKET_CAPACITY - 1) as Size;
+    let start: Size = kani::any();
+    kani::assume(start <= size_mask);
+
+    let mut index = start;
+    for _ in 0..size_mask {
+        index = next_index(index, size_mask);
+        assert!(index <= size_mask);
+        assert!(index != start);
+    }
+    assert_eq!(next_index(index, size_mask), start);
+}
+
+/// Every index in the key store maps to a `key_offset` of at least `MIN_KEY`, so subtracting
+/// `MIN_KEY` never underflows and recovers the original index.
+#[kani::proof]
+fn key_offsets_round_trip() {
+    let capacity = any_capacity();
+    let index: usize = kani::any();
+    kani::assume(index < key_store_capacity(capacity));
+
+    let offset = key_offset(index);
+    assert!(offset.0 >= constants::MIN_KEY);
+    assert_eq!(key_index(offset), index);
+}
+
+/// A live entry can never be mistaken for `Entry::EMPTY`, whatever its hash and value.
+#[kani::proof]
+fn live_entries_are_not_empty() {
+    let capacity = any_capacity();
+    let index: usize = kani::any();
+    kani::assume(index < key_store_capacity(capacity));
+
+    let entry = Entry::<u16> {
+        key_hash: kani::any(),
+        key_offset: key_offset(index),
+        value: kani::any(),
+    };
+    assert!(entry.key_offset != constants::EMPTY_KEY);
+    assert!(Entry::<u16>::EMPTY.key_offset == constants::EMPTY_KEY);
+    assert!(bytemuck::bytes_of(&entry) != bytemuck::bytes_of(&Entry::<u16>::EMPTY));
+}
@@ -0,0 +1,91 @@
+use crate::{Entry, HashMap};
+use atomic::{Atomic, Ordering};
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::task::Poll;
+
+/// Watches one key for changes to its value or flags. Created by [`HashMap::watch`].
+///
+/// The watcher holds the key's entry, so checking for a change is a single load, without hashing
+/// or probing. Entries hold no version, so a change which is undone before the watcher looks, such
+/// as an update back to the same value, isn't seen.
+pub struct Watcher<'map, V> {
+    entry: &'map Atomic<Entry<V>>,
+    seen: Entry<V>,
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Watch a key for changes. Returns None if the key doesn't exist.
+    /// ```
+    /// let map = folklore::HashMap::<u32, u16>::with_capacity(8);
+    /// map.insert(1, 1);
+    /// let mut watcher = map.watch(&1).unwrap();
+    /// assert!(watcher.poll_changed().is_pending());
+    /// map.update(&1, 2);
+    /// assert_eq!(watcher.poll_changed(), core::task::Poll::Ready(Some(2)));
+    /// ```
+    pub fn watch<Q>(&self, key: &Q) -> Option<Watcher<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self._find_entry(key)?;
+        Some(Watcher {
+            entry,
+            seen: entry.load(Ordering::Acquire),
+        })
+    }
+}
+
+impl<V: Copy + NoUninit> Watcher<'_, V> {
+    /// The value as of the last change the watcher saw.
+    #[must_use]
+    pub fn value(&self) -> V {
+        self.seen.value
+    }
+
+    /// Check whether the key's value or flags have changed since the watcher last looked. Returns
+    /// the new value, or `Ready(None)` once the key has been [removed](HashMap::remove) or moved
+    /// out of the entry by [`rekey`](HashMap::rekey). Every later poll returns `Ready(None)` too,
+    /// even if the key is inserted again and takes back its entry.
+    pub fn poll_changed(&mut self) -> Poll<Option<V>> {
+        if !self.seen.is_live() {
+            return Poll::Ready(None);
+        }
+        let current = self.entry.load(Ordering::Acquire);
+        if bytemuck::bytes_of(&current) == bytemuck::bytes_of(&self.seen) {
+            return Poll::Pending;
+        }
+        self.seen = current;
+        Poll::Ready(current.is_live().then_some(current.value))
+    }
+
+    /// Block until the key's value or flags change, and return the new value, or None as soon as
+    /// the key has been removed. See [`poll_changed`](Self::poll_changed).
+    ///
+    /// Updates don't signal watchers, so this spins briefly and then sleeps between checks, for
+    /// up to a millisecond at a time.
+    #[cfg(feature = "std")]
+    pub fn changed(&mut self) -> Option<V> {
+        let mut backoff = 0u32;
+        loop {
+            if let Poll::Ready(value) = self.poll_changed() {
+                return value;
+            }
+            match backoff {
+                0..=63 => core::hint::spin_loop(),
+                64..=127 => std::thread::yield_now(),
+                _ => std::thread::sleep(std::time::Duration::from_micros(
+                    u64::from(backoff - 127).min(1000),
+                )),
+            }
+            backoff = backoff.saturating_add(1);
+        }
+    }
+}
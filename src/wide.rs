@@ -0,0 +1,388 @@
+use crate::array::ConcurrentArray;
+use crate::{generic_asserts, hashing, key_store_capacity, DefaultHashBuilder, Error, HashedKey};
+use alloc::boxed::Box;
+use bytemuck::NoUninit;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use portable_atomic::AtomicU128;
+
+/// The key offset of an entry which has never held a key.
+const EMPTY_OFFSET: u32 = 0;
+/// The key offset of an entry whose key was removed.
+const TOMBSTONE: u32 = u32::MAX;
+
+/// A map like [`HashMap`](crate::HashMap), for values of 4 or 8 bytes, such as `u32`, `u64` or
+/// small `#[repr(C)]` structs. Each entry is two words, updated with a single 128-bit CAS:
+/// `cmpxchg16b` on `x86_64`, or a paired load and store exclusive on `aarch64`.
+///
+/// Maps are only created where the CAS is lock-free. Other targets, and `x86_64` CPUs without
+/// `cmpxchg16b`, get [`Error::NotLockFree`] rather than a map which quietly takes locks.
+///
+/// ```
+/// # if !portable_atomic::AtomicU128::is_lock_free() { return; }
+/// let map = folklore::WideHashMap::<&str, u64>::with_capacity(8);
+/// assert!(map.insert("big", u64::MAX));
+/// assert_eq!(map.update("big", 1 << 40), Some(u64::MAX));
+/// assert_eq!(map.get("big"), Some(1 << 40));
+/// ```
+pub struct WideHashMap<K, V, S = DefaultHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    table: Box<[AtomicU128]>,
+    key_store: ConcurrentArray<HashedKey<K>>,
+    capacity: usize,
+    count: AtomicUsize,
+    /// Removed entries, which still occupy the table, so count towards the capacity.
+    tombstones: AtomicUsize,
+    hash_builder: S,
+    values: PhantomData<V>,
+}
+
+/// An entry, unpacked from its 128 bits.
+#[derive(Clone, Copy)]
+struct WideEntry {
+    key_hash: u32,
+    /// The key's index in the key store plus one, [`EMPTY_OFFSET`] or [`TOMBSTONE`].
+    key_offset: u32,
+    /// The value's bytes, in native byte order, padded with zeroes if it is 4 bytes.
+    value: u64,
+}
+
+impl WideEntry {
+    fn pack(self) -> u128 {
+        u128::from(self.key_hash) | u128::from(self.key_offset) << 32 | u128::from(self.value) << 64
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn unpack(bits: u128) -> Self {
+        Self {
+            key_hash: bits as u32,
+            key_offset: (bits >> 32) as u32,
+            value: (bits >> 64) as u64,
+        }
+    }
+
+    fn is_live(self) -> bool {
+        self.key_offset != EMPTY_OFFSET && self.key_offset != TOMBSTONE
+    }
+}
+
+/// The result of probing the table for a key.
+enum WideProbe<'map> {
+    /// The key is in this entry, which held `WideEntry` when it was probed.
+    Found(&'map AtomicU128, WideEntry),
+    /// The key isn't in the table. The entry at this index was empty.
+    Vacant(usize),
+    /// Every entry is occupied.
+    Exhausted,
+}
+
+impl<K, V> WideHashMap<K, V, DefaultHashBuilder>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+{
+    /// # Panics
+    /// If `capacity > i16::MAX`, the 128-bit CAS isn't lock-free, or the allocation fails. See
+    /// [`try_with_capacity`](Self::try_with_capacity).
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        match Self::try_with_capacity(capacity) {
+            Ok(map) => map,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    /// Create a map with the given capacity, returning an error instead of panicking.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, [`Error::NotLockFree`], or
+    /// [`Error::AllocationFailed`].
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        Self::try_with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> WideHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Copy + NoUninit,
+    S: BuildHasher,
+{
+    /// Create a map with the given capacity, which uses `hash_builder` to hash keys.
+    /// # Errors
+    /// [`Error::CapacityTooLarge`] if `capacity > i16::MAX`, [`Error::NotLockFree`] if this CPU
+    /// has no lock-free 128-bit CAS, or [`Error::AllocationFailed`].
+    pub fn try_with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Result<Self, Error> {
+        generic_asserts!((V);
+            VALUE_SIZE: size_of::<V>() == 4 || size_of::<V>() == 8;
+        );
+        if i16::try_from(capacity).is_err() {
+            return Err(Error::CapacityTooLarge);
+        }
+        if !AtomicU128::is_lock_free() {
+            return Err(Error::NotLockFree);
+        }
+        let key_store = ConcurrentArray::try_new(key_store_capacity(capacity))
+            .ok_or(Error::AllocationFailed)?;
+        let table = (0..hashing::table_slots(capacity))
+            .map(|_| AtomicU128::new(0))
+            .collect();
+        Ok(Self {
+            table,
+            key_store,
+            capacity,
+            count: AtomicUsize::new(0),
+            tombstones: AtomicUsize::new(0),
+            hash_builder,
+            values: PhantomData,
+        })
+    }
+
+    /// Insert a key-value pair into the map.
+    /// Returns true if the key was inserted, false if the map is full or the key already exists.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Insert a key-value pair into the map, returning the reason on failure.
+    /// # Errors
+    /// - [`Error::DuplicateKey`] if the key already exists.
+    /// - [`Error::Full`] if the map already holds `capacity` entries, counting tombstones.
+    /// - [`Error::KeyStoreFull`] if there is no room left to store the key.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), Error> {
+        let key_hash = hashing::hash(&key, &self.hash_builder);
+        let mut index = match self._probe(&key, key_hash, self._home(key_hash)) {
+            WideProbe::Found(..) => return Err(Error::DuplicateKey),
+            WideProbe::Vacant(index) => index,
+            WideProbe::Exhausted => return Err(Error::Full),
+        };
+        self.count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                let used = count + self.tombstones.load(Ordering::Relaxed);
+                (used < self.capacity).then_some(count + 1)
+            })
+            .map_err(|_| Error::Full)?;
+        let Some((stored, key_index)) = self.key_store.push(HashedKey { key_hash, key }) else {
+            self.count.fetch_sub(1, Ordering::Relaxed);
+            return Err(Error::KeyStoreFull);
+        };
+        // The key store holds fewer than `u32::MAX` keys.
+        #[allow(clippy::cast_possible_truncation)]
+        let new_entry = WideEntry {
+            key_hash,
+            key_offset: key_index as u32 + 1,
+            value: encode(value),
+        }
+        .pack();
+
+        // If another thread fills the empty entry first, carry on probing from that entry, in
+        // case the other thread was inserting the same key.
+        while self.table[index]
+            .compare_exchange(0, new_entry, Ordering::Release, Ordering::Acquire)
+            .is_err()
+        {
+            match self._probe(&stored.key, key_hash, index) {
+                WideProbe::Vacant(next_index) => index = next_index,
+                failed => {
+                    self.count.fetch_sub(1, Ordering::Relaxed);
+                    // May fail if another key was pushed since, leaving this one unpublished.
+                    self.key_store.remove(key_index);
+                    return Err(match failed {
+                        WideProbe::Found(..) => Error::DuplicateKey,
+                        _ => Error::Full,
+                    });
+                }
+            }
+        }
+        self.key_store.publish(key_index);
+        Ok(())
+    }
+
+    /// Get the value associated with a key. Returns None if the key doesn't exist.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self._find(key) {
+            WideProbe::Found(_, entry) => Some(decode(entry.value)),
+            _ => None,
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        matches!(self._find(key), WideProbe::Found(..))
+    }
+
+    /// Update the value associated with a key. Returns the previous value, or None if the key
+    /// doesn't exist.
+    pub fn update<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.fetch_update(key, |_| Some(value))
+    }
+
+    /// Update the value associated with a key with `f`, retrying if another thread changes it
+    /// first. Returns the previous value, or None if the key doesn't exist or `f` returned None.
+    pub fn fetch_update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnMut(V) -> Option<V>,
+    {
+        let WideProbe::Found(entry, _) = self._find(key) else {
+            return None;
+        };
+        entry
+            .fetch_update(Ordering::Release, Ordering::Acquire, |bits| {
+                let current = WideEntry::unpack(bits);
+                if !current.is_live() {
+                    return None;
+                }
+                let value = f(decode(current.value))?;
+                Some(
+                    WideEntry {
+                        value: encode(value),
+                        ..current
+                    }
+                    .pack(),
+                )
+            })
+            .ok()
+            .map(|previous| decode(WideEntry::unpack(previous).value))
+    }
+
+    /// Remove a key from the map, returning its value, or None if it doesn't exist. The removal
+    /// leaves a tombstone, which still counts towards the capacity.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let WideProbe::Found(entry, _) = self._find(key) else {
+            return None;
+        };
+        let previous = entry
+            .fetch_update(Ordering::Release, Ordering::Acquire, |bits| {
+                let current = WideEntry::unpack(bits);
+                current.is_live().then(|| {
+                    WideEntry {
+                        key_offset: TOMBSTONE,
+                        ..current
+                    }
+                    .pack()
+                })
+            })
+            .ok()?;
+        self.tombstones.fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_sub(1, Ordering::Relaxed);
+        Some(decode(WideEntry::unpack(previous).value))
+    }
+
+    /// Iterate over the key-value pairs, in insertion order, with the same snapshot semantics as
+    /// [`HashMap::iter`](crate::HashMap::iter).
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_ {
+        (0..self.key_store.len()).filter_map(|key_index| {
+            let stored = self.key_store.get(key_index)?;
+            match self._probe(&stored.key, stored.key_hash, self._home(stored.key_hash)) {
+                // A key which was removed and inserted again is stored twice, but only the copy
+                // its entry refers to is in the map.
+                WideProbe::Found(_, entry) if entry.key_offset as usize == key_index + 1 => {
+                    Some((&stored.key, decode(entry.value)))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Iterate over the keys, in the same order as [`iter`](Self::iter).
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Iterate over the values, in the same order as [`iter`](Self::iter).
+    pub fn values(&self) -> impl Iterator<Item = V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Get the count of key-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of key-value pairs the map can hold.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn _find<Q>(&self, key: &Q) -> WideProbe<'_>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let key_hash = hashing::hash(key, &self.hash_builder);
+        self._probe(key, key_hash, self._home(key_hash))
+    }
+
+    /// Probe the table for `key` from `index`, until the key or an empty entry is found.
+    fn _probe<Q>(&self, key: &Q, key_hash: u32, index: usize) -> WideProbe<'_>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mask = self.table.len() - 1;
+        for distance in 0..self.table.len() {
+            let index = (index + distance) & mask;
+            let entry = WideEntry::unpack(self.table[index].load(Ordering::Acquire));
+            if entry.key_offset == EMPTY_OFFSET {
+                return WideProbe::Vacant(index);
+            }
+            if entry.is_live() && entry.key_hash == key_hash {
+                // SAFETY: The key was pushed before the entry was inserted with `Release`, and
+                // the entry was loaded with `Acquire`. Keys in the table are never removed from
+                // the key store.
+                let stored = unsafe { self.key_store.get_unchecked(entry.key_offset as usize - 1) };
+                if stored.key.borrow() == key {
+                    return WideProbe::Found(&self.table[index], entry);
+                }
+            }
+        }
+        WideProbe::Exhausted
+    }
+
+    fn _home(&self, key_hash: u32) -> usize {
+        hashing::home_index(key_hash, self.table.len())
+    }
+}
+
+/// The bytes of `value`, in native byte order, padded with zeroes to 8 bytes.
+fn encode<V: NoUninit>(value: V) -> u64 {
+    let mut bytes = [0; 8];
+    let value = bytemuck::bytes_of(&value);
+    bytes[..value.len()].copy_from_slice(value);
+    u64::from_ne_bytes(bytes)
+}
+
+/// The value whose bytes were packed by [`encode`].
+fn decode<V: Copy>(bits: u64) -> V {
+    let bytes = bits.to_ne_bytes();
+    // SAFETY: Every value in the table was written by `encode`, from a valid `V` of at most 8
+    // bytes.
+    unsafe { core::ptr::read_unaligned(bytes.as_ptr().cast::<V>()) }
+}
@@ -0,0 +1,193 @@
+//! Differential test of the map against a locked `std` map.
+//!
+//! Threads run random gets, inserts, updates and removes on a few shared keys, logging when each
+//! call started and returned. The log is then checked for a linearization: an order of the
+//! operations which respects real time, and which gives every recorded result when replayed
+//! against a `Mutex<std::collections::HashMap>`. Linearizability is local, so each key's history is searched
+//! on its own, and the orders found are replayed together.
+
+use folklore::{Error, HashMap};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap as StdHashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Barrier, Mutex};
+use std::thread;
+
+const ROUNDS: u64 = 1000;
+const THREADS: usize = 4;
+const OPS_PER_THREAD: usize = 48;
+/// Few keys, so that most operations on a key overlap with another thread's.
+const KEYS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Get,
+    Insert(u16),
+    Update(u16),
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Get(Option<u16>),
+    Insert(Result<(), Error>),
+    Update(Option<u16>),
+    Remove(Option<u16>),
+}
+
+/// An operation which completed, and the clock ticks it started and returned at.
+#[derive(Debug, Clone, Copy)]
+struct Call {
+    key: u32,
+    op: Op,
+    outcome: Outcome,
+    invoked: u64,
+    returned: u64,
+}
+
+/// The sequential behaviour the map must match.
+fn apply(oracle: &mut StdHashMap<u32, u16>, key: u32, op: Op) -> Outcome {
+    match op {
+        Op::Get => Outcome::Get(oracle.get(&key).copied()),
+        Op::Insert(value) => Outcome::Insert(match oracle.entry(key) {
+            Entry::Occupied(_) => Err(Error::DuplicateKey),
+            Entry::Vacant(vacant) => {
+                vacant.insert(value);
+                Ok(())
+            }
+        }),
+        Op::Update(value) => Outcome::Update(oracle.get_mut(&key).map(|v| {
+            let previous = *v;
+            *v = value;
+            previous
+        })),
+        Op::Remove => Outcome::Remove(oracle.remove(&key)),
+    }
+}
+
+fn run_round(seed: u64) -> Vec<Call> {
+    // Every remove leaves a tombstone which takes up room, so leave room for every operation to
+    // be an insert, and none of them fail with `Full`.
+    let map = HashMap::<u32, u16>::with_capacity(THREADS * OPS_PER_THREAD);
+    let clock = AtomicU64::new(0);
+    let barrier = Barrier::new(THREADS);
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let (map, clock, barrier) = (&map, &clock, &barrier);
+                s.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed * THREADS as u64 + thread as u64);
+                    barrier.wait();
+                    (0..OPS_PER_THREAD)
+                        .map(|i| {
+                            // Every write is of a value no other write uses, so a read can only
+                            // be explained by one write.
+                            let value = u16::try_from(thread * OPS_PER_THREAD + i).unwrap();
+                            let key = rng.gen_range(0..KEYS);
+                            let op = match rng.gen_range(0..4) {
+                                0 => Op::Get,
+                                1 => Op::Insert(value),
+                                2 => Op::Update(value),
+                                _ => Op::Remove,
+                            };
+                            let invoked = clock.fetch_add(1, Ordering::SeqCst);
+                            let outcome = match op {
+                                Op::Get => Outcome::Get(map.get(&key)),
+                                Op::Insert(value) => Outcome::Insert(map.try_insert(key, value)),
+                                Op::Update(value) => Outcome::Update(map.update(&key, value)),
+                                Op::Remove => Outcome::Remove(map.remove(&key)),
+                            };
+                            let returned = clock.fetch_add(1, Ordering::SeqCst);
+                            Call {
+                                key,
+                                op,
+                                outcome,
+                                invoked,
+                                returned,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+/// Search for a linearization of one key's `calls`, returning their indexes in order.
+fn linearize(calls: &[Call]) -> Option<Vec<usize>> {
+    assert!(calls.len() <= 128, "too many calls on one key to search");
+    let mut order = Vec::with_capacity(calls.len());
+    let mut dead_ends = HashSet::new();
+    search(calls, 0, None, &mut order, &mut dead_ends).then_some(order)
+}
+
+/// Wing and Gong's search: try each call which could take effect next, and backtrack when its
+/// recorded outcome doesn't match. `done` is a bitmask of the calls already placed, and `state`
+/// is the key's value after them.
+fn search(
+    calls: &[Call],
+    done: u128,
+    state: Option<u16>,
+    order: &mut Vec<usize>,
+    dead_ends: &mut HashSet<(u128, Option<u16>)>,
+) -> bool {
+    if order.len() == calls.len() {
+        return true;
+    }
+    if dead_ends.contains(&(done, state)) {
+        return false;
+    }
+    let pending = || (0..calls.len()).filter(|&i| done & (1 << i) == 0);
+    // A call can go next if no other pending call returned before it was invoked.
+    let first_return = pending().map(|i| calls[i].returned).min().unwrap();
+    for i in pending().filter(|&i| calls[i].invoked < first_return) {
+        let call = calls[i];
+        let mut oracle = StdHashMap::new();
+        if let Some(value) = state {
+            oracle.insert(call.key, value);
+        }
+        if apply(&mut oracle, call.key, call.op) != call.outcome {
+            continue;
+        }
+        order.push(i);
+        let next = oracle.get(&call.key).copied();
+        if search(calls, done | (1 << i), next, order, dead_ends) {
+            return true;
+        }
+        order.pop();
+    }
+    dead_ends.insert((done, state));
+    false
+}
+
+#[test]
+fn linearizable_against_locked_std_map() {
+    for seed in 0..ROUNDS {
+        let calls = run_round(seed);
+        let mut by_key: StdHashMap<u32, Vec<Call>> = StdHashMap::new();
+        for call in calls {
+            by_key.entry(call.key).or_default().push(call);
+        }
+
+        let mut log = Vec::new();
+        for (key, history) in &by_key {
+            let Some(order) = linearize(history) else {
+                panic!("round {seed}: no linearization of key {key}'s history: {history:#?}");
+            };
+            log.extend(order.into_iter().map(|i| history[i]));
+        }
+
+        // Replay the serialized log against the locked map, which must give every recorded result.
+        let oracle = Mutex::new(StdHashMap::new());
+        for call in &log {
+            let outcome = apply(&mut oracle.lock().unwrap(), call.key, call.op);
+            assert_eq!(outcome, call.outcome, "round {seed}: replaying {call:?}");
+        }
+    }
+}